@@ -0,0 +1,230 @@
+use std::fmt;
+use std::ops::AddAssign;
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Transform, Vector3, Vector4};
+
+/// Axis-aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    /// The smallest box around `points`, or `None` if there are none.
+    pub fn from_points(points: &[Point3<f32>]) -> Option<Self> {
+        let (&first, rest) = points.split_first()?;
+        Some(rest.iter().fold(
+            Aabb {
+                min: first,
+                max: first,
+            },
+            |aabb, point| Aabb {
+                min: Point3::new(
+                    aabb.min.x.min(point.x),
+                    aabb.min.y.min(point.y),
+                    aabb.min.z.min(point.z),
+                ),
+                max: Point3::new(
+                    aabb.max.x.max(point.x),
+                    aabb.max.y.max(point.y),
+                    aabb.max.z.max(point.z),
+                ),
+            },
+        ))
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        self.min.midpoint(self.max)
+    }
+
+    pub fn half_extents(&self) -> Vector3<f32> {
+        (self.max - self.min) * 0.5
+    }
+
+    /// The box around this one after `matrix`, which is loose for rotations.
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Aabb {
+        // the extents along each world axis are the absolute values of the rotated ones summed
+        let center = matrix.transform_point(self.center());
+        let half_extents = self.half_extents();
+        let extent = |row: usize| {
+            matrix.x[row].abs() * half_extents.x
+                + matrix.y[row].abs() * half_extents.y
+                + matrix.z[row].abs() * half_extents.z
+        };
+        let half_extents = Vector3::new(extent(0), extent(1), extent(2));
+        Aabb {
+            min: center - half_extents,
+            max: center + half_extents,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Point3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// A sphere around `points` found with Ritter's algorithm, at most a few percent larger
+    /// than the smallest one, or `None` if there are no points.
+    pub fn from_points(points: &[Point3<f32>]) -> Option<Self> {
+        let first = *points.first()?;
+        let farthest_from = |from: Point3<f32>| {
+            points
+                .iter()
+                .copied()
+                .max_by(|a, b| (a - from).magnitude2().total_cmp(&(b - from).magnitude2()))
+                .unwrap_or(from)
+        };
+        let a = farthest_from(first);
+        let b = farthest_from(a);
+        let mut sphere = BoundingSphere {
+            center: a.midpoint(b),
+            radius: (b - a).magnitude() * 0.5,
+        };
+        // grow the sphere just enough to take in the points outside of it
+        for &point in points {
+            let distance = (point - sphere.center).magnitude();
+            if distance > sphere.radius {
+                let radius = (sphere.radius + distance) * 0.5;
+                sphere.center += (point - sphere.center) * ((radius - sphere.radius) / distance);
+                sphere.radius = radius;
+            }
+        }
+        Some(sphere)
+    }
+
+    /// The sphere around this one after `matrix`, scaled by its largest axis.
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> BoundingSphere {
+        let scale = [matrix.x, matrix.y, matrix.z]
+            .iter()
+            .map(|axis| axis.truncate().magnitude())
+            .fold(0.0, f32::max);
+        BoundingSphere {
+            center: matrix.transform_point(self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+/// Both bounding volumes of a mesh; the sphere is cheaper to test, the box is tighter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere,
+}
+
+impl Bounds {
+    /// Bounds of the mesh with vertices at `points`, or `None` if there are none.
+    pub fn from_points(points: &[Point3<f32>]) -> Option<Self> {
+        Some(Bounds {
+            aabb: Aabb::from_points(points)?,
+            sphere: BoundingSphere::from_points(points)?,
+        })
+    }
+
+    /// The bounds in the space `matrix` maps to, e.g. world space for a model matrix.
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Bounds {
+        Bounds {
+            aabb: self.aabb.transform(matrix),
+            sphere: self.sphere.transform(matrix),
+        }
+    }
+}
+
+/// The six planes bounding what a camera sees, pointing inwards.
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    // `(normal, distance)` with `dot(normal, p) + distance >= 0` on the inner side
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a view projection matrix with wgpu's 0 to 1 depth range, as
+    /// described by Gribb and Hartmann.
+    pub fn from_matrix(vp_matrix: Matrix4<f32>) -> Self {
+        let row = |i: usize| {
+            Vector4::new(
+                vp_matrix.x[i],
+                vp_matrix.y[i],
+                vp_matrix.z[i],
+                vp_matrix.w[i],
+            )
+        };
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        let planes = [w + x, w - x, w + y, w - y, z, w - z].map(|plane| {
+            // normalized so that sphere radii can be compared with the distances
+            plane / plane.truncate().magnitude()
+        });
+        Frustum { planes }
+    }
+
+    fn distance(plane: &Vector4<f32>, point: Point3<f32>) -> f32 {
+        plane.truncate().dot(point.to_vec()) + plane.w
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| Self::distance(plane, sphere.center) >= -sphere.radius)
+    }
+
+    /// Conservative: boxes near the frustum edges may pass without touching it.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let center = aabb.center();
+        let half_extents = aabb.half_extents();
+        self.planes.iter().all(|plane| {
+            let reach = plane.x.abs() * half_extents.x
+                + plane.y.abs() * half_extents.y
+                + plane.z.abs() * half_extents.z;
+            Self::distance(plane, center) >= -reach
+        })
+    }
+
+    pub fn intersects(&self, bounds: &Bounds) -> bool {
+        self.intersects_sphere(&bounds.sphere) && self.intersects_aabb(&bounds.aabb)
+    }
+}
+
+/// How many objects a view drew and how many were culled as outside of its frustum.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CullStats {
+    pub drawn: usize,
+    pub culled: usize,
+}
+
+impl CullStats {
+    /// Counts one object, drawn if `visible`.
+    pub fn record(&mut self, visible: bool) {
+        if visible {
+            self.drawn += 1;
+        } else {
+            self.culled += 1;
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.drawn + self.culled
+    }
+}
+
+impl AddAssign for CullStats {
+    fn add_assign(&mut self, other: CullStats) {
+        self.drawn += other.drawn;
+        self.culled += other.culled;
+    }
+}
+
+impl fmt::Display for CullStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} objects drawn, {} culled",
+            self.drawn,
+            self.total(),
+            self.culled
+        )
+    }
+}
@@ -5,11 +5,21 @@ use winit::window::{Window, WindowBuilder};
 
 use wgpu::util;
 use wgpu::{
-    Adapter, Backends, Device, DeviceDescriptor, Features, Instance, Limits, PresentMode, Queue,
-    Sampler, Surface, SurfaceConfiguration, TextureUsages, TextureView, TextureViewDescriptor,
+    Adapter, Backends, BindGroupLayout, Device, DeviceDescriptor, Features, Instance, Limits,
+    PresentMode, Queue, Sampler, Surface, SurfaceConfiguration, TextureFormat, TextureUsages,
 };
 
+use crate::debug_draw::DebugDraw;
 use crate::global::Global;
+use crate::material::Material;
+use crate::texture::{MipmapGenerator, TextureCache, TEXTURE_FORMATS};
+
+/// Format of the lit scene, tone mapped to the surface by `ToneMapping`.
+pub const HDR_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+/// MSAA sample counts that can be selected. WebGPU guarantees 4x for every renderable format,
+/// while wgpu does not report support for any other count, so 2x and 8x are not offered.
+pub const SAMPLE_COUNTS: [u32; 2] = [1, 4];
+const DEFAULT_SAMPLE_COUNT: u32 = 4;
 
 pub struct Context {
     pub window: Window,
@@ -23,10 +33,20 @@ pub struct Context {
 
     // global uniforms
     pub global: Global,
-
-    // for depth test
+    // shared by the pipelines and bind groups of every material
+    pub material_bind_group_layout: BindGroupLayout,
+    pub mipmap_generator: MipmapGenerator,
+    // textures loaded from disk, shared between materials
+    pub textures: TextureCache,
+    // shapes queued for the overlays of this frame, from anywhere
+    pub debug_draw: DebugDraw,
+
+    // comparison sampler, also filtering the shadow maps
     pub sampler: Sampler,
-    pub depth_texture_view: TextureView,
+
+    // MSAA sample count of the scene and overlay passes, and of every pipeline drawn in them;
+    // their targets are allocated by the render graph, see `FrameTargets`
+    pub sample_count: u32,
 }
 
 impl Context {
@@ -36,7 +56,6 @@ impl Context {
             .with_inner_size(LogicalSize::new(640, 360))
             .build(&event_loop)
             .expect("Failed to create window");
-        let window_size = window.inner_size();
 
         #[cfg(target_os = "macos")]
         let backends = Backends::METAL;
@@ -63,7 +82,8 @@ impl Context {
         );
         let features = (optional_features & adapter_features) | required_features;
 
-        let needed_limits = Limits::downlevel_webgl2_defaults().using_resolution(adapter.limits());
+        // lights live in a storage buffer, which WebGL2-level devices lack
+        let needed_limits = Limits::downlevel_defaults().using_resolution(adapter.limits());
 
         let trace_dir = std::env::var("TRACE_DIR");
         let (device, queue) = adapter
@@ -88,7 +108,7 @@ impl Context {
 
         surface.configure(&device, &surface_config);
 
-        let depth_texture_view = Self::create_depth_texture_view(&device, &surface_config);
+        let sample_count = Self::fallback_sample_count(DEFAULT_SAMPLE_COUNT);
 
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             label: None,
@@ -102,7 +122,9 @@ impl Context {
             ..wgpu::SamplerDescriptor::default()
         });
 
-        let global = Global::new(&device, size);
+        let global = Global::new(&device, &queue, &sampler);
+        let material_bind_group_layout = Material::create_bind_group_layout(&device);
+        let mipmap_generator = MipmapGenerator::new(&device, &TEXTURE_FORMATS);
 
         (
             Context {
@@ -115,39 +137,51 @@ impl Context {
                 size,
                 surface,
                 sampler,
-                depth_texture_view,
+                sample_count,
                 global,
+                material_bind_group_layout,
+                mipmap_generator,
+                textures: TextureCache::default(),
+                debug_draw: DebugDraw::default(),
             },
             event_loop,
         )
     }
 
-    pub fn create_depth_texture_view(
-        device: &Device,
-        surface_config: &SurfaceConfiguration,
-    ) -> TextureView {
-        let depth_texture_size = wgpu::Extent3d {
-            width: surface_config.width,
-            height: surface_config.height,
-            depth_or_array_layers: 1,
-        };
-        let depth_texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: None,
-            size: depth_texture_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Depth32Float,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-        });
+    pub fn recreate_surface(&mut self) {
+        self.surface.configure(&self.device, &self.surface_config);
+    }
 
-        depth_texture.create_view(&TextureViewDescriptor::default())
+    /// The largest of `SAMPLE_COUNTS` not above `requested`.
+    fn fallback_sample_count(requested: u32) -> u32 {
+        SAMPLE_COUNTS
+            .into_iter()
+            .filter(|&count| count <= requested)
+            .max()
+            .unwrap_or(1)
     }
 
-    pub fn recreate_surface(&mut self) {
-        self.surface.configure(&self.device, &self.surface_config);
-        self.depth_texture_view =
-            Self::create_depth_texture_view(&self.device, &self.surface_config);
+    /// Switches MSAA to `requested` samples, or the closest supported count below it. The
+    /// render graph follows with its targets, but every pipeline and render bundle drawn into
+    /// them has to be recreated by the caller. Returns the sample count in use.
+    pub fn set_sample_count(&mut self, requested: u32) -> u32 {
+        let sample_count = Self::fallback_sample_count(requested);
+        if sample_count != requested {
+            log::warn!(
+                "{}x MSAA is not supported, falling back to {}x",
+                requested,
+                sample_count
+            );
+        }
+        self.sample_count = sample_count;
+        sample_count
+    }
+
+    pub fn multisample_state(&self) -> wgpu::MultisampleState {
+        wgpu::MultisampleState {
+            count: self.sample_count,
+            ..wgpu::MultisampleState::default()
+        }
     }
 
     pub fn get_aspect_ratio(&self) -> f32 {
@@ -0,0 +1,185 @@
+use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Transform, Vector2, Vector3};
+
+use crate::bounds::{Aabb, Bounds};
+use crate::shapes::{perpendicular_basis, LineBatch, TextBatch};
+use crate::{Ray, ScreenSpace};
+
+use std::cell::{Cell, RefCell};
+use std::time::{Duration, Instant};
+
+/// Length of arrow heads, as a fraction of the arrow.
+const ARROW_HEAD_RATIO: f32 = 0.15;
+const ARROW_HEAD_LINES: usize = 4;
+const TEXT_SCALE: u32 = 1;
+/// Pixels between a text anchor and the text.
+const TEXT_OFFSET: f32 = 4.0;
+
+#[derive(Debug, Clone)]
+enum Shape {
+    Line(Point3<f32>, Point3<f32>),
+    Box { aabb: Aabb, transform: Matrix4<f32> },
+    Sphere { center: Point3<f32>, radius: f32 },
+    Text(Point3<f32>, String),
+}
+
+#[derive(Debug, Clone)]
+struct Item {
+    shape: Shape,
+    color: [f32; 4],
+    // drawn for one frame if `None`
+    expires: Option<Instant>,
+}
+
+/// Shapes queued in world space from anywhere the `Context` reaches, to visualize normals,
+/// bounds, rays and the like while developing tools.
+///
+/// Shapes are drawn over the scene in every view of the frame they are queued in, or for as
+/// long as given to `persist`. Each view writes them into its overlay batches with `draw`,
+/// and `end_frame` drops what has been shown long enough.
+pub struct DebugDraw {
+    items: RefCell<Vec<Item>>,
+    // of the shapes queued now
+    lifetime: Cell<Option<Duration>>,
+    /// Shapes queued while disabled are ignored.
+    pub enabled: Cell<bool>,
+}
+
+impl Default for DebugDraw {
+    fn default() -> Self {
+        DebugDraw {
+            items: RefCell::new(Vec::new()),
+            lifetime: Cell::new(None),
+            enabled: Cell::new(true),
+        }
+    }
+}
+
+impl DebugDraw {
+    fn push(&self, shape: Shape, color: [f32; 4]) {
+        if !self.enabled.get() {
+            return;
+        }
+        let expires = self
+            .lifetime
+            .get()
+            .map(|lifetime| Instant::now() + lifetime);
+        self.items.borrow_mut().push(Item {
+            shape,
+            color,
+            expires,
+        });
+    }
+
+    /// Keeps the shapes `queue` adds for `lifetime` instead of a single frame.
+    pub fn persist(&self, lifetime: Duration, queue: impl FnOnce(&DebugDraw)) {
+        let outer = self.lifetime.replace(Some(lifetime));
+        queue(self);
+        self.lifetime.set(outer);
+    }
+
+    pub fn line(&self, a: Point3<f32>, b: Point3<f32>, color: [f32; 4]) {
+        self.push(Shape::Line(a, b), color);
+    }
+
+    /// A line with a head at `to`.
+    pub fn arrow(&self, from: Point3<f32>, to: Point3<f32>, color: [f32; 4]) {
+        self.line(from, to, color);
+        let direction = to - from;
+        let length = direction.magnitude();
+        if length <= 0.0 {
+            return;
+        }
+        let head = length * ARROW_HEAD_RATIO;
+        let base = to - direction / length * head;
+        let [u, v] = perpendicular_basis(direction);
+        for i in 0..ARROW_HEAD_LINES {
+            let angle = i as f32 / ARROW_HEAD_LINES as f32 * std::f32::consts::TAU;
+            let spoke = (u * angle.cos() + v * angle.sin()) * head * 0.5;
+            self.line(to, base + spoke, color);
+        }
+    }
+
+    /// An arrow `length` long along `ray`.
+    pub fn ray(&self, ray: &Ray, length: f32, color: [f32; 4]) {
+        self.arrow(ray.origin, ray.at(length), color);
+    }
+
+    pub fn aabb(&self, aabb: &Aabb, color: [f32; 4]) {
+        self.oriented_box(aabb, Matrix4::identity(), color);
+    }
+
+    /// The edges of `aabb` moved by `transform`, e.g. object-space bounds placed by a model
+    /// matrix.
+    pub fn oriented_box(&self, aabb: &Aabb, transform: Matrix4<f32>, color: [f32; 4]) {
+        let aabb = *aabb;
+        self.push(Shape::Box { aabb, transform }, color);
+    }
+
+    /// Three circles around the axes.
+    pub fn sphere(&self, center: Point3<f32>, radius: f32, color: [f32; 4]) {
+        self.push(Shape::Sphere { center, radius }, color);
+    }
+
+    /// Both bounding volumes.
+    pub fn bounds(&self, bounds: &Bounds, color: [f32; 4]) {
+        self.aabb(&bounds.aabb, color);
+        self.sphere(bounds.sphere.center, bounds.sphere.radius, color);
+    }
+
+    /// Screen-space text below and to the right of where `position` appears.
+    pub fn text(&self, position: Point3<f32>, text: &str, color: [f32; 4]) {
+        self.push(Shape::Text(position, text.to_owned()), color);
+    }
+
+    /// Writes the queued shapes into the batches of a view seen through `screen`. The batches
+    /// are not cleared or uploaded.
+    pub fn draw(&self, lines: &mut LineBatch, text: &mut TextBatch, screen: &ScreenSpace) {
+        for item in self.items.borrow().iter() {
+            let color = item.color;
+            match &item.shape {
+                &Shape::Line(a, b) => lines.line(a, b, color),
+                Shape::Box { aabb, transform } => {
+                    let corner = |i: usize| {
+                        let point = Point3::new(
+                            if i & 1 == 0 { aabb.min.x } else { aabb.max.x },
+                            if i & 2 == 0 { aabb.min.y } else { aabb.max.y },
+                            if i & 4 == 0 { aabb.min.z } else { aabb.max.z },
+                        );
+                        transform.transform_point(point)
+                    };
+                    for i in 0..8 {
+                        for bit in [1, 2, 4] {
+                            if i & bit == 0 {
+                                lines.line(corner(i), corner(i | bit), color);
+                            }
+                        }
+                    }
+                }
+                &Shape::Sphere { center, radius } => {
+                    for axis in [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()] {
+                        lines.circle(center, axis, radius, color);
+                    }
+                }
+                Shape::Text(position, string) => {
+                    if let Some(pixel) = screen.project(*position) {
+                        let offset = Vector2::new(TEXT_OFFSET, TEXT_OFFSET);
+                        text.text(pixel + offset, string, TEXT_SCALE, color);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Drops the shapes queued for this frame and those whose lifetime is over. Call it once
+    /// all views are drawn.
+    pub fn end_frame(&self) {
+        let now = Instant::now();
+        self.items
+            .borrow_mut()
+            .retain(|item| item.expires.is_some_and(|expires| expires > now));
+    }
+
+    pub fn clear(&self) {
+        self.items.borrow_mut().clear();
+    }
+}
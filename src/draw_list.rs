@@ -0,0 +1,36 @@
+use wgpu::RenderBundle;
+
+/// The render bundles of a scene pass, in the order they must be drawn in: all opaque ones
+/// first, writing the depth buffer, then the blended ones from the farthest to the nearest,
+/// since they only test against it and each covers what was drawn before.
+///
+/// Meshes are ordered as a whole, by the distance of their center, so intersecting blended
+/// meshes can still be drawn in the wrong order where they overlap.
+#[derive(Default)]
+pub struct DrawList<'a> {
+    opaque: Vec<&'a RenderBundle>,
+    transparent: Vec<(f32, &'a RenderBundle)>,
+}
+
+impl<'a> DrawList<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_opaque(&mut self, bundle: &'a RenderBundle) {
+        self.opaque.push(bundle);
+    }
+
+    /// A blended mesh `distance` away from the camera.
+    pub fn push_transparent(&mut self, distance: f32, bundle: &'a RenderBundle) {
+        self.transparent.push((distance, bundle));
+    }
+
+    /// Sorts the blended bundles and returns all of them, to execute in a render pass.
+    pub fn bundles(mut self) -> impl Iterator<Item = &'a RenderBundle> {
+        self.transparent.sort_by(|a, b| b.0.total_cmp(&a.0));
+        self.opaque
+            .into_iter()
+            .chain(self.transparent.into_iter().map(|(_, bundle)| bundle))
+    }
+}
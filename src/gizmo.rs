@@ -0,0 +1,441 @@
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix, Matrix3, Matrix4, MetricSpace, Point2, Point3, Rad, Vector3,
+};
+
+use crate::selection::{distance_to_segment, Region, Selection, SelectionTarget};
+use crate::shapes::{circle_points, LineBatch};
+use crate::{Ray, ScreenSpace};
+
+const GIZMO_SIZE_IN_PIXELS: f32 = 90.0;
+const HANDLE_PICK_RADIUS: f32 = 8.0;
+const PLANE_HANDLE_RANGE: (f32, f32) = (0.25, 0.45);
+const ARROW_HEAD_RATIO: f32 = 0.12;
+const SCALE_BOX_RATIO: f32 = 0.05;
+const RING_SEGMENTS: usize = 48;
+const MIN_SCALE: f32 = 0.01;
+
+// matches the colors of the world axes drawn by `BaseLine`
+const AXIS_COLORS: [[f32; 4]; 3] = [
+    [1.0, 0.0, 0.0, 1.0],
+    [0.0, 0.0, 1.0, 1.0],
+    [0.0, 1.0, 0.0, 1.0],
+];
+const CENTER_COLOR: [f32; 4] = [0.9, 0.9, 0.9, 1.0];
+const HOVER_COLOR: [f32; 4] = [1.0, 1.0, 0.0, 1.0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GizmoSpace {
+    World,
+    Local,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Handle {
+    Axis(usize),
+    /// Plane spanned by the two axes other than the given one.
+    Plane(usize),
+    Center,
+}
+
+/// Position and orientation the gizmo is drawn and dragged in.
+#[derive(Debug, Clone, Copy)]
+pub struct GizmoFrame {
+    pub pivot: Point3<f32>,
+    pub axes: [Vector3<f32>; 3],
+}
+
+/// Transform produced by a drag, relative to where the drag started.
+#[derive(Debug, Clone, Copy)]
+pub enum GizmoDelta {
+    /// World-space offset.
+    Translate(Vector3<f32>),
+    /// Rotation around a world-space axis through the pivot.
+    Rotate { axis: Vector3<f32>, angle: Rad<f32> },
+    /// Scale factors along the gizmo axes.
+    Scale(Vector3<f32>),
+}
+
+/// Subspace a translation is restricted to by the dragged handle.
+#[derive(Debug, Clone, Copy)]
+pub enum Constraint {
+    Free,
+    Axis(Vector3<f32>),
+    /// Plane with the given normal.
+    Plane(Vector3<f32>),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct GizmoDrag {
+    handle: Handle,
+    frame: GizmoFrame,
+    start_cursor: Point2<f32>,
+    start_point: Point3<f32>,
+}
+
+/// Interactive translate/rotate/scale handles attached to the selection pivot.
+#[derive(Debug)]
+pub struct Gizmo {
+    pub mode: GizmoMode,
+    pub space: GizmoSpace,
+    hovered: Option<Handle>,
+    drag: Option<GizmoDrag>,
+}
+
+impl Default for Gizmo {
+    fn default() -> Self {
+        Gizmo {
+            mode: GizmoMode::Translate,
+            space: GizmoSpace::World,
+            hovered: None,
+            drag: None,
+        }
+    }
+}
+
+impl GizmoFrame {
+    pub fn new(pivot: Point3<f32>, model_matrix: Matrix4<f32>, space: GizmoSpace) -> Self {
+        let axes = match space {
+            GizmoSpace::World => [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()],
+            GizmoSpace::Local => [
+                model_matrix.x.truncate().normalize(),
+                model_matrix.y.truncate().normalize(),
+                model_matrix.z.truncate().normalize(),
+            ],
+        };
+        GizmoFrame { pivot, axes }
+    }
+
+    fn size(&self, screen: &ScreenSpace) -> f32 {
+        GIZMO_SIZE_IN_PIXELS * screen.pixel_size_at(self.pivot)
+    }
+
+    fn plane_axes(handle_axis: usize) -> (usize, usize) {
+        ((handle_axis + 1) % 3, (handle_axis + 2) % 3)
+    }
+}
+
+impl GizmoDelta {
+    /// World-space matrix applying the delta around the pivot of `frame`.
+    pub fn to_matrix(&self, frame: &GizmoFrame) -> Matrix4<f32> {
+        let to_pivot = Matrix4::from_translation(frame.pivot.to_vec());
+        let from_pivot = Matrix4::from_translation(-frame.pivot.to_vec());
+        match *self {
+            GizmoDelta::Translate(offset) => Matrix4::from_translation(offset),
+            GizmoDelta::Rotate { axis, angle } => {
+                to_pivot * Matrix4::from_axis_angle(axis, angle) * from_pivot
+            }
+            GizmoDelta::Scale(factors) => {
+                // the axes are orthonormal, so the transpose is the inverse
+                let basis = Matrix3::from_cols(frame.axes[0], frame.axes[1], frame.axes[2]);
+                to_pivot
+                    * Matrix4::from(basis)
+                    * Matrix4::from_nonuniform_scale(factors.x, factors.y, factors.z)
+                    * Matrix4::from(basis.transpose())
+                    * from_pivot
+            }
+        }
+    }
+}
+
+impl Constraint {
+    pub fn apply(&self, offset: Vector3<f32>) -> Vector3<f32> {
+        match *self {
+            Constraint::Free => offset,
+            Constraint::Axis(axis) => axis * offset.dot(axis),
+            Constraint::Plane(normal) => offset - normal * offset.dot(normal),
+        }
+    }
+}
+
+impl Gizmo {
+    pub fn toggle_space(&mut self) {
+        self.space = match self.space {
+            GizmoSpace::World => GizmoSpace::Local,
+            GizmoSpace::Local => GizmoSpace::World,
+        };
+    }
+
+    /// Frame around the current selection, or `None` when nothing is selected.
+    pub fn frame(&self, selection: &Selection, targets: &[SelectionTarget]) -> Option<GizmoFrame> {
+        selection
+            .pivot(targets)
+            .map(|(pivot, model_matrix)| GizmoFrame::new(pivot, model_matrix, self.space))
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    pub fn is_hovered(&self) -> bool {
+        self.hovered.is_some()
+    }
+
+    /// Frame the current drag started from, which stays fixed while the selection moves.
+    pub fn drag_frame(&self) -> Option<GizmoFrame> {
+        self.drag.map(|drag| drag.frame)
+    }
+
+    pub fn drag_constraint(&self) -> Option<Constraint> {
+        let drag = self.drag?;
+        Some(match drag.handle {
+            Handle::Axis(i) => Constraint::Axis(drag.frame.axes[i]),
+            Handle::Plane(i) => Constraint::Plane(drag.frame.axes[i]),
+            Handle::Center => Constraint::Free,
+        })
+    }
+
+    pub fn hover(&mut self, cursor: Point2<f32>, screen: &ScreenSpace, frame: Option<&GizmoFrame>) {
+        if self.drag.is_none() {
+            self.hovered = frame.and_then(|frame| self.pick(cursor, screen, frame));
+        }
+    }
+
+    /// Starts dragging the hovered handle. Returns `false` if no handle is under the cursor.
+    pub fn begin_drag(
+        &mut self,
+        cursor: Point2<f32>,
+        screen: &ScreenSpace,
+        frame: GizmoFrame,
+    ) -> bool {
+        let handle = match self.pick(cursor, screen, &frame) {
+            Some(handle) => handle,
+            None => return false,
+        };
+        let ray = screen.ray(cursor);
+        let start_point = match self.drag_point(handle, &frame, &ray) {
+            Some(point) => point,
+            None => return false,
+        };
+        self.hovered = Some(handle);
+        self.drag = Some(GizmoDrag {
+            handle,
+            frame,
+            start_cursor: cursor,
+            start_point,
+        });
+        true
+    }
+
+    pub fn end_drag(&mut self) {
+        self.drag = None;
+    }
+
+    /// Transform from the start of the drag to the current cursor position.
+    pub fn drag_delta(&self, cursor: Point2<f32>, screen: &ScreenSpace) -> Option<GizmoDelta> {
+        let drag = self.drag?;
+        let frame = &drag.frame;
+        let pivot = frame.pivot;
+        let ray = screen.ray(cursor);
+
+        if let (GizmoMode::Scale, Handle::Center) = (self.mode, drag.handle) {
+            // dragging right grows, dragging left shrinks
+            let factor =
+                (1.0 + (cursor.x - drag.start_cursor.x) / GIZMO_SIZE_IN_PIXELS).max(MIN_SCALE);
+            return Some(GizmoDelta::Scale(Vector3::new(factor, factor, factor)));
+        }
+
+        let point = self.drag_point(drag.handle, frame, &ray)?;
+        let delta = match (self.mode, drag.handle) {
+            (GizmoMode::Translate, _) => GizmoDelta::Translate(point - drag.start_point),
+            (GizmoMode::Rotate, handle) => {
+                let axis = match handle {
+                    Handle::Axis(i) | Handle::Plane(i) => frame.axes[i],
+                    Handle::Center => return None,
+                };
+                let from = drag.start_point - pivot;
+                let to = point - pivot;
+                let angle = axis.dot(from.cross(to)).atan2(from.dot(to));
+                GizmoDelta::Rotate {
+                    axis,
+                    angle: Rad(angle),
+                }
+            }
+            (GizmoMode::Scale, handle) => {
+                let ratio = |axis: Vector3<f32>| {
+                    let start = (drag.start_point - pivot).dot(axis);
+                    if start.abs() < f32::EPSILON {
+                        1.0
+                    } else {
+                        ((point - pivot).dot(axis) / start).max(MIN_SCALE)
+                    }
+                };
+                let mut factors = Vector3::new(1.0, 1.0, 1.0);
+                match handle {
+                    Handle::Axis(i) => factors[i] = ratio(frame.axes[i]),
+                    Handle::Plane(i) => {
+                        let (a, b) = GizmoFrame::plane_axes(i);
+                        let start = (drag.start_point - pivot).magnitude().max(f32::EPSILON);
+                        let factor = ((point - pivot).magnitude() / start).max(MIN_SCALE);
+                        factors[a] = factor;
+                        factors[b] = factor;
+                    }
+                    Handle::Center => unreachable!(),
+                }
+                GizmoDelta::Scale(factors)
+            }
+        };
+        Some(delta)
+    }
+
+    /// Point under the cursor on the axis or plane constraining `handle`.
+    fn drag_point(&self, handle: Handle, frame: &GizmoFrame, ray: &Ray) -> Option<Point3<f32>> {
+        let pivot = frame.pivot;
+        match (self.mode, handle) {
+            (GizmoMode::Rotate, Handle::Axis(i) | Handle::Plane(i)) => {
+                ray.intersect_plane(pivot, frame.axes[i]).map(|t| ray.at(t))
+            }
+            (_, Handle::Axis(i)) => closest_point_on_axis(ray, pivot, frame.axes[i]),
+            (_, Handle::Plane(i)) => ray.intersect_plane(pivot, frame.axes[i]).map(|t| ray.at(t)),
+            (_, Handle::Center) => Some(pivot),
+        }
+    }
+
+    fn pick(
+        &self,
+        cursor: Point2<f32>,
+        screen: &ScreenSpace,
+        frame: &GizmoFrame,
+    ) -> Option<Handle> {
+        let size = frame.size(screen);
+        let pivot = frame.pivot;
+        let distance_to_polyline = |points: &[Point3<f32>], closed: bool| {
+            let projected: Vec<Point2<f32>> =
+                points.iter().filter_map(|&p| screen.project(p)).collect();
+            let segments = if closed {
+                projected.len()
+            } else {
+                projected.len().saturating_sub(1)
+            };
+            (0..segments)
+                .map(|i| {
+                    distance_to_segment(cursor, projected[i], projected[(i + 1) % projected.len()])
+                })
+                .fold(f32::INFINITY, f32::min)
+        };
+
+        let mut candidates: Vec<(f32, Handle)> = Vec::new();
+        match self.mode {
+            GizmoMode::Translate | GizmoMode::Scale => {
+                for (i, axis) in frame.axes.iter().enumerate() {
+                    let distance = distance_to_polyline(&[pivot, pivot + axis * size], false);
+                    candidates.push((distance, Handle::Axis(i)));
+
+                    let quad: Vec<Point2<f32>> = plane_handle_corners(frame, i, size)
+                        .iter()
+                        .filter_map(|&p| screen.project(p))
+                        .collect();
+                    if quad.len() == 4 && Region::Lasso(quad).contains(cursor) {
+                        candidates.push((0.0, Handle::Plane(i)));
+                    }
+                }
+                if self.mode == GizmoMode::Scale {
+                    if let Some(center) = screen.project(pivot) {
+                        candidates.push((center.distance(cursor), Handle::Center));
+                    }
+                }
+            }
+            GizmoMode::Rotate => {
+                for (i, &axis) in frame.axes.iter().enumerate() {
+                    let ring = circle_points(pivot, axis, size, RING_SEGMENTS);
+                    candidates.push((distance_to_polyline(&ring, true), Handle::Axis(i)));
+                }
+            }
+        }
+        candidates
+            .into_iter()
+            .filter(|(distance, _)| *distance <= HANDLE_PICK_RADIUS)
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, handle)| handle)
+    }
+
+    pub fn draw(&self, lines: &mut LineBatch, screen: &ScreenSpace, frame: &GizmoFrame) {
+        let size = frame.size(screen);
+        let pivot = frame.pivot;
+        let active = self.drag.map(|drag| drag.handle).or(self.hovered);
+        let color = |handle: Handle, base: [f32; 4]| {
+            if active == Some(handle) {
+                HOVER_COLOR
+            } else {
+                base
+            }
+        };
+
+        match self.mode {
+            GizmoMode::Translate | GizmoMode::Scale => {
+                for (i, &axis) in frame.axes.iter().enumerate() {
+                    let axis_color = color(Handle::Axis(i), AXIS_COLORS[i]);
+                    let tip = pivot + axis * size;
+                    lines.line(pivot, tip, axis_color);
+                    if self.mode == GizmoMode::Translate {
+                        let (a, b) = GizmoFrame::plane_axes(i);
+                        let head = size * ARROW_HEAD_RATIO;
+                        for side in [frame.axes[a], -frame.axes[a], frame.axes[b], -frame.axes[b]] {
+                            lines.line(tip, tip - axis * head + side * head * 0.4, axis_color);
+                        }
+                    } else {
+                        let half = size * SCALE_BOX_RATIO;
+                        let offset = Vector3::new(half, half, half);
+                        lines.aabb(tip - offset, tip + offset, axis_color);
+                    }
+                    lines.polyline_closed(
+                        &plane_handle_corners(frame, i, size),
+                        color(Handle::Plane(i), AXIS_COLORS[i]),
+                    );
+                }
+                if self.mode == GizmoMode::Scale {
+                    let half = size * SCALE_BOX_RATIO;
+                    let offset = Vector3::new(half, half, half);
+                    lines.aabb(
+                        pivot - offset,
+                        pivot + offset,
+                        color(Handle::Center, CENTER_COLOR),
+                    );
+                }
+            }
+            GizmoMode::Rotate => {
+                for (i, &axis) in frame.axes.iter().enumerate() {
+                    lines.polyline_closed(
+                        &circle_points(pivot, axis, size, RING_SEGMENTS),
+                        color(Handle::Axis(i), AXIS_COLORS[i]),
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn plane_handle_corners(frame: &GizmoFrame, handle_axis: usize, size: f32) -> [Point3<f32>; 4] {
+    let (a, b) = GizmoFrame::plane_axes(handle_axis);
+    let (near, far) = (PLANE_HANDLE_RANGE.0 * size, PLANE_HANDLE_RANGE.1 * size);
+    let (u, v) = (frame.axes[a], frame.axes[b]);
+    let pivot = frame.pivot;
+    [
+        pivot + u * near + v * near,
+        pivot + u * far + v * near,
+        pivot + u * far + v * far,
+        pivot + u * near + v * far,
+    ]
+}
+
+/// Point on the line through `origin` along `axis` closest to `ray`.
+fn closest_point_on_axis(
+    ray: &Ray,
+    origin: Point3<f32>,
+    axis: Vector3<f32>,
+) -> Option<Point3<f32>> {
+    let b = axis.dot(ray.direction);
+    let denom = 1.0 - b * b;
+    if denom.abs() < 1e-6 {
+        return None;
+    }
+    let w = origin - ray.origin;
+    let s = (b * ray.direction.dot(w) - axis.dot(w)) / denom;
+    Some(origin + axis * s)
+}
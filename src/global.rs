@@ -1,21 +1,34 @@
 mod camera;
+mod environment;
 mod light;
+mod screen_space;
+mod shadow;
 
 use std::mem;
 
 use bytemuck::{Pod, Zeroable};
-pub use camera::{Camera, RawCamera};
-pub use light::{Light, RawLight};
+pub use camera::{Camera, Projection};
+use cgmath::Point3;
+pub use environment::Environment;
+pub use light::{lighting_wgsl, Light, LightKind, MAX_LIGHTS};
+use light::{RawLight, RawLightsHeader};
+pub use screen_space::{Ray, ScreenSpace};
+use shadow::ShadowUniforms;
+pub use shadow::{ShadowCaster, Shadows, CASCADE_COUNT, MAX_POINT_SHADOWS};
 
 pub use wgpu::util::DeviceExt;
 
 use winit::dpi::PhysicalSize;
 
 pub struct Global {
-    pub camera: Camera,
-    pub light: Light,
+    pub shadows: Shadows,
+    pub environment: Environment,
+    lights: Vec<Light>,
+    ambient: [f32; 3],
+    lights_changed: bool,
 
     pub ubo: wgpu::Buffer,
+    pub light_buffer: wgpu::Buffer,
     pub bind_group: wgpu::BindGroup,
     pub bind_group_layout: wgpu::BindGroupLayout,
 }
@@ -26,29 +39,18 @@ struct GlobalUniforms {
     vp_matrix: [f32; 16],
     camera_pos: [f32; 3],
     _0: f32,
-    point_light_pos: [f32; 3],
-    _1: f32,
-    inf_light_dir: [f32; 3],
-    _2: f32,
 }
 
 impl Global {
     const VP_MATRIX_SIZE: usize = mem::size_of::<[f32; 16]>();
-    pub fn new(device: &wgpu::Device, size: PhysicalSize<u32>) -> Self {
-        let camera = Camera::default();
-        let light = Light::default();
-        let raw_camera = camera.create_raw_camera(size.width as f32 / size.height as f32);
-        let raw_light = light.to_raw_light();
-
-        let global_uniforms = GlobalUniforms {
-            vp_matrix: raw_camera.vp_matrix,
-            camera_pos: raw_camera.camera_pos,
-            _0: 0.0,
-            point_light_pos: raw_light.point,
-            _1: 0.0,
-            inf_light_dir: raw_light.infinite,
-            _2: 0.0,
-        };
+    /// `shadow_sampler` must be a comparison sampler; it filters the shadow maps.
+    /// The camera uniforms are zeroed until the first `update_camera`.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, shadow_sampler: &wgpu::Sampler) -> Self {
+        let shadows = Shadows::new(device);
+        let environment = Environment::new(device, queue);
+        let lights = Light::default_lights();
+        let ambient = [1.0, 1.0, 1.0];
+        let global_uniforms = GlobalUniforms::zeroed();
 
         log::info!("size: {}", bytemuck::bytes_of(&global_uniforms).len());
 
@@ -58,10 +60,26 @@ impl Global {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
-        let bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[wgpu::BindGroupLayoutEntry {
+        let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: &Self::raw_lights(&lights, ambient),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let environment_entry = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
                     binding: 0,
                     visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
@@ -72,31 +90,226 @@ impl Global {
                         ),
                     },
                     count: None,
-                }],
-            });
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            mem::size_of::<RawLightsHeader>() as u64
+                        ),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            mem::size_of::<ShadowUniforms>() as u64
+                        ),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+                environment_entry(6, wgpu::TextureViewDimension::Cube),
+                environment_entry(7, wgpu::TextureViewDimension::Cube),
+                environment_entry(8, wgpu::TextureViewDimension::D2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 9,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
 
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: ubo.as_entire_binding(),
-            }],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: ubo.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: light_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: shadows.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&shadows.cascade_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&shadows.point_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::Sampler(shadow_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::TextureView(&environment.irradiance_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: wgpu::BindingResource::TextureView(&environment.prefiltered_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: wgpu::BindingResource::TextureView(&environment.brdf_lut_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: wgpu::BindingResource::Sampler(&environment.sampler),
+                },
+            ],
             layout: &bind_group_layout,
         });
 
         Global {
-            camera,
-            light,
+            shadows,
+            environment,
+            lights,
+            ambient,
+            lights_changed: false,
             bind_group,
             bind_group_layout,
             ubo,
+            light_buffer,
+        }
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    pub fn ambient(&self) -> [f32; 3] {
+        self.ambient
+    }
+
+    /// Returns the index of the new light.
+    pub fn add_light(&mut self, light: Light) -> usize {
+        self.lights.push(light);
+        self.lights_changed = true;
+        self.lights.len() - 1
+    }
+
+    /// Removes a light, shifting the indices of the following ones down by one.
+    pub fn remove_light(&mut self, index: usize) -> Light {
+        self.lights_changed = true;
+        self.lights.remove(index)
+    }
+
+    pub fn set_light(&mut self, index: usize, light: Light) {
+        self.lights[index] = light;
+        self.lights_changed = true;
+    }
+
+    /// Mutable access to a light; it is uploaded by the next `upload_lights`.
+    pub fn light_mut(&mut self, index: usize) -> Option<&mut Light> {
+        self.lights_changed = true;
+        self.lights.get_mut(index)
+    }
+
+    pub fn move_light(&mut self, index: usize, position: Point3<f32>) {
+        if let Some(light) = self.light_mut(index) {
+            light.position = position;
         }
     }
 
-    pub fn on_resize(&self, queue: &wgpu::Queue, size: PhysicalSize<u32>) {
-        let raw_camera = self
-            .camera
-            .create_raw_camera(size.width as f32 / size.height as f32);
+    /// Tints and scales the light received from the environment.
+    pub fn set_ambient(&mut self, ambient: [f32; 3]) {
+        self.ambient = ambient;
+        self.lights_changed = true;
+    }
+
+    /// Writes the lights to the GPU if they were edited since the last upload.
+    pub fn upload_lights(&mut self, queue: &wgpu::Queue) {
+        if !self.lights_changed {
+            return;
+        }
+        queue.write_buffer(
+            &self.light_buffer,
+            0,
+            &Self::raw_lights(&self.lights, self.ambient),
+        );
+        self.lights_changed = false;
+    }
+
+    /// Contents of the light storage buffer. It always has room for `MAX_LIGHTS` lights so
+    /// the bind group, and every render bundle using it, stays valid as lights come and go.
+    fn raw_lights(lights: &[Light], ambient: [f32; 3]) -> Vec<u8> {
+        if lights.len() > MAX_LIGHTS {
+            log::warn!(
+                "{} lights exceed the maximum of {}, ignoring the rest",
+                lights.len(),
+                MAX_LIGHTS
+            );
+        }
+        let count = lights.len().min(MAX_LIGHTS);
+        let mut raw_lights = vec![RawLight::zeroed(); MAX_LIGHTS];
+        let slots = shadow::shadow_slots(&lights[..count]);
+        for ((raw_light, light), slot) in raw_lights.iter_mut().zip(&lights[..count]).zip(slots) {
+            *raw_light = light.to_raw_light(slot);
+        }
+        let header = RawLightsHeader {
+            ambient,
+            count: count as u32,
+        };
+        let mut bytes = bytemuck::bytes_of(&header).to_vec();
+        bytes.extend_from_slice(bytemuck::cast_slice(&raw_lights));
+        bytes
+    }
+
+    /// Refits the shadow maps to `camera` and the lights; render them with `Shadows::render`.
+    pub fn update_shadows(
+        &mut self,
+        queue: &wgpu::Queue,
+        camera: &Camera,
+        size: PhysicalSize<u32>,
+    ) {
+        let aspect_ratio = size.width as f32 / size.height as f32;
+        self.shadows
+            .update(queue, camera, aspect_ratio, &self.lights);
+    }
+
+    /// Makes the following draws look through `camera` onto a viewport of `size`.
+    pub fn update_camera(&self, queue: &wgpu::Queue, camera: &Camera, size: PhysicalSize<u32>) {
+        let raw_camera = camera.create_raw_camera(size.width as f32 / size.height as f32);
         queue.write_buffer(&self.ubo, 0, bytemuck::bytes_of(&raw_camera.vp_matrix));
         queue.write_buffer(
             &self.ubo,
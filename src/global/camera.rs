@@ -1,10 +1,16 @@
 use bytemuck::{Pod, Zeroable};
 use cgmath::{
-    EuclideanSpace, InnerSpace, Matrix4, Point3, Quaternion, Rad, Rotation, Rotation3, Vector2,
-    Vector3,
+    EuclideanSpace, InnerSpace, Matrix4, Point3, Quaternion, Rad, Rotation, Rotation3,
+    SquareMatrix, Vector2, Vector3, Vector4,
 };
+use winit::dpi::PhysicalSize;
+
+use super::ScreenSpace;
+use crate::bounds::{BoundingSphere, Frustum};
 
 const DELTA_POSITION: f32 = 0.05;
+/// Relative change of the orthographic height per update while zooming.
+const DELTA_ZOOM: f32 = 0.02;
 const DELTA_ANGLE: f32 = std::f32::consts::PI / 100.0;
 const ELEVATION_MARGIN_RATIO: f32 = 0.001;
 const MAX_ELEVATION_ANGLE: Rad<f32> =
@@ -13,19 +19,50 @@ const MIN_ELEVATION_ANGLE: Rad<f32> =
     Rad(-std::f32::consts::FRAC_PI_2 * (1.0 - ELEVATION_MARGIN_RATIO));
 
 #[rustfmt::skip]
-const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
+pub(crate) const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
     0.0, 1.0, 0.0, 0.0,
     0.0, 0.0, 0.5, 0.0,
     0.0, 0.0, 0.5, 1.0,
 );
 
+/// How a camera maps view space to the screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// `fov` is the vertical field of view in degrees.
+    Perspective { fov: f32 },
+    /// `height` is the extent of the view in world units, vertically.
+    Orthographic { height: f32 },
+}
+
+impl Projection {
+    fn matrix(self, aspect_ratio: f32, near: f32, far: f32) -> Matrix4<f32> {
+        match self {
+            Projection::Perspective { fov } => {
+                cgmath::perspective(cgmath::Deg(fov), aspect_ratio, near, far)
+            }
+            Projection::Orthographic { height } => {
+                let half_height = height * 0.5;
+                let half_width = half_height * aspect_ratio;
+                cgmath::ortho(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    near,
+                    far,
+                )
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Camera {
     position: Point3<f32>,
     dir: Vector3<f32>,
     up: Vector3<f32>,
-    fov: f32,
+    projection: Projection,
     near: f32,
     far: f32,
 
@@ -46,7 +83,7 @@ impl Default for Camera {
         let position = Point3::new(0.0, 0.0, 5.0);
         let dir = -position.to_vec();
         let up = Vector3::unit_y();
-        let fov = 45f32;
+        let projection = Projection::Perspective { fov: 45.0 };
         let near = 0.1;
         let far = 1000.0;
 
@@ -54,7 +91,7 @@ impl Default for Camera {
             position,
             dir,
             up,
-            fov,
+            projection,
             near,
             far,
             should_move_right: false,
@@ -80,10 +117,7 @@ pub struct RawCamera {
 
 impl Camera {
     pub fn create_raw_camera(&self, aspect_ratio: f32) -> RawCamera {
-        let projection_matrix =
-            cgmath::perspective(cgmath::Deg(self.fov), aspect_ratio, self.near, self.far);
-        let view_matrix = Matrix4::look_to_rh(self.position, self.dir, self.up);
-        let vp_matrix = OPENGL_TO_WGPU_MATRIX * projection_matrix * view_matrix;
+        let vp_matrix = self.view_projection_matrix(aspect_ratio);
         let vp_matrix = *vp_matrix.as_ref();
         let camera_pos = self.position.into();
         RawCamera {
@@ -92,6 +126,127 @@ impl Camera {
         }
     }
 
+    /// Looks along `-axis` from the side it points to, like after `snap_to_view`.
+    pub fn looking_along(axis: Vector3<f32>, projection: Projection) -> Self {
+        let mut camera = Camera {
+            projection,
+            ..Camera::default()
+        };
+        camera.snap_to_view(axis);
+        camera
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    /// Switches between a perspective and an orthographic projection, the latter showing as
+    /// much as the former does at the distance of the origin.
+    pub fn toggle_projection(&mut self) {
+        let distance = self.position.to_vec().magnitude().max(self.near);
+        self.projection = match self.projection {
+            Projection::Perspective { fov } => Projection::Orthographic {
+                height: 2.0 * distance * (fov.to_radians() * 0.5).tan(),
+            },
+            Projection::Orthographic { height } => Projection::Perspective {
+                fov: (2.0 * (height * 0.5 / distance).atan())
+                    .to_degrees()
+                    .clamp(10.0, 120.0),
+            },
+        };
+    }
+
+    pub fn view_projection_matrix(&self, aspect_ratio: f32) -> Matrix4<f32> {
+        let projection_matrix = self.projection.matrix(aspect_ratio, self.near, self.far);
+        OPENGL_TO_WGPU_MATRIX * projection_matrix * self.view_matrix()
+    }
+
+    /// What the camera sees on a viewport of `aspect_ratio`, for culling.
+    pub fn frustum(&self, aspect_ratio: f32) -> Frustum {
+        Frustum::from_matrix(self.view_projection_matrix(aspect_ratio))
+    }
+
+    /// How many pixels `sphere` spans on a viewport `viewport_height` pixels high.
+    pub fn projected_size(&self, sphere: &BoundingSphere, viewport_height: f32) -> f32 {
+        let view_height = match self.projection {
+            Projection::Perspective { fov } => {
+                let distance = (sphere.center - self.position).magnitude();
+                if distance <= sphere.radius {
+                    return f32::INFINITY;
+                }
+                2.0 * distance * (fov.to_radians() * 0.5).tan()
+            }
+            Projection::Orthographic { height } => height,
+        };
+        sphere.radius * 2.0 / view_height * viewport_height
+    }
+
+    /// Corners of the slice of the view frustum between the `near` and `far` view distances,
+    /// near plane first.
+    pub fn frustum_corners(&self, aspect_ratio: f32, near: f32, far: f32) -> [Point3<f32>; 8] {
+        let projection_matrix = self.projection.matrix(aspect_ratio, near, far);
+        let inverse_vp_matrix = (OPENGL_TO_WGPU_MATRIX * projection_matrix * self.view_matrix())
+            .invert()
+            .expect("failed to calculate inverse matrix of view projection");
+        let mut corners = [Point3::new(0.0, 0.0, 0.0); 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let x = if i & 1 == 0 { -1.0 } else { 1.0 };
+            let y = if i & 2 == 0 { -1.0 } else { 1.0 };
+            let z = if i & 4 == 0 { 0.0 } else { 1.0 };
+            let world = inverse_vp_matrix * Vector4::new(x, y, z, 1.0);
+            *corner = Point3::from_vec(world.truncate() / world.w);
+        }
+        corners
+    }
+
+    pub fn near(&self) -> f32 {
+        self.near
+    }
+
+    pub fn far(&self) -> f32 {
+        self.far
+    }
+
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::look_to_rh(self.position, self.dir, self.up)
+    }
+
+    pub fn screen_space(&self, size: PhysicalSize<u32>) -> ScreenSpace {
+        let aspect_ratio = size.width as f32 / size.height as f32;
+        ScreenSpace::new(self.view_projection_matrix(aspect_ratio), size)
+    }
+
+    pub fn position(&self) -> Point3<f32> {
+        self.position
+    }
+
+    pub fn direction(&self) -> Vector3<f32> {
+        self.dir.normalize()
+    }
+
+    /// Looks from the side `axis` points to at the point currently in front of the camera,
+    /// keeping the distance to it.
+    pub fn snap_to_view(&mut self, axis: Vector3<f32>) {
+        let distance = self.position.to_vec().magnitude().max(1.0);
+        let target = self.position + self.direction() * distance;
+
+        // looking straight up or down would make the view matrix degenerate
+        let dir = -axis.normalize();
+        let elevation = dir
+            .y
+            .clamp(-1.0, 1.0)
+            .asin()
+            .clamp(MIN_ELEVATION_ANGLE.0, MAX_ELEVATION_ANGLE.0);
+        let horizontal = Vector3::new(dir.x, 0.0, dir.z);
+        let horizontal = if horizontal.magnitude2() > f32::EPSILON {
+            horizontal.normalize()
+        } else {
+            -Vector3::unit_z()
+        };
+        self.dir = horizontal * elevation.cos() + Vector3::unit_y() * elevation.sin();
+        self.position = target - self.dir * distance;
+    }
+
     pub fn move_right(&mut self, should_move: bool) {
         self.should_move_right = should_move;
     }
@@ -132,6 +287,20 @@ impl Camera {
         self.should_look_down = should_look;
     }
 
+    /// Stops every movement and turn, as if all their keys were released.
+    pub fn stop(&mut self) {
+        self.should_move_right = false;
+        self.should_move_left = false;
+        self.should_move_forward = false;
+        self.should_move_backward = false;
+        self.should_move_up = false;
+        self.should_move_down = false;
+        self.should_turn_left = false;
+        self.should_turn_right = false;
+        self.should_look_up = false;
+        self.should_look_down = false;
+    }
+
     pub fn update(&mut self) {
         let forward_dir = self.dir_xz_projection();
         let up_dir = Vector3::unit_y();
@@ -153,8 +322,15 @@ impl Camera {
         let vertical_rotation = Quaternion::from_axis_angle(right_dir, vertical_angle);
         self.dir = vertical_rotation.rotate_vector(self.dir);
 
-        let delta_forward =
-            (self.should_move_forward as i8 - self.should_move_backward as i8) as f32 * forward_dir;
+        let forward = (self.should_move_forward as i8 - self.should_move_backward as i8) as f32;
+        // moving closer changes nothing in an orthographic view, so it zooms instead
+        let delta_forward = match &mut self.projection {
+            Projection::Perspective { .. } => forward * forward_dir,
+            Projection::Orthographic { height } => {
+                *height *= 1.0 - DELTA_ZOOM * forward;
+                Vector3::new(0.0, 0.0, 0.0)
+            }
+        };
         let delta_up = (self.should_move_up as i8 - self.should_move_down as i8) as f32 * up_dir;
         let delta_right =
             (self.should_move_right as i8 - self.should_move_left as i8) as f32 * right_dir;
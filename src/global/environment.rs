@@ -0,0 +1,473 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+use wgpu::TextureFormat;
+
+use crate::texture::{HdrImage, MipmapGenerator, TextureError};
+
+use std::borrow::Cow;
+use std::num::NonZeroU32;
+use std::path::Path;
+
+/// Face size of the cubemap the equirectangular image is converted to.
+const ENVIRONMENT_SIZE: u32 = 512;
+const IRRADIANCE_SIZE: u32 = 32;
+const PREFILTER_SIZE: u32 = 128;
+/// Roughness 0 to 1 is spread over these mips; `PREFILTER_MAX_LOD` in light.wgsl is one less.
+const PREFILTER_MIP_COUNT: u32 = 5;
+const BRDF_LUT_SIZE: u32 = 128;
+const CUBE_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+const BRDF_LUT_FORMAT: TextureFormat = TextureFormat::Rg16Float;
+const FACE_COUNT: u32 = 6;
+/// Per-face uniforms are bound with dynamic offsets, which must be aligned to this.
+const FILTER_UNIFORM_STRIDE: u64 = 256;
+/// Radiance of the environment until an image is loaded: the old background color.
+const DEFAULT_RADIANCE: [f32; 3] = [0.1, 0.2, 0.3];
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct FilterUniforms {
+    face: u32,
+    roughness: f32,
+    _0: [f32; 2],
+}
+
+/// Image-based lighting from an equirectangular HDR image: the image is converted to a
+/// cubemap, from which the irradiance map (diffuse) and GGX prefiltered mips (specular) are
+/// computed on the GPU. Loading another image re-renders into the same textures, so bind
+/// groups using the views stay valid.
+pub struct Environment {
+    /// The environment itself with its full mip chain, as seen by the skybox.
+    pub cube_view: wgpu::TextureView,
+    pub irradiance_view: wgpu::TextureView,
+    pub prefiltered_view: wgpu::TextureView,
+    /// Split-sum scale and bias of the specular reflectance, by n.v and roughness.
+    pub brdf_lut_view: wgpu::TextureView,
+    /// Trilinear, clamping sampler for all of the above.
+    pub sampler: wgpu::Sampler,
+
+    cube_texture: wgpu::Texture,
+    irradiance_texture: wgpu::Texture,
+    prefiltered_texture: wgpu::Texture,
+    cube_mip_count: u32,
+    mipmaps: MipmapGenerator,
+    uniform_buffer: wgpu::Buffer,
+    equirect_bind_group_layout: wgpu::BindGroupLayout,
+    cube_bind_group: wgpu::BindGroup,
+    equirect_pipeline: wgpu::RenderPipeline,
+    irradiance_pipeline: wgpu::RenderPipeline,
+    prefilter_pipeline: wgpu::RenderPipeline,
+}
+
+impl Environment {
+    /// Creates the maps for a uniform environment of `DEFAULT_RADIANCE`.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("environment"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..wgpu::SamplerDescriptor::default()
+        });
+
+        let cube_mip_count = u32::BITS - ENVIRONMENT_SIZE.leading_zeros();
+        let cube_texture = create_cube_texture(device, ENVIRONMENT_SIZE, cube_mip_count);
+        let irradiance_texture = create_cube_texture(device, IRRADIANCE_SIZE, 1);
+        let prefiltered_texture = create_cube_texture(device, PREFILTER_SIZE, PREFILTER_MIP_COUNT);
+        let cube_view = create_cube_view(&cube_texture);
+        let irradiance_view = create_cube_view(&irradiance_texture);
+        let prefiltered_view = create_cube_view(&prefiltered_texture);
+
+        // one slot per face and prefiltered mip; the other passes use the first mip's
+        let uniforms: Vec<u8> = (0..PREFILTER_MIP_COUNT)
+            .flat_map(|mip| {
+                (0..FACE_COUNT).flat_map(move |face| {
+                    let uniforms = FilterUniforms {
+                        face,
+                        roughness: mip as f32 / (PREFILTER_MIP_COUNT - 1) as f32,
+                        _0: [0.0; 2],
+                    };
+                    let mut slot = bytemuck::bytes_of(&uniforms).to_vec();
+                    slot.resize(FILTER_UNIFORM_STRIDE as usize, 0);
+                    slot
+                })
+            })
+            .collect();
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("environment filter"),
+            contents: &uniforms,
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let uniform_entry = wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: true,
+                min_binding_size: wgpu::BufferSize::new(
+                    std::mem::size_of::<FilterUniforms>() as u64
+                ),
+            },
+            count: None,
+        };
+        let sampler_entry = wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+        let texture_entry = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let equirect_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("environment equirect"),
+                entries: &[
+                    uniform_entry,
+                    sampler_entry,
+                    texture_entry(2, wgpu::TextureViewDimension::D2),
+                ],
+            });
+        let cube_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("environment cube"),
+                entries: &[
+                    uniform_entry,
+                    sampler_entry,
+                    texture_entry(3, wgpu::TextureViewDimension::Cube),
+                ],
+            });
+        let cube_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("environment cube"),
+            layout: &cube_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &uniform_buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(std::mem::size_of::<FilterUniforms>() as u64),
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&cube_view),
+                },
+            ],
+        });
+
+        let shader_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("global/environment/filter.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                "environment/filter.wgsl"
+            ))),
+        });
+        let create_pipeline =
+            |layouts: &[&wgpu::BindGroupLayout], entry_point, format: TextureFormat| {
+                let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: None,
+                    bind_group_layouts: layouts,
+                    push_constant_ranges: &[],
+                });
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some(entry_point),
+                    layout: Some(&layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader_module,
+                        entry_point: "vs_main",
+                        buffers: &[],
+                    },
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader_module,
+                        entry_point,
+                        targets: &[format.into()],
+                    }),
+                    multiview: None,
+                })
+            };
+        let equirect_pipeline =
+            create_pipeline(&[&equirect_bind_group_layout], "fs_equirect", CUBE_FORMAT);
+        let irradiance_pipeline =
+            create_pipeline(&[&cube_bind_group_layout], "fs_irradiance", CUBE_FORMAT);
+        let prefilter_pipeline =
+            create_pipeline(&[&cube_bind_group_layout], "fs_prefilter", CUBE_FORMAT);
+        // the BRDF does not depend on the environment, so its table is only rendered once
+        let brdf_pipeline = create_pipeline(&[], "fs_brdf", BRDF_LUT_FORMAT);
+
+        let brdf_lut_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("brdf lut"),
+            size: wgpu::Extent3d {
+                width: BRDF_LUT_SIZE,
+                height: BRDF_LUT_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: BRDF_LUT_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        });
+        let brdf_lut_view = brdf_lut_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut rpass = begin_filter_pass(&mut encoder, &brdf_lut_view);
+            rpass.set_pipeline(&brdf_pipeline);
+            rpass.draw(0..3, 0..1);
+        }
+        queue.submit(Some(encoder.finish()));
+
+        let environment = Environment {
+            cube_view,
+            irradiance_view,
+            prefiltered_view,
+            brdf_lut_view,
+            sampler,
+            cube_texture,
+            irradiance_texture,
+            prefiltered_texture,
+            cube_mip_count,
+            mipmaps: MipmapGenerator::new(device, &[CUBE_FORMAT]),
+            uniform_buffer,
+            equirect_bind_group_layout,
+            cube_bind_group,
+            equirect_pipeline,
+            irradiance_pipeline,
+            prefilter_pipeline,
+        };
+        environment.set_image(
+            device,
+            queue,
+            &HdrImage {
+                width: 1,
+                height: 1,
+                rgb: vec![DEFAULT_RADIANCE],
+            },
+        );
+        environment
+    }
+
+    /// Loads an equirectangular Radiance (.hdr) image as the environment. Images wider or
+    /// taller than a texture of `device` can be are rejected.
+    pub fn load_hdr(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: impl AsRef<Path>,
+    ) -> Result<(), TextureError> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|source| TextureError::Io {
+            path: path.to_owned(),
+            source,
+        })?;
+        let image = HdrImage::decode(&bytes).map_err(|source| TextureError::DecodeHdr {
+            path: path.to_owned(),
+            source,
+        })?;
+        let limit = device.limits().max_texture_dimension_2d;
+        if image.width > limit || image.height > limit {
+            return Err(TextureError::TooLarge {
+                path: path.to_owned(),
+                width: image.width,
+                height: image.height,
+                limit,
+            });
+        }
+        self.set_image(device, queue, &image);
+        log::info!(
+            "loaded environment {} ({}x{})",
+            path.display(),
+            image.width,
+            image.height
+        );
+        Ok(())
+    }
+
+    /// Converts an equirectangular image to the environment cubemap and refilters the maps.
+    ///
+    /// # Panics
+    ///
+    /// If the image is larger than the textures of `device` can be, see `load_hdr`.
+    pub fn set_image(&self, device: &wgpu::Device, queue: &wgpu::Queue, image: &HdrImage) {
+        let size = wgpu::Extent3d {
+            width: image.width,
+            height: image.height,
+            depth_or_array_layers: 1,
+        };
+        let equirect_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("environment equirect"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: CUBE_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+        queue.write_texture(
+            equirect_texture.as_image_copy(),
+            bytemuck::cast_slice(&image.to_rgba16f()),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                // four half floats per texel
+                bytes_per_row: Some(
+                    image
+                        .width
+                        .checked_mul(8)
+                        .and_then(NonZeroU32::new)
+                        .expect("environment rows overflow the row pitch"),
+                ),
+                rows_per_image: None,
+            },
+            size,
+        );
+        let equirect_view = equirect_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let equirect_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("environment equirect"),
+            layout: &self.equirect_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &self.uniform_buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(std::mem::size_of::<FilterUniforms>() as u64),
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&equirect_view),
+                },
+            ],
+        });
+
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.render_faces(
+            &mut encoder,
+            &self.cube_texture,
+            0,
+            &self.equirect_pipeline,
+            &equirect_bind_group,
+        );
+        queue.submit(Some(encoder.finish()));
+
+        // the filters read coarser mips of the environment to avoid aliasing
+        self.mipmaps.generate(
+            device,
+            queue,
+            &self.cube_texture,
+            CUBE_FORMAT,
+            self.cube_mip_count,
+            FACE_COUNT,
+        );
+
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.render_faces(
+            &mut encoder,
+            &self.irradiance_texture,
+            0,
+            &self.irradiance_pipeline,
+            &self.cube_bind_group,
+        );
+        for mip in 0..PREFILTER_MIP_COUNT {
+            self.render_faces(
+                &mut encoder,
+                &self.prefiltered_texture,
+                mip,
+                &self.prefilter_pipeline,
+                &self.cube_bind_group,
+            );
+        }
+        queue.submit(Some(encoder.finish()));
+    }
+
+    /// Renders the six faces of one mip of a cubemap, with the uniforms of that mip.
+    fn render_faces(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        mip: u32,
+        pipeline: &wgpu::RenderPipeline,
+        bind_group: &wgpu::BindGroup,
+    ) {
+        for face in 0..FACE_COUNT {
+            let view = texture.create_view(&wgpu::TextureViewDescriptor {
+                label: None,
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_mip_level: mip,
+                mip_level_count: NonZeroU32::new(1),
+                base_array_layer: face,
+                array_layer_count: NonZeroU32::new(1),
+                ..wgpu::TextureViewDescriptor::default()
+            });
+            let offset = (mip * FACE_COUNT + face) as u64 * FILTER_UNIFORM_STRIDE;
+            let mut rpass = begin_filter_pass(encoder, &view);
+            rpass.set_pipeline(pipeline);
+            rpass.set_bind_group(0, bind_group, &[offset as u32]);
+            rpass.draw(0..3, 0..1);
+        }
+    }
+}
+
+fn create_cube_texture(device: &wgpu::Device, size: u32, mip_level_count: u32) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size: wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: FACE_COUNT,
+        },
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: CUBE_FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+    })
+}
+
+fn create_cube_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..wgpu::TextureViewDescriptor::default()
+    })
+}
+
+fn begin_filter_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    view: &'a wgpu::TextureView,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("environment filter"),
+        color_attachments: &[wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: true,
+            },
+        }],
+        depth_stencil_attachment: None,
+    })
+}
@@ -0,0 +1,178 @@
+struct FilterUniforms {
+    // cube face rendered: +X, -X, +Y, -Y, +Z, -Z
+    face: u32;
+    roughness: f32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> u_filter: FilterUniforms;
+[[group(0), binding(1)]]
+var s_source: sampler;
+[[group(0), binding(2)]]
+var t_equirect: texture_2d<f32>;
+[[group(0), binding(3)]]
+var t_cube: texture_cube<f32>;
+
+let PI: f32 = 3.14159265359;
+
+struct VertexOutput {
+    [[location(0)]] uv: vec2<f32>;
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+// one triangle covering the whole target
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.uv = uv;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    return out;
+}
+
+// direction through a texel of the face being rendered
+fn face_direction(uv: vec2<f32>) -> vec3<f32> {
+    let st = uv * 2.0 - vec2<f32>(1.0);
+    var direction: vec3<f32>;
+    switch (i32(u_filter.face)) {
+        case 0: { direction = vec3<f32>(1.0, -st.y, -st.x); }
+        case 1: { direction = vec3<f32>(-1.0, -st.y, st.x); }
+        case 2: { direction = vec3<f32>(st.x, 1.0, st.y); }
+        case 3: { direction = vec3<f32>(st.x, -1.0, -st.y); }
+        case 4: { direction = vec3<f32>(st.x, -st.y, 1.0); }
+        default: { direction = vec3<f32>(-st.x, -st.y, -1.0); }
+    }
+    return normalize(direction);
+}
+
+// orthonormal basis around n, tangent in x and bitangent in y
+fn tangent_frame(n: vec3<f32>) -> mat3x3<f32> {
+    var up = vec3<f32>(0.0, 0.0, 1.0);
+    if (abs(n.z) > 0.999) {
+        up = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    let tangent = normalize(cross(up, n));
+    return mat3x3<f32>(tangent, cross(n, tangent), n);
+}
+
+fn radical_inverse(index: u32) -> f32 {
+    var bits = (index << 16u) | (index >> 16u);
+    bits = ((bits & 1431655765u) << 1u) | ((bits & 2863311530u) >> 1u);
+    bits = ((bits & 858993459u) << 2u) | ((bits & 3435973836u) >> 2u);
+    bits = ((bits & 252645135u) << 4u) | ((bits & 4042322160u) >> 4u);
+    bits = ((bits & 16711935u) << 8u) | ((bits & 4278255360u) >> 8u);
+    return f32(bits) * 2.3283064365386963e-10;
+}
+
+fn hammersley(index: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(index) / f32(count), radical_inverse(index));
+}
+
+// GGX distributed half vector around +Z
+fn importance_sample_ggx(xi: vec2<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+}
+
+fn d_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / max(PI * d * d, 1e-6);
+}
+
+[[stage(fragment)]]
+fn fs_equirect(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let direction = face_direction(in.uv);
+    let uv = vec2<f32>(
+        atan2(direction.z, direction.x) / (2.0 * PI) + 0.5,
+        acos(clamp(direction.y, -1.0, 1.0)) / PI,
+    );
+    return vec4<f32>(textureSampleLevel(t_equirect, s_source, uv, 0.0).rgb, 1.0);
+}
+
+// cosine weighted average of the radiance over the hemisphere around each normal
+[[stage(fragment)]]
+fn fs_irradiance(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let frame = tangent_frame(face_direction(in.uv));
+    // a small mip is enough for such a blurry result, and avoids missing bright spots
+    let level = max(log2(f32(textureDimensions(t_cube).x) / 32.0), 0.0);
+    var irradiance = vec3<f32>(0.0);
+    let phi_steps = 64;
+    let theta_steps = 16;
+    for (var i: i32 = 0; i < phi_steps; i = i + 1) {
+        let phi = 2.0 * PI * (f32(i) + 0.5) / f32(phi_steps);
+        for (var j: i32 = 0; j < theta_steps; j = j + 1) {
+            let theta = 0.5 * PI * (f32(j) + 0.5) / f32(theta_steps);
+            let local = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let radiance = textureSampleLevel(t_cube, s_source, frame * local, level).rgb;
+            irradiance = irradiance + radiance * cos(theta) * sin(theta);
+        }
+    }
+    irradiance = PI * irradiance / f32(phi_steps * theta_steps);
+    return vec4<f32>(irradiance, 1.0);
+}
+
+// GGX prefiltered radiance, assuming the view direction equals the normal
+[[stage(fragment)]]
+fn fs_prefilter(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let n = face_direction(in.uv);
+    let frame = tangent_frame(n);
+    let roughness = u_filter.roughness;
+    let source_size = f32(textureDimensions(t_cube).x);
+    let texel_solid_angle = 4.0 * PI / (6.0 * source_size * source_size);
+    let sample_count = 64u;
+
+    var color = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i: u32 = 0u; i < sample_count; i = i + 1u) {
+        let h = frame * importance_sample_ggx(hammersley(i, sample_count), roughness);
+        let l = 2.0 * dot(n, h) * h - n;
+        let n_dot_l = dot(n, l);
+        if (n_dot_l > 0.0) {
+            // sample a coarser mip the less likely the direction, to keep the result smooth
+            let n_dot_h = max(dot(n, h), 0.0);
+            let pdf = d_ggx(n_dot_h, roughness) * 0.25 + 1e-4;
+            let sample_solid_angle = 1.0 / (f32(sample_count) * pdf);
+            var level = 0.0;
+            if (roughness > 0.0) {
+                level = max(0.5 * log2(sample_solid_angle / texel_solid_angle) + 1.0, 0.0);
+            }
+            color = color + textureSampleLevel(t_cube, s_source, l, level).rgb * n_dot_l;
+            weight = weight + n_dot_l;
+        }
+    }
+    return vec4<f32>(color / max(weight, 1e-4), 1.0);
+}
+
+// scale (x) and bias (y) of f0 in the split-sum approximation, by n.v (u) and roughness (v)
+[[stage(fragment)]]
+fn fs_brdf(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let n_dot_v = max(in.uv.x, 1e-3);
+    let roughness = in.uv.y;
+    let v = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+    let a = roughness * roughness;
+    let k = a / 2.0;
+    let sample_count = 256u;
+
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i: u32 = 0u; i < sample_count; i = i + 1u) {
+        let h = importance_sample_ggx(hammersley(i, sample_count), roughness);
+        let l = 2.0 * dot(v, h) * h - v;
+        let n_dot_l = max(l.z, 0.0);
+        let n_dot_h = max(h.z, 0.0);
+        let v_dot_h = max(dot(v, h), 0.0);
+        if (n_dot_l > 0.0) {
+            let g = n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+            let g_vis = g * v_dot_h / max(n_dot_h * n_dot_v, 1e-4);
+            let fc = pow(1.0 - v_dot_h, 5.0);
+            scale = scale + (1.0 - fc) * g_vis;
+            bias = bias + fc * g_vis;
+        }
+    }
+    return vec4<f32>(scale / f32(sample_count), bias / f32(sample_count), 0.0, 1.0);
+}
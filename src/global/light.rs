@@ -1,32 +1,153 @@
 use bytemuck::{Pod, Zeroable};
-use cgmath::{InnerSpace, Point3, Vector3};
+use cgmath::{Deg, InnerSpace, Point3, Rad, Vector3};
+
+use super::shadow::{CASCADE_COUNT, FACE_COUNT, MAX_POINT_SHADOWS};
+
+/// Lights beyond this many are ignored by the shaders.
+pub const MAX_LIGHTS: usize = 64;
+
+/// Declarations of the light buffer and `shade_lights`, prepended to lit shaders. The sizes
+/// of the shadow matrix arrays are declared from the constants of `Shadows`.
+pub fn lighting_wgsl() -> String {
+    format!(
+        "let CASCADE_COUNT: i32 = {};\nlet POINT_SHADOW_FACES: i32 = {};\n{}",
+        CASCADE_COUNT,
+        MAX_POINT_SHADOWS * FACE_COUNT,
+        include_str!("light.wgsl")
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightKind {
+    Point,
+    Directional,
+    /// Light cone between `inner_angle` (full intensity) and `outer_angle` (no light), measured
+    /// from the light direction.
+    Spot {
+        inner_angle: Rad<f32>,
+        outer_angle: Rad<f32>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    /// For directional lights this only places their gizmo.
+    pub position: Point3<f32>,
+    /// Ignored by point lights.
+    pub direction: Vector3<f32>,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// Distance at which point and spot lights fade out completely; 0.0 means unlimited.
+    pub range: f32,
+    pub cast_shadows: bool,
+}
 
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
 pub struct RawLight {
-    pub point: [f32; 3],
-    pub infinite: [f32; 3],
+    pub position: [f32; 3],
+    pub kind: u32,
+    pub direction: [f32; 3],
+    pub range: f32,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub spot_cos: [f32; 2],
+    /// Index into the shadow maps of the light's kind, or -1 without shadows.
+    pub shadow_index: i32,
+    pub _0: f32,
 }
 
-pub struct Light {
-    pub point: Point3<f32>,
-    pub infinite: Vector3<f32>,
+/// Header of the light storage buffer, followed by `MAX_LIGHTS` `RawLight`s.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct RawLightsHeader {
+    pub ambient: [f32; 3],
+    pub count: u32,
 }
 
 impl Light {
-    pub fn to_raw_light(&self) -> RawLight {
-        RawLight {
-            point: self.point.into(),
-            infinite: self.infinite.into(),
+    pub fn point(position: Point3<f32>, color: [f32; 3], intensity: f32, range: f32) -> Self {
+        Light {
+            kind: LightKind::Point,
+            position,
+            direction: -Vector3::unit_y(),
+            color,
+            intensity,
+            range,
+            cast_shadows: true,
+        }
+    }
+
+    pub fn directional(direction: Vector3<f32>, color: [f32; 3], intensity: f32) -> Self {
+        Light {
+            kind: LightKind::Directional,
+            position: Point3::new(0.0, 5.0, 0.0),
+            direction: direction.normalize(),
+            color,
+            intensity,
+            range: 0.0,
+            cast_shadows: true,
         }
     }
-}
 
-impl Default for Light {
-    fn default() -> Self {
+    pub fn spot(
+        position: Point3<f32>,
+        direction: Vector3<f32>,
+        angle: Rad<f32>,
+        color: [f32; 3],
+        intensity: f32,
+        range: f32,
+    ) -> Self {
         Light {
-            point: Point3::new(4.0, 4.0, 4.0),
-            infinite: Vector3::new(1.0, -1.0, 1.0).normalize(),
+            kind: LightKind::Spot {
+                inner_angle: angle * 0.8,
+                outer_angle: angle,
+            },
+            position,
+            direction: direction.normalize(),
+            color,
+            intensity,
+            range,
+            cast_shadows: true,
         }
     }
+
+    pub fn to_raw_light(&self, shadow_slot: Option<usize>) -> RawLight {
+        let (kind, spot_cos) = match self.kind {
+            LightKind::Point => (0, [-1.0, -1.0]),
+            LightKind::Directional => (1, [-1.0, -1.0]),
+            LightKind::Spot {
+                inner_angle,
+                outer_angle,
+            } => (2, [inner_angle.0.cos(), outer_angle.0.cos()]),
+        };
+        RawLight {
+            position: self.position.into(),
+            kind,
+            direction: self.direction.normalize().into(),
+            range: self.range,
+            color: self.color,
+            intensity: self.intensity,
+            spot_cos,
+            shadow_index: shadow_slot.map_or(-1, |slot| slot as i32),
+            _0: 0.0,
+        }
+    }
+
+    /// The lights the scene starts with, matching the former single point and infinite light.
+    pub fn default_lights() -> Vec<Light> {
+        vec![
+            Light::point(Point3::new(4.0, 4.0, 4.0), [1.0, 1.0, 1.0], 120.0, 0.0),
+            Light::directional(Vector3::new(1.0, -1.0, 1.0), [1.0, 1.0, 1.0], 1.5),
+            Light::spot(
+                Point3::new(-3.0, 4.0, -2.0),
+                Vector3::new(3.0, -4.0, 2.0),
+                Deg(20.0).into(),
+                [1.0, 0.8, 0.6],
+                90.0,
+                15.0,
+            ),
+        ]
+    }
 }
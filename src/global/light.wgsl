@@ -0,0 +1,244 @@
+struct Light {
+    position: vec3<f32>;
+    // 0: point, 1: directional, 2: spot
+    kind: u32;
+    direction: vec3<f32>;
+    range: f32;
+    color: vec3<f32>;
+    intensity: f32;
+    // cosines of the inner and outer cone angles of spot lights
+    spot_cos: vec2<f32>;
+    // cascade set for directional lights, point shadow slot otherwise; -1 without shadows
+    shadow_index: i32;
+};
+
+struct Lights {
+    // scales the image-based lighting of the environment
+    ambient: vec3<f32>;
+    count: u32;
+    lights: array<Light>;
+};
+
+// CASCADE_COUNT and POINT_SHADOW_FACES are declared by `lighting_wgsl`
+struct ShadowUniforms {
+    cascade_vp: array<mat4x4<f32>, CASCADE_COUNT>;
+    // six faces per point shadow: +X, -X, +Y, -Y, +Z, -Z
+    point_vp: array<mat4x4<f32>, POINT_SHADOW_FACES>;
+    cascade_splits: vec4<f32>;
+    cascade_texel_sizes: vec4<f32>;
+    camera_forward: vec3<f32>;
+    point_texel_size: f32;
+};
+
+[[group(0), binding(1)]]
+var<storage, read> u_lights: Lights;
+[[group(0), binding(2)]]
+var<uniform> u_shadow: ShadowUniforms;
+[[group(0), binding(3)]]
+var t_cascade_shadow: texture_depth_2d_array;
+[[group(0), binding(4)]]
+var t_point_shadow: texture_depth_2d_array;
+[[group(0), binding(5)]]
+var s_shadow: sampler_comparison;
+[[group(0), binding(6)]]
+var t_irradiance: texture_cube<f32>;
+[[group(0), binding(7)]]
+var t_prefiltered: texture_cube<f32>;
+[[group(0), binding(8)]]
+var t_brdf_lut: texture_2d<f32>;
+[[group(0), binding(9)]]
+var s_environment: sampler;
+
+// roughness 1 maps to the last mip of the prefiltered environment
+let PREFILTER_MAX_LOD: f32 = 4.0;
+
+fn shadow_uv(clip: vec4<f32>) -> vec3<f32> {
+    let ndc = clip.xyz / clip.w;
+    return vec3<f32>(ndc.xy * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5), ndc.z);
+}
+
+// 3x3 percentage closer filtering on top of the bilinear comparison of the sampler
+fn cascade_pcf(uvz: vec3<f32>, layer: i32) -> f32 {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_cascade_shadow));
+    var lit = 0.0;
+    for (var y: i32 = -1; y <= 1; y = y + 1) {
+        for (var x: i32 = -1; x <= 1; x = x + 1) {
+            let uv = uvz.xy + vec2<f32>(f32(x), f32(y)) * texel;
+            lit = lit + textureSampleCompareLevel(t_cascade_shadow, s_shadow, uv, layer, uvz.z);
+        }
+    }
+    return lit / 9.0;
+}
+
+fn point_pcf(uvz: vec3<f32>, layer: i32) -> f32 {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_point_shadow));
+    var lit = 0.0;
+    for (var y: i32 = -1; y <= 1; y = y + 1) {
+        for (var x: i32 = -1; x <= 1; x = x + 1) {
+            let uv = uvz.xy + vec2<f32>(f32(x), f32(y)) * texel;
+            lit = lit + textureSampleCompareLevel(t_point_shadow, s_shadow, uv, layer, uvz.z);
+        }
+    }
+    return lit / 9.0;
+}
+
+fn cascade_shadow(position: vec3<f32>, normal: vec3<f32>, camera_position: vec3<f32>) -> f32 {
+    let view_depth = dot(position - camera_position, u_shadow.camera_forward);
+    var cascade: i32 = 0;
+    loop {
+        if (cascade >= CASCADE_COUNT - 1 || view_depth <= u_shadow.cascade_splits[cascade]) {
+            break;
+        }
+        cascade = cascade + 1;
+    }
+    if (view_depth > u_shadow.cascade_splits[cascade]) {
+        return 1.0;
+    }
+    // offsetting along the normal by about a texel hides acne on surfaces facing away
+    let offset_position = position + normal * u_shadow.cascade_texel_sizes[cascade] * 1.5;
+    let uvz = shadow_uv(u_shadow.cascade_vp[cascade] * vec4<f32>(offset_position, 1.0));
+    if (any(uvz.xy < vec2<f32>(0.0)) || any(uvz.xy > vec2<f32>(1.0)) || uvz.z > 1.0) {
+        return 1.0;
+    }
+    return cascade_pcf(uvz, cascade);
+}
+
+fn point_shadow(slot: i32, light_position: vec3<f32>, position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    let to_position = position - light_position;
+    let magnitude = abs(to_position);
+    var face: i32;
+    if (magnitude.x >= magnitude.y && magnitude.x >= magnitude.z) {
+        face = select(1, 0, to_position.x > 0.0);
+    } else if (magnitude.y >= magnitude.z) {
+        face = select(3, 2, to_position.y > 0.0);
+    } else {
+        face = select(5, 4, to_position.z > 0.0);
+    }
+    let layer = slot * 6 + face;
+    let texel_size = u_shadow.point_texel_size * length(to_position);
+    let offset_position = position + normal * texel_size * 1.5;
+    let uvz = shadow_uv(u_shadow.point_vp[layer] * vec4<f32>(offset_position, 1.0));
+    if (uvz.z > 1.0) {
+        return 1.0;
+    }
+    return point_pcf(uvz, layer);
+}
+
+// windowed inverse square falloff as in KHR_lights_punctual; a range of 0.0 never cuts off
+fn light_attenuation(distance: f32, range: f32) -> f32 {
+    let inverse_square = 1.0 / max(distance * distance, 1e-4);
+    if (range <= 0.0) {
+        return inverse_square;
+    }
+    let ratio = distance / range;
+    let window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+    return window * window * inverse_square;
+}
+
+let PI: f32 = 3.14159265359;
+
+// GGX / Trowbridge-Reitz normal distribution
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / max(PI * d * d, 1e-6);
+}
+
+// Smith height-correlated visibility, i.e. G / (4 n.l n.v)
+fn visibility_smith(n_dot_l: f32, n_dot_v: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let ggx_v = n_dot_l * sqrt(n_dot_v * n_dot_v * (1.0 - a2) + a2);
+    let ggx_l = n_dot_v * sqrt(n_dot_l * n_dot_l * (1.0 - a2) + a2);
+    return 0.5 / max(ggx_v + ggx_l, 1e-6);
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (vec3<f32>(1.0) - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+fn fresnel_schlick_roughness(cos_theta: f32, f0: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let f90 = max(vec3<f32>(1.0 - roughness), f0);
+    return f0 + (f90 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// Diffuse irradiance and split-sum specular reflection of the environment.
+fn environment_light(
+    diffuse_color: vec3<f32>,
+    f0: vec3<f32>,
+    roughness: f32,
+    normal: vec3<f32>,
+    to_eye: vec3<f32>,
+    n_dot_v: f32,
+) -> vec3<f32> {
+    let fresnel = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+    let irradiance = textureSample(t_irradiance, s_environment, normal).rgb;
+    let diffuse = (vec3<f32>(1.0) - fresnel) * diffuse_color * irradiance;
+    let reflected = reflect(-to_eye, normal);
+    let prefiltered = textureSampleLevel(t_prefiltered, s_environment, reflected, roughness * PREFILTER_MAX_LOD).rgb;
+    let brdf = textureSample(t_brdf_lut, s_environment, vec2<f32>(n_dot_v, roughness)).rg;
+    let specular = prefiltered * (f0 * brdf.x + brdf.y);
+    return (diffuse + specular) * u_lights.ambient;
+}
+
+// Metallic-roughness (glTF) shading of a surface point lit by every light in `u_lights`.
+// `occlusion` only darkens the environment light, direct light is shadowed by the shadow maps.
+fn shade_lights(
+    base_color: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+    occlusion: f32,
+    position: vec3<f32>,
+    normal: vec3<f32>,
+    camera_position: vec3<f32>,
+) -> vec3<f32> {
+    let to_eye = normalize(camera_position - position);
+    let n_dot_v = max(dot(normal, to_eye), 1e-4);
+    let f0 = mix(vec3<f32>(0.04), base_color, metallic);
+    let diffuse_color = base_color * (1.0 - metallic);
+
+    var color = environment_light(diffuse_color, f0, roughness, normal, to_eye, n_dot_v) * occlusion;
+    var i: u32 = 0u;
+    loop {
+        if (i >= u_lights.count) {
+            break;
+        }
+        let light = u_lights.lights[i];
+        var to_light: vec3<f32>;
+        var radiance = light.color * light.intensity;
+        if (light.kind == 1u) {
+            to_light = -normalize(light.direction);
+        } else {
+            let offset = light.position - position;
+            let distance = length(offset);
+            to_light = offset / max(distance, 1e-4);
+            radiance = radiance * light_attenuation(distance, light.range);
+            if (light.kind == 2u) {
+                let cos_angle = dot(-to_light, normalize(light.direction));
+                radiance = radiance * smoothStep(light.spot_cos.y, light.spot_cos.x, cos_angle);
+            }
+        }
+        let n_dot_l = dot(normal, to_light);
+        if (n_dot_l > 0.0) {
+            if (light.shadow_index >= 0) {
+                if (light.kind == 1u) {
+                    radiance = radiance * cascade_shadow(position, normal, camera_position);
+                } else {
+                    radiance = radiance * point_shadow(light.shadow_index, light.position, position, normal);
+                }
+            }
+            let half_vector = normalize(to_light + to_eye);
+            let n_dot_h = max(dot(normal, half_vector), 0.0);
+            let fresnel = fresnel_schlick(max(dot(half_vector, to_eye), 0.0), f0);
+            let specular = fresnel * distribution_ggx(n_dot_h, roughness) * visibility_smith(n_dot_l, n_dot_v, roughness);
+            let diffuse = (vec3<f32>(1.0) - fresnel) * diffuse_color / PI;
+            color = color + (diffuse + specular) * radiance * n_dot_l;
+        }
+
+        continuing {
+            i = i + 1u;
+        }
+    }
+    return color;
+}
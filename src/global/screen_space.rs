@@ -0,0 +1,131 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point2, Point3, SquareMatrix, Vector3, Vector4};
+use winit::dpi::PhysicalSize;
+
+const RAY_EPSILON: f32 = 1e-6;
+
+/// Maps world-space positions to window pixels (origin at the top-left corner) and back.
+#[derive(Debug, Clone, Copy)]
+pub struct ScreenSpace {
+    vp_matrix: Matrix4<f32>,
+    inverse_vp_matrix: Matrix4<f32>,
+    width: f32,
+    height: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+impl ScreenSpace {
+    pub fn new(vp_matrix: Matrix4<f32>, size: PhysicalSize<u32>) -> Self {
+        let inverse_vp_matrix = vp_matrix
+            .invert()
+            .expect("failed to calculate inverse matrix of view projection");
+        ScreenSpace {
+            vp_matrix,
+            inverse_vp_matrix,
+            width: size.width as f32,
+            height: size.height as f32,
+        }
+    }
+
+    pub fn vp_matrix(&self) -> Matrix4<f32> {
+        self.vp_matrix
+    }
+
+    pub fn inverse_vp_matrix(&self) -> Matrix4<f32> {
+        self.inverse_vp_matrix
+    }
+
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+
+    /// Returns `None` for points behind the camera.
+    pub fn project(&self, point: Point3<f32>) -> Option<Point2<f32>> {
+        let clip = self.vp_matrix * point.to_homogeneous();
+        (clip.w > RAY_EPSILON).then(|| {
+            let ndc = clip.truncate() / clip.w;
+            Point2::new(
+                (ndc.x + 1.0) * 0.5 * self.width,
+                (1.0 - ndc.y) * 0.5 * self.height,
+            )
+        })
+    }
+
+    /// `depth` is in normalized device coordinates, i.e. 0.0 on the near plane and 1.0 on the far plane.
+    pub fn unproject(&self, pixel: Point2<f32>, depth: f32) -> Point3<f32> {
+        let ndc = Vector4::new(
+            pixel.x / self.width * 2.0 - 1.0,
+            1.0 - pixel.y / self.height * 2.0,
+            depth,
+            1.0,
+        );
+        let world = self.inverse_vp_matrix * ndc;
+        Point3::from_vec(world.truncate() / world.w)
+    }
+
+    pub fn ray(&self, pixel: Point2<f32>) -> Ray {
+        let near = self.unproject(pixel, 0.0);
+        let far = self.unproject(pixel, 1.0);
+        Ray {
+            origin: near,
+            direction: (far - near).normalize(),
+        }
+    }
+
+    /// World-space length covered by one pixel at the depth of `point`.
+    pub fn pixel_size_at(&self, point: Point3<f32>) -> f32 {
+        let clip = self.vp_matrix * point.to_homogeneous();
+        let depth = clip.z / clip.w;
+        let center = Point2::new(self.width * 0.5, self.height * 0.5);
+        let a = self.unproject(center, depth);
+        let b = self.unproject(Point2::new(center.x + 1.0, center.y), depth);
+        (b - a).magnitude()
+    }
+}
+
+impl Ray {
+    pub fn at(&self, t: f32) -> Point3<f32> {
+        self.origin + self.direction * t
+    }
+
+    /// Möller–Trumbore intersection, returning the distance along the ray.
+    pub fn intersect_triangle(&self, triangle: [Point3<f32>; 3]) -> Option<f32> {
+        let edge1 = triangle[1] - triangle[0];
+        let edge2 = triangle[2] - triangle[0];
+        let p = self.direction.cross(edge2);
+        let det = edge1.dot(p);
+        if det.abs() < RAY_EPSILON {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let s = self.origin - triangle[0];
+        let u = s.dot(p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s.cross(edge1);
+        let v = self.direction.dot(q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = edge2.dot(q) * inv_det;
+        (t > RAY_EPSILON).then_some(t)
+    }
+
+    pub fn intersect_plane(&self, origin: Point3<f32>, normal: Vector3<f32>) -> Option<f32> {
+        let denom = self.direction.dot(normal);
+        if denom.abs() < RAY_EPSILON {
+            return None;
+        }
+        let t = (origin - self.origin).dot(normal) / denom;
+        (t > 0.0).then_some(t)
+    }
+}
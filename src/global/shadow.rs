@@ -0,0 +1,391 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{Deg, EuclideanSpace, InnerSpace, Matrix4, MetricSpace, Point3, Vector3, Vector4};
+use wgpu::util::DeviceExt;
+
+use super::camera::OPENGL_TO_WGPU_MATRIX;
+use super::{Camera, Light, LightKind};
+
+use std::mem;
+use std::num::NonZeroU32;
+
+pub const CASCADE_COUNT: usize = 4;
+// the splits and texel sizes of the cascades are a vec4 each in light.wgsl
+const _: () = assert!(CASCADE_COUNT == 4);
+/// Point and spot lights beyond this many do not cast shadows.
+pub const MAX_POINT_SHADOWS: usize = 4;
+const CASCADE_MAP_SIZE: u32 = 2048;
+const POINT_MAP_SIZE: u32 = 512;
+/// View distance covered by the cascades.
+const SHADOW_DISTANCE: f32 = 60.0;
+/// Blend between logarithmic (1.0) and uniform (0.0) cascade splits.
+const CASCADE_SPLIT_LAMBDA: f32 = 0.75;
+/// How far behind a cascade casters are still rendered into it.
+const CASTER_MARGIN: f32 = 50.0;
+const POINT_SHADOW_NEAR: f32 = 0.05;
+/// Far plane of point light shadows when the light range is unlimited.
+const POINT_SHADOW_FAR: f32 = 50.0;
+pub(super) const FACE_COUNT: usize = 6;
+const LAYER_COUNT: usize = CASCADE_COUNT + MAX_POINT_SHADOWS * FACE_COUNT;
+/// Layer matrices are bound with dynamic offsets, which must be aligned to this.
+const PASS_UNIFORM_STRIDE: u64 = 256;
+
+/// Stands in for the global uniforms while rendering a shadow map, so lit shaders can reuse
+/// their vertex stage for the depth-only pass.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct ShadowPassUniforms {
+    vp_matrix: [f32; 16],
+    light_pos: [f32; 3],
+    _0: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub(crate) struct ShadowUniforms {
+    cascade_vp: [[f32; 16]; CASCADE_COUNT],
+    point_vp: [[f32; 16]; MAX_POINT_SHADOWS * FACE_COUNT],
+    /// Far view distance of each cascade.
+    cascade_splits: [f32; CASCADE_COUNT],
+    /// World-space size of a shadow map texel in each cascade.
+    cascade_texel_sizes: [f32; CASCADE_COUNT],
+    camera_forward: [f32; 3],
+    /// Size of a point shadow texel one unit away from the light.
+    point_texel_size: f32,
+}
+
+/// Something drawn into the shadow maps. Bind group 0 is set to the light's view before
+/// `draw_shadow` is called, laid out like the global uniforms.
+pub trait ShadowCaster {
+    fn draw_shadow<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>);
+}
+
+/// Cascaded shadow maps for the first shadow-casting directional light and six-sided shadow
+/// maps for the first `MAX_POINT_SHADOWS` shadow-casting point and spot lights.
+pub struct Shadows {
+    /// Layers that have to be rendered this frame.
+    active_layers: Vec<usize>,
+
+    pub cascade_texture: wgpu::Texture,
+    pub point_texture: wgpu::Texture,
+    pub cascade_view: wgpu::TextureView,
+    pub point_view: wgpu::TextureView,
+    layer_views: Vec<wgpu::TextureView>,
+
+    pub uniform_buffer: wgpu::Buffer,
+    pub pass_uniform_buffer: wgpu::Buffer,
+    pub pass_bind_group: wgpu::BindGroup,
+    /// Layout of bind group 0 in shadow pipelines.
+    pub pass_bind_group_layout: wgpu::BindGroupLayout,
+}
+
+/// Shadow slot of each light: 0 for the directional light with cascades, the point shadow index
+/// for point and spot lights, `None` for lights without shadows.
+pub(crate) fn shadow_slots(lights: &[Light]) -> Vec<Option<usize>> {
+    let mut has_directional = false;
+    let mut point_count = 0;
+    lights
+        .iter()
+        .map(|light| {
+            if !light.cast_shadows {
+                return None;
+            }
+            match light.kind {
+                LightKind::Directional if !has_directional => {
+                    has_directional = true;
+                    Some(0)
+                }
+                LightKind::Point | LightKind::Spot { .. } if point_count < MAX_POINT_SHADOWS => {
+                    point_count += 1;
+                    Some(point_count - 1)
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+impl Shadows {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let create_texture = |size: u32, layers: usize| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: wgpu::Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: layers as u32,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Depth32Float,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+            })
+        };
+        let cascade_texture = create_texture(CASCADE_MAP_SIZE, CASCADE_COUNT);
+        let point_texture = create_texture(POINT_MAP_SIZE, MAX_POINT_SHADOWS * FACE_COUNT);
+
+        let array_view = |texture: &wgpu::Texture| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                ..wgpu::TextureViewDescriptor::default()
+            })
+        };
+        let cascade_view = array_view(&cascade_texture);
+        let point_view = array_view(&point_texture);
+
+        let layer_views = (0..LAYER_COUNT)
+            .map(|layer| {
+                let (texture, base_array_layer) = if layer < CASCADE_COUNT {
+                    (&cascade_texture, layer)
+                } else {
+                    (&point_texture, layer - CASCADE_COUNT)
+                };
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: base_array_layer as u32,
+                    array_layer_count: NonZeroU32::new(1),
+                    ..wgpu::TextureViewDescriptor::default()
+                })
+            })
+            .collect();
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&ShadowUniforms::zeroed()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let pass_uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: PASS_UNIFORM_STRIDE * LAYER_COUNT as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let pass_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(
+                            mem::size_of::<ShadowPassUniforms>() as u64,
+                        ),
+                    },
+                    count: None,
+                }],
+            });
+
+        let pass_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pass_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &pass_uniform_buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(mem::size_of::<ShadowPassUniforms>() as u64),
+                }),
+            }],
+        });
+
+        Shadows {
+            active_layers: Vec::new(),
+            cascade_texture,
+            point_texture,
+            cascade_view,
+            point_view,
+            layer_views,
+            uniform_buffer,
+            pass_uniform_buffer,
+            pass_bind_group,
+            pass_bind_group_layout,
+        }
+    }
+
+    /// Fits the cascades to the camera and the point shadow faces to their lights.
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        camera: &Camera,
+        aspect_ratio: f32,
+        lights: &[Light],
+    ) {
+        let mut uniforms = ShadowUniforms::zeroed();
+        let mut pass_uniforms = vec![ShadowPassUniforms::zeroed(); LAYER_COUNT];
+        self.active_layers.clear();
+
+        let camera_forward = camera.direction();
+        uniforms.camera_forward = camera_forward.into();
+        uniforms.point_texel_size = 2.0 / POINT_MAP_SIZE as f32;
+
+        let slots = shadow_slots(lights);
+        for (light, slot) in lights.iter().zip(slots) {
+            let slot = match slot {
+                Some(slot) => slot,
+                None => continue,
+            };
+            match light.kind {
+                LightKind::Directional => {
+                    let direction = light.direction.normalize();
+                    let splits = cascade_splits(camera.near(), camera.far().min(SHADOW_DISTANCE));
+                    let mut near = camera.near();
+                    for (i, &far) in splits.iter().enumerate() {
+                        let corners = camera.frustum_corners(aspect_ratio, near, far);
+                        let (vp_matrix, texel_size) = fit_cascade(&corners, direction);
+                        uniforms.cascade_vp[i] = *vp_matrix.as_ref();
+                        uniforms.cascade_splits[i] = far;
+                        uniforms.cascade_texel_sizes[i] = texel_size;
+                        pass_uniforms[i].vp_matrix = *vp_matrix.as_ref();
+                        self.active_layers.push(i);
+                        near = far;
+                    }
+                }
+                LightKind::Point | LightKind::Spot { .. } => {
+                    let far = if light.range > 0.0 {
+                        light.range
+                    } else {
+                        POINT_SHADOW_FAR
+                    };
+                    for (face, vp_matrix) in face_vp_matrices(light.position, far)
+                        .into_iter()
+                        .enumerate()
+                    {
+                        let index = slot * FACE_COUNT + face;
+                        uniforms.point_vp[index] = *vp_matrix.as_ref();
+                        pass_uniforms[CASCADE_COUNT + index] = ShadowPassUniforms {
+                            vp_matrix: *vp_matrix.as_ref(),
+                            light_pos: light.position.into(),
+                            _0: 0.0,
+                        };
+                        self.active_layers.push(CASCADE_COUNT + index);
+                    }
+                }
+            }
+        }
+
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+        for (layer, pass_uniform) in pass_uniforms.iter().enumerate() {
+            queue.write_buffer(
+                &self.pass_uniform_buffer,
+                layer as u64 * PASS_UNIFORM_STRIDE,
+                bytemuck::bytes_of(pass_uniform),
+            );
+        }
+    }
+
+    /// Renders `casters` into every shadow map in use.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, casters: &[&dyn ShadowCaster]) {
+        for &layer in &self.active_layers {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("shadow pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.layer_views[layer],
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            let offset = (layer as u64 * PASS_UNIFORM_STRIDE) as u32;
+            rpass.set_bind_group(0, &self.pass_bind_group, &[offset]);
+            for caster in casters {
+                caster.draw_shadow(&mut rpass);
+            }
+        }
+    }
+
+    /// Depth-stencil state for shadow pipelines, biased against shadow acne.
+    pub fn depth_stencil_state() -> wgpu::DepthStencilState {
+        wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth32Float,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState {
+                constant: 2,
+                slope_scale: 2.0,
+                clamp: 0.0,
+            },
+        }
+    }
+}
+
+/// Far view distance of each cascade, mixing logarithmic and uniform splits.
+fn cascade_splits(near: f32, far: f32) -> [f32; CASCADE_COUNT] {
+    let mut splits = [0.0; CASCADE_COUNT];
+    for (i, split) in splits.iter_mut().enumerate() {
+        let ratio = (i + 1) as f32 / CASCADE_COUNT as f32;
+        let logarithmic = near * (far / near).powf(ratio);
+        let uniform = near + (far - near) * ratio;
+        *split = CASCADE_SPLIT_LAMBDA * logarithmic + (1.0 - CASCADE_SPLIT_LAMBDA) * uniform;
+    }
+    splits
+}
+
+/// Orthographic light view-projection enclosing the bounding sphere of `corners`, snapped to
+/// whole texels so shadows do not shimmer as the camera moves. Also returns the texel size.
+fn fit_cascade(corners: &[Point3<f32>; 8], direction: Vector3<f32>) -> (Matrix4<f32>, f32) {
+    let center = Point3::centroid(corners);
+    let radius = corners
+        .iter()
+        .map(|corner| corner.distance(center))
+        .fold(0.0, f32::max);
+    // rounding keeps the projection size fixed while the camera only rotates
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    let up = if direction.y.abs() > 0.99 {
+        Vector3::unit_z()
+    } else {
+        Vector3::unit_y()
+    };
+    let eye = center - direction * (radius + CASTER_MARGIN);
+    let view_matrix = Matrix4::look_to_rh(eye, direction, up);
+    let projection_matrix = OPENGL_TO_WGPU_MATRIX
+        * cgmath::ortho(
+            -radius,
+            radius,
+            -radius,
+            radius,
+            0.0,
+            2.0 * radius + CASTER_MARGIN,
+        );
+    let vp_matrix = projection_matrix * view_matrix;
+
+    let texels_per_unit = CASCADE_MAP_SIZE as f32 * 0.5;
+    let origin = vp_matrix * Vector4::new(0.0, 0.0, 0.0, 1.0);
+    let snapped_x = (origin.x * texels_per_unit).round() / texels_per_unit;
+    let snapped_y = (origin.y * texels_per_unit).round() / texels_per_unit;
+    let snap = Matrix4::from_translation(Vector3::new(
+        snapped_x - origin.x,
+        snapped_y - origin.y,
+        0.0,
+    ));
+    (snap * vp_matrix, 2.0 * radius / CASCADE_MAP_SIZE as f32)
+}
+
+/// View-projections of the +X, -X, +Y, -Y, +Z and -Z faces around `position`.
+fn face_vp_matrices(position: Point3<f32>, far: f32) -> [Matrix4<f32>; FACE_COUNT] {
+    let projection_matrix = OPENGL_TO_WGPU_MATRIX
+        * cgmath::perspective(
+            Deg(90.0),
+            1.0,
+            POINT_SHADOW_NEAR,
+            far.max(POINT_SHADOW_NEAR * 2.0),
+        );
+    let faces = [
+        (Vector3::unit_x(), -Vector3::unit_y()),
+        (-Vector3::unit_x(), -Vector3::unit_y()),
+        (Vector3::unit_y(), Vector3::unit_z()),
+        (-Vector3::unit_y(), -Vector3::unit_z()),
+        (Vector3::unit_z(), -Vector3::unit_y()),
+        (-Vector3::unit_z(), -Vector3::unit_y()),
+    ];
+    faces.map(|(direction, up)| projection_matrix * Matrix4::look_to_rh(position, direction, up))
+}
@@ -0,0 +1,100 @@
+use cgmath::{Point2, Vector2};
+
+use crate::bounds::CullStats;
+use crate::selection::{Selection, SelectionMode};
+use crate::shapes::TextBatch;
+use crate::viewport::View;
+
+/// Distance of the panel from the corner of the view, and of the text from the panel edges,
+/// in pixels.
+const MARGIN: f32 = 8.0;
+const PADDING: f32 = 6.0;
+const TEXT_SCALE: u32 = 2;
+const TEXT_COLOR: [f32; 4] = [0.9, 0.9, 0.9, 1.0];
+const PANEL_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+
+/// What was drawn in the view the HUD is shown in.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameStats {
+    /// In milliseconds, averaged over the last frames.
+    pub average_frametime: Option<f32>,
+    pub triangles: usize,
+    pub cull_stats: CullStats,
+}
+
+/// Overlay in the top-left corner of a view with the frame rate and what the view shows.
+pub struct Hud {
+    pub visible: bool,
+}
+
+impl Default for Hud {
+    fn default() -> Self {
+        Hud { visible: true }
+    }
+}
+
+impl Hud {
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Queues the HUD of `view` into `text`, if it is visible.
+    pub fn draw(
+        &self,
+        text: &mut TextBatch,
+        view: &View,
+        selection: &Selection,
+        stats: &FrameStats,
+    ) {
+        if !self.visible {
+            return;
+        }
+        let timing = match stats.average_frametime {
+            Some(frametime) => format!("{:.0} fps, {:.2} ms", 1000.0 / frametime, frametime),
+            None => "measuring...".to_owned(),
+        };
+        let position = view.camera.position();
+        let lines = [
+            timing,
+            format!("{} view, {:?} shading", view.name, view.shading),
+            format!(
+                "camera {:.2}, {:.2}, {:.2}",
+                position.x, position.y, position.z
+            ),
+            format!("selection: {}", Self::describe(selection)),
+            format!("{} triangles", stats.triangles),
+            stats.cull_stats.to_string(),
+        ]
+        .join("\n");
+
+        let size = TextBatch::measure(&lines, TEXT_SCALE);
+        let corner = Point2::new(MARGIN, MARGIN);
+        text.rect(
+            corner,
+            size + Vector2::new(PADDING, PADDING) * 2.0,
+            PANEL_COLOR,
+        );
+        text.text(
+            corner + Vector2::new(PADDING, PADDING),
+            &lines,
+            TEXT_SCALE,
+            TEXT_COLOR,
+        );
+    }
+
+    fn describe(selection: &Selection) -> String {
+        let count = selection.elements().count();
+        let noun = match selection.mode() {
+            SelectionMode::Vertex => "vertex",
+            SelectionMode::Edge => "edge",
+            SelectionMode::Face => "face",
+            SelectionMode::Object => "object",
+        };
+        match count {
+            0 => "none".to_owned(),
+            1 => format!("1 {}", noun),
+            _ if noun == "vertex" => format!("{} vertices", count),
+            _ => format!("{} {}s", count, noun),
+        }
+    }
+}
@@ -1,8 +1,27 @@
+pub mod bounds;
 pub mod context;
+pub mod debug_draw;
+pub mod draw_list;
+pub mod gizmo;
 mod global;
+pub mod hud;
+pub mod light_gizmo;
 mod loop_clock;
+pub mod material;
+pub mod mesh;
+pub mod post_process;
+pub mod render_graph;
+pub mod selection;
+pub mod shading;
 pub mod shapes;
+pub mod snapping;
+pub mod texture;
+pub mod tone_mapping;
+pub mod viewport;
 
 pub use context::Context;
-pub use global::Camera;
+pub use global::{
+    lighting_wgsl, Camera, Environment, Light, LightKind, Projection, Ray, ScreenSpace,
+    ShadowCaster, Shadows, CASCADE_COUNT, MAX_LIGHTS, MAX_POINT_SHADOWS,
+};
 pub use loop_clock::LoopClock;
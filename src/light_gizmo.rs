@@ -0,0 +1,164 @@
+use cgmath::{InnerSpace, MetricSpace, Point2, Point3, Vector3};
+
+use crate::shapes::{circle_points, perpendicular_basis, LineBatch};
+use crate::{Light, LightKind, ScreenSpace};
+
+const MARKER_SIZE_IN_PIXELS: f32 = 10.0;
+const ARROW_LENGTH_IN_PIXELS: f32 = 60.0;
+const SPOT_LENGTH_IN_PIXELS: f32 = 50.0;
+const PICK_RADIUS: f32 = 12.0;
+const MARKER_SEGMENTS: usize = 16;
+const HOVER_COLOR: [f32; 4] = [1.0, 1.0, 0.0, 1.0];
+
+#[derive(Debug, Clone, Copy)]
+struct LightDrag {
+    index: usize,
+    /// Lights are dragged in the plane through the light facing the camera.
+    plane_normal: Vector3<f32>,
+    /// Offset from the grabbed point to the light position.
+    offset: Vector3<f32>,
+}
+
+/// Markers for the scene lights: a ring at the position of point lights, a cone for spot lights
+/// and an arrow for directional lights. Lights can be grabbed and moved with the mouse.
+#[derive(Debug, Default)]
+pub struct LightGizmo {
+    hovered: Option<usize>,
+    drag: Option<LightDrag>,
+}
+
+impl LightGizmo {
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    /// Index of the light whose marker is under `cursor`.
+    pub fn pick(
+        &self,
+        cursor: Point2<f32>,
+        screen: &ScreenSpace,
+        lights: &[Light],
+    ) -> Option<usize> {
+        lights
+            .iter()
+            .enumerate()
+            .filter_map(|(i, light)| Some((i, screen.project(light.position)?.distance(cursor))))
+            .filter(|&(_, distance)| distance <= PICK_RADIUS)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    pub fn hover(&mut self, cursor: Point2<f32>, screen: &ScreenSpace, lights: &[Light]) {
+        if self.drag.is_none() {
+            self.hovered = self.pick(cursor, screen, lights);
+        }
+    }
+
+    /// Grabs the light under the cursor. Returns `false` if there is none.
+    pub fn begin_drag(
+        &mut self,
+        cursor: Point2<f32>,
+        screen: &ScreenSpace,
+        lights: &[Light],
+    ) -> bool {
+        let index = match self.pick(cursor, screen, lights) {
+            Some(index) => index,
+            None => return false,
+        };
+        let position = lights[index].position;
+        let plane_normal = view_direction(screen);
+        let ray = screen.ray(cursor);
+        let grabbed = match ray.intersect_plane(position, plane_normal) {
+            Some(t) => ray.at(t),
+            None => return false,
+        };
+        self.hovered = Some(index);
+        self.drag = Some(LightDrag {
+            index,
+            plane_normal,
+            offset: position - grabbed,
+        });
+        true
+    }
+
+    pub fn end_drag(&mut self) {
+        self.drag = None;
+    }
+
+    /// Index and new position of the dragged light.
+    pub fn drag_position(
+        &self,
+        cursor: Point2<f32>,
+        screen: &ScreenSpace,
+        lights: &[Light],
+    ) -> Option<(usize, Point3<f32>)> {
+        let drag = self.drag?;
+        let origin = lights.get(drag.index)?.position;
+        let ray = screen.ray(cursor);
+        let t = ray.intersect_plane(origin, drag.plane_normal)?;
+        Some((drag.index, ray.at(t) + drag.offset))
+    }
+
+    pub fn draw(&self, lines: &mut LineBatch, screen: &ScreenSpace, lights: &[Light]) {
+        let active = self.drag.map(|drag| drag.index).or(self.hovered);
+        let facing = view_direction(screen);
+        for (i, light) in lights.iter().enumerate() {
+            let color = if active == Some(i) {
+                HOVER_COLOR
+            } else {
+                let [r, g, b] = light.color;
+                let max = r.max(g).max(b).max(f32::EPSILON);
+                [r / max, g / max, b / max, 1.0]
+            };
+            let position = light.position;
+            let pixel = screen.pixel_size_at(position);
+            let radius = MARKER_SIZE_IN_PIXELS * pixel;
+            let direction = light.direction.normalize();
+
+            lines.polyline_closed(
+                &circle_points(position, facing, radius, MARKER_SEGMENTS),
+                color,
+            );
+            match light.kind {
+                LightKind::Point => {
+                    // short rays around the ring
+                    let [u, v] = perpendicular_basis(facing);
+                    for k in 0..8 {
+                        let angle = k as f32 / 8.0 * std::f32::consts::TAU;
+                        let ray = u * angle.cos() + v * angle.sin();
+                        lines.line(
+                            position + ray * radius * 1.4,
+                            position + ray * radius * 2.0,
+                            color,
+                        );
+                    }
+                }
+                LightKind::Directional => {
+                    let tip = position + direction * ARROW_LENGTH_IN_PIXELS * pixel;
+                    lines.line(position, tip, color);
+                    let head = radius;
+                    let [u, v] = perpendicular_basis(direction);
+                    for side in [u, -u, v, -v] {
+                        lines.line(tip, tip - direction * head + side * head * 0.5, color);
+                    }
+                }
+                LightKind::Spot { outer_angle, .. } => {
+                    let length = SPOT_LENGTH_IN_PIXELS * pixel;
+                    let base = position + direction * length;
+                    let base_radius = length * outer_angle.0.tan();
+                    let points = circle_points(base, direction, base_radius, MARKER_SEGMENTS);
+                    lines.polyline_closed(&points, color);
+                    for point in points.iter().step_by(MARKER_SEGMENTS / 4) {
+                        lines.line(position, *point, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Direction the camera looks in, taken from the ray through the center of the screen.
+fn view_direction(screen: &ScreenSpace) -> Vector3<f32> {
+    let center = Point2::new(screen.width() * 0.5, screen.height() * 0.5);
+    screen.ray(center).direction
+}
@@ -4,6 +4,7 @@ pub struct LoopClock {
     target_frametime: Duration,
     frame_count: i32,
     accum_time: f32,
+    average_frametime: Option<f32>,
     last_update_inst: Instant,
     last_frame_inst: Instant,
 }
@@ -20,6 +21,7 @@ impl LoopClock {
             target_frametime,
             frame_count,
             accum_time,
+            average_frametime: None,
             last_update_inst,
             last_frame_inst,
         }
@@ -44,7 +46,13 @@ impl LoopClock {
             let average_frametime = self.accum_time * 1000.0 / self.frame_count as f32;
             self.accum_time = 0.0;
             self.frame_count = 0;
+            self.average_frametime = Some(average_frametime);
             average_frametime
         })
     }
+
+    /// The last average `tick` returned, in milliseconds, if there was one yet.
+    pub fn average_frametime(&self) -> Option<f32> {
+        self.average_frametime
+    }
 }
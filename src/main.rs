@@ -1,7 +1,29 @@
-use modelers::shapes::BaseLine;
-use modelers::LoopClock;
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point2, Point3, Transform, Vector3};
+use modelers::bounds::{Bounds, CullStats};
+use modelers::context::SAMPLE_COUNTS;
+use modelers::draw_list::DrawList;
+use modelers::gizmo::{Constraint, Gizmo, GizmoDelta, GizmoMode};
+use modelers::hud::{FrameStats, Hud};
+use modelers::light_gizmo::LightGizmo;
+use modelers::material::{AlphaMode, Material};
+use modelers::post_process::{OutlineCaster, PostProcess};
+use modelers::render_graph::{FrameTargets, RenderGraph, TexturePool};
+use modelers::selection::{
+    Region, SelectOp, Selection, SelectionDrag, SelectionMode, TransformSession,
+};
+use modelers::shading::ShadingMode;
+use modelers::shapes::{
+    benchmark_instancing, AxisWidget, BaseLine, CubeInstances, Grid, LineBatch, LodMesh, Skybox,
+    TextBatch,
+};
+use modelers::snapping::{self, Nudge, Snapping};
+use modelers::texture::{Texture, ASSET_DIR};
+use modelers::viewport::ViewportLayout;
+use modelers::{Light, LightKind, LoopClock, ShadowCaster};
 use wgpu::Operations;
-use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit::event::{
+    ElementState, Event, KeyboardInput, ModifiersState, MouseButton, VirtualKeyCode, WindowEvent,
+};
 use winit::event_loop::ControlFlow;
 
 use futures::executor;
@@ -9,6 +31,26 @@ use futures::executor;
 use modelers::{shapes::Cube, Context};
 
 use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+
+const CUBE_ID: usize = 0;
+/// Copies per side of the instanced field toggled with C.
+const INSTANCE_FIELD_SIDE: i32 = 16;
+const INSTANCE_FIELD_SPACING: f32 = 3.0;
+/// Copies drawn by the instancing benchmark run with B.
+const BENCHMARK_COUNTS: [usize; 3] = [100, 1_000, 10_000];
+/// Quads around and along the sphere standing in for a scanned mesh.
+const SCAN_SEGMENTS: u32 = 128;
+const SCAN_RINGS: u32 = 64;
+/// How long the ray of a click stays drawn with the debug shapes.
+const PICK_RAY_LIFETIME: Duration = Duration::from_secs(3);
+const PICK_RAY_LENGTH: f32 = 20.0;
+const PICK_RAY_COLOR: [f32; 4] = [1.0, 0.2, 0.8, 1.0];
+/// Texels per side of the perforated material's base color, and holes per side.
+const PERFORATION_SIZE: u32 = 256;
+const PERFORATION_HOLES: u32 = 8;
 
 fn main() {
     env_logger::Builder::from_default_env()
@@ -28,8 +70,74 @@ fn main() {
         .init();
 
     let (mut ctx, event_loop) = executor::block_on(Context::create_context());
-    let base_line = BaseLine::new(&ctx);
+    // an equirectangular .hdr given on the command line, or the bundled one if there is any
+    let environment_path = match std::env::args().nth(1) {
+        Some(path) => Some(PathBuf::from(path)),
+        None => Some(Path::new(ASSET_DIR).join("environment.hdr")).filter(|path| path.exists()),
+    };
+    if let Some(path) = environment_path {
+        let environment = &ctx.global.environment;
+        if let Err(error) = environment.load_hdr(&ctx.device, &ctx.queue, path) {
+            log::error!("{}", error);
+        }
+    }
+    let mut skybox = Skybox::new(&ctx);
+    let mut base_line = BaseLine::new(&ctx);
+    let mut grid = Grid::new(&ctx);
     let mut cube = Cube::new(&ctx);
+    let mut instances = CubeInstances::new(&ctx, &cube, cube.material.clone());
+    let mut scan = {
+        let (positions, uvs, indices) = scan_sphere();
+        let clay = Material::dielectric("clay", [0.7, 0.55, 0.45, 1.0], 0.8);
+        LodMesh::new(&ctx, &cube, clay, &positions, &uvs, &indices)
+    };
+    scan.model_matrix = Matrix4::from_translation(Vector3::new(4.0, 0.0, 0.0));
+    scan.update(&ctx.queue);
+    let mut overlay_lines = LineBatch::new(&ctx);
+    let mut overlay_text = TextBatch::new(&ctx);
+    let mut hud = Hud::default();
+    // bounds, normals, light directions and pick rays, toggled with F12
+    let mut debug_shapes = false;
+    let materials = [
+        cube.material.clone(),
+        Material {
+            name: "gold".to_owned(),
+            base_color_factor: [1.0, 0.77, 0.34, 1.0],
+            metallic_factor: 1.0,
+            roughness_factor: 0.3,
+            ..Material::default()
+        },
+        Material::dielectric("red plastic", [0.8, 0.05, 0.05, 1.0], 0.4),
+        Material {
+            // bright enough to bloom
+            emissive_factor: [4.0, 1.6, 0.4],
+            ..Material::dielectric("glowing", [0.1, 0.1, 0.1, 1.0], 0.5)
+        },
+        Material {
+            alpha_mode: AlphaMode::Blend,
+            ..Material::dielectric("glass", [0.6, 0.8, 0.9, 0.35], 0.05)
+        },
+        Material {
+            alpha_mode: AlphaMode::Mask,
+            base_color_texture: Some(Rc::new(perforated_texture(&ctx))),
+            ..Material::dielectric("perforated", [0.9, 0.9, 0.9, 1.0], 0.6)
+        },
+    ];
+    let mut material_index = 0;
+    let mut axis_widget = AxisWidget::new(&ctx);
+    let mut post_process = PostProcess::new(&ctx);
+    let mut texture_pool = TexturePool::new();
+    let mut layout = ViewportLayout::new(&ctx);
+
+    let mut selection = Selection::default();
+    let mut selection_drag: Option<SelectionDrag> = None;
+    let mut gizmo = Gizmo::default();
+    let mut light_gizmo = LightGizmo::default();
+    let mut transform_session: Option<TransformSession> = None;
+    let mut snapping = Snapping::default();
+    let mut snap_point: Option<Point3<f32>> = None;
+    let mut cursor = Point2::new(0.0, 0.0);
+    let mut modifiers = ModifiersState::empty();
 
     let mut loop_clock = LoopClock::start_clock(60.0);
 
@@ -63,20 +171,272 @@ fn main() {
                         ElementState::Pressed => true,
                         ElementState::Released => false,
                     };
+                    if let (true, Some(nudge)) = (should_do, Nudge::from_keycode(keycode)) {
+                        nudge_selection(&ctx, &mut cube, &selection, &gizmo, &snapping, nudge);
+                    }
+                    let view = layout.focused_mut();
                     match keycode {
-                        VirtualKeyCode::W => ctx.global.camera.move_forward(should_do),
-                        VirtualKeyCode::A => ctx.global.camera.move_left(should_do),
-                        VirtualKeyCode::S => ctx.global.camera.move_backward(should_do),
-                        VirtualKeyCode::D => ctx.global.camera.move_right(should_do),
-                        VirtualKeyCode::LShift => ctx.global.camera.move_down(should_do),
-                        VirtualKeyCode::Space => ctx.global.camera.move_up(should_do),
-                        VirtualKeyCode::H => ctx.global.camera.turn_left(should_do),
-                        VirtualKeyCode::L => ctx.global.camera.turn_right(should_do),
-                        VirtualKeyCode::J => ctx.global.camera.look_down(should_do),
-                        VirtualKeyCode::K => ctx.global.camera.look_up(should_do),
+                        VirtualKeyCode::W => view.camera.move_forward(should_do),
+                        VirtualKeyCode::A => view.camera.move_left(should_do),
+                        VirtualKeyCode::S => view.camera.move_backward(should_do),
+                        VirtualKeyCode::D => view.camera.move_right(should_do),
+                        VirtualKeyCode::LShift => view.camera.move_down(should_do),
+                        VirtualKeyCode::Space => view.camera.move_up(should_do),
+                        VirtualKeyCode::H => view.camera.turn_left(should_do),
+                        VirtualKeyCode::L => view.camera.turn_right(should_do),
+                        VirtualKeyCode::J => view.camera.look_down(should_do),
+                        VirtualKeyCode::K => view.camera.look_up(should_do),
+                        VirtualKeyCode::Key1 if should_do => {
+                            selection.set_mode(SelectionMode::Vertex)
+                        }
+                        VirtualKeyCode::Key2 if should_do => {
+                            selection.set_mode(SelectionMode::Edge)
+                        }
+                        VirtualKeyCode::Key3 if should_do => {
+                            selection.set_mode(SelectionMode::Face)
+                        }
+                        VirtualKeyCode::Key4 if should_do => {
+                            selection.set_mode(SelectionMode::Object)
+                        }
+                        VirtualKeyCode::T if should_do => gizmo.mode = GizmoMode::Translate,
+                        VirtualKeyCode::R if should_do => gizmo.mode = GizmoMode::Rotate,
+                        VirtualKeyCode::E if should_do => gizmo.mode = GizmoMode::Scale,
+                        VirtualKeyCode::X if should_do => gizmo.toggle_space(),
+                        VirtualKeyCode::P if should_do => grid.plane = grid.plane.next(),
+                        VirtualKeyCode::N if should_do => snapping.toggle(),
+                        VirtualKeyCode::G if should_do => snapping.target = snapping.target.next(),
+                        VirtualKeyCode::LBracket if should_do => snapping.scale_grid(0.5),
+                        VirtualKeyCode::RBracket if should_do => snapping.scale_grid(2.0),
+                        VirtualKeyCode::M if should_do => {
+                            material_index = (material_index + 1) % materials.len();
+                            cube.set_material(&ctx, materials[material_index].clone());
+                        }
+                        VirtualKeyCode::O if should_do => {
+                            let tone_mapping = &mut post_process.tone_mapping;
+                            tone_mapping.operator = tone_mapping.operator.next();
+                            log::info!("tone mapping: {:?}", tone_mapping.operator);
+                            tone_mapping.update(&ctx.queue);
+                        }
+                        VirtualKeyCode::Minus | VirtualKeyCode::Equals if should_do => {
+                            let tone_mapping = &mut post_process.tone_mapping;
+                            let step = if keycode == VirtualKeyCode::Minus {
+                                -0.5
+                            } else {
+                                0.5
+                            };
+                            tone_mapping.exposure += step;
+                            log::info!("exposure: {:+} EV", tone_mapping.exposure);
+                            tone_mapping.update(&ctx.queue);
+                        }
+                        VirtualKeyCode::Comma | VirtualKeyCode::Period if should_do => {
+                            let tone_mapping = &mut post_process.tone_mapping;
+                            let step = if keycode == VirtualKeyCode::Comma {
+                                -0.1
+                            } else {
+                                0.1
+                            };
+                            tone_mapping.gamma = (tone_mapping.gamma + step).max(0.1);
+                            log::info!("gamma: {:.1}", tone_mapping.gamma);
+                            tone_mapping.update(&ctx.queue);
+                        }
+                        VirtualKeyCode::Q if should_do => {
+                            // cycle through the MSAA sample counts
+                            let next = SAMPLE_COUNTS
+                                .iter()
+                                .position(|&count| count == ctx.sample_count)
+                                .map_or(0, |i| (i + 1) % SAMPLE_COUNTS.len());
+                            let sample_count = ctx.set_sample_count(SAMPLE_COUNTS[next]);
+                            skybox = Skybox::new(&ctx);
+                            base_line = BaseLine::new(&ctx);
+                            overlay_lines = LineBatch::new(&ctx);
+                            overlay_text = TextBatch::new(&ctx);
+                            grid.recreate_pipelines(&ctx);
+                            cube.recreate_pipelines(&ctx);
+                            instances.recreate_pipelines(&ctx, &cube);
+                            scan.recreate_render_bundles(&ctx, &cube);
+                            axis_widget.recreate_pipelines(&ctx);
+                            post_process.recreate_pipelines(&ctx);
+                            log::info!("MSAA: {}x", sample_count);
+                        }
+                        VirtualKeyCode::F1
+                        | VirtualKeyCode::F2
+                        | VirtualKeyCode::F3
+                        | VirtualKeyCode::F4
+                            if should_do =>
+                        {
+                            let settings = &mut post_process.settings;
+                            let (name, enabled) = match keycode {
+                                VirtualKeyCode::F1 => ("FXAA", &mut settings.fxaa),
+                                VirtualKeyCode::F2 => ("SSAO", &mut settings.ssao),
+                                VirtualKeyCode::F3 => ("bloom", &mut settings.bloom),
+                                _ => ("selection outline", &mut settings.outline),
+                            };
+                            *enabled = !*enabled;
+                            log::info!("{}: {}", name, if *enabled { "on" } else { "off" });
+                        }
+                        VirtualKeyCode::F5
+                        | VirtualKeyCode::F6
+                        | VirtualKeyCode::F7
+                        | VirtualKeyCode::F8
+                        | VirtualKeyCode::F9
+                        | VirtualKeyCode::F10
+                            if should_do =>
+                        {
+                            view.shading =
+                                ShadingMode::ALL[keycode as usize - VirtualKeyCode::F5 as usize];
+                            log::info!("{} shading: {:?}", view.name, view.shading);
+                        }
+                        VirtualKeyCode::Key5 if should_do => {
+                            view.camera.toggle_projection();
+                            log::info!("{} projection: {:?}", view.name, view.camera.projection());
+                        }
+                        VirtualKeyCode::V if should_do => layout.toggle_maximized(),
+                        VirtualKeyCode::F11 if should_do => hud.toggle(),
+                        VirtualKeyCode::F12 if should_do => debug_shapes = !debug_shapes,
+                        VirtualKeyCode::Tab if should_do => layout.focus_next(),
+                        VirtualKeyCode::C if should_do => {
+                            if instances.is_empty() {
+                                add_instance_field(&mut instances);
+                            } else {
+                                instances.clear();
+                            }
+                            log::info!("instances: {}", instances.len());
+                        }
+                        VirtualKeyCode::B if should_do => {
+                            for count in BENCHMARK_COUNTS {
+                                log::info!(
+                                    "{}",
+                                    benchmark_instancing(
+                                        &ctx,
+                                        &cube,
+                                        &view.camera,
+                                        view.size(),
+                                        count
+                                    )
+                                );
+                            }
+                        }
+                        VirtualKeyCode::Y if should_do => {
+                            // automatic, then each level from the full mesh down
+                            scan.forced_level = match scan.forced_level {
+                                None => Some(0),
+                                Some(level) if level + 1 < scan.levels().len() => Some(level + 1),
+                                Some(_) => None,
+                            };
+                            log::info!("forced level of detail: {:?}", scan.forced_level);
+                        }
+                        VirtualKeyCode::I if should_do => {
+                            let camera = &view.camera;
+                            let position = camera.position() + camera.direction() * 3.0;
+                            ctx.global.add_light(Light::point(
+                                position,
+                                [1.0, 1.0, 1.0],
+                                10.0,
+                                10.0,
+                            ));
+                        }
                         _ => {}
                     }
                 }
+                WindowEvent::ModifiersChanged(state) => modifiers = state,
+                WindowEvent::CursorMoved { position, .. } => {
+                    cursor = Point2::new(position.x as f32, position.y as f32);
+                    if layout.drag_splitter(cursor) {
+                        return;
+                    }
+                    ctx.window.set_cursor_icon(layout.cursor_icon(cursor));
+                    // everything below happens in the focused view
+                    let view = layout.focused();
+                    let cursor = view.to_local(cursor);
+                    if let Some(drag) = &mut selection_drag {
+                        drag.extend(cursor);
+                    }
+                    let screen = view.screen_space();
+                    if let Some((index, position)) =
+                        light_gizmo.drag_position(cursor, &screen, ctx.global.lights())
+                    {
+                        ctx.global.move_light(index, position);
+                        return;
+                    }
+                    match (&transform_session, gizmo.drag_frame()) {
+                        (Some(session), Some(frame)) => {
+                            if let Some(delta) = gizmo.drag_delta(cursor, &screen) {
+                                snap_point = snapping.snap_point(
+                                    cursor,
+                                    &screen,
+                                    &[cube.selection_target(CUBE_ID)],
+                                    session,
+                                );
+                                let constraint =
+                                    gizmo.drag_constraint().unwrap_or(Constraint::Free);
+                                let delta = snapping.snap(delta, &frame, constraint, snap_point);
+                                apply_transform(&ctx, &mut cube, session, delta.to_matrix(&frame));
+                            }
+                        }
+                        _ => {
+                            axis_widget.hover(cursor, view.size());
+                            light_gizmo.hover(cursor, &screen, ctx.global.lights());
+                            let frame = gizmo.frame(&selection, &[cube.selection_target(CUBE_ID)]);
+                            gizmo.hover(cursor, &screen, frame.as_ref());
+                        }
+                    }
+                }
+                WindowEvent::MouseInput {
+                    state,
+                    button: MouseButton::Left,
+                    ..
+                } => match state {
+                    // holding Alt while dragging draws a lasso instead of a box
+                    ElementState::Pressed => {
+                        if layout.begin_splitter_drag(cursor) {
+                            return;
+                        }
+                        layout.focus_at(cursor);
+                        let view = layout.focused();
+                        let cursor = view.to_local(cursor);
+                        if let Some(axis) = axis_widget.pick(cursor, view.size()) {
+                            layout.focused_mut().camera.snap_to_view(axis);
+                            return;
+                        }
+                        let screen = view.screen_space();
+                        if light_gizmo.begin_drag(cursor, &screen, ctx.global.lights()) {
+                            return;
+                        }
+                        let targets = [cube.selection_target(CUBE_ID)];
+                        let grabbed = gizmo
+                            .frame(&selection, &targets)
+                            .is_some_and(|frame| gizmo.begin_drag(cursor, &screen, frame));
+                        if grabbed {
+                            transform_session = Some(TransformSession::begin(&selection, &targets));
+                        } else {
+                            selection_drag = Some(SelectionDrag::start(cursor, modifiers.alt()));
+                        }
+                    }
+                    ElementState::Released => {
+                        if layout.end_splitter_drag() {
+                            return;
+                        }
+                        light_gizmo.end_drag();
+                        if transform_session.take().is_some() {
+                            gizmo.end_drag();
+                            snap_point = None;
+                        }
+                        if let Some(drag) = selection_drag.take() {
+                            let screen = layout.focused().screen_space();
+                            if let (true, Region::Point(point)) = (debug_shapes, drag.region()) {
+                                ctx.debug_draw.persist(PICK_RAY_LIFETIME, |debug| {
+                                    debug.ray(&screen.ray(point), PICK_RAY_LENGTH, PICK_RAY_COLOR);
+                                });
+                            }
+                            selection.select(
+                                &drag.region(),
+                                SelectOp::from_modifiers(modifiers),
+                                &screen,
+                                &[cube.selection_target(CUBE_ID)],
+                            );
+                        }
+                    }
+                },
                 WindowEvent::Resized(size)
                 | WindowEvent::ScaleFactorChanged {
                     new_inner_size: &mut size,
@@ -85,6 +445,7 @@ fn main() {
                     ctx.surface_config.width = size.width;
                     ctx.surface_config.height = size.height;
                     ctx.size = size;
+                    layout.resize(size);
 
                     ctx.recreate_surface();
                 }
@@ -94,10 +455,16 @@ fn main() {
             //     ctx.window.request_redraw();
             // }
             Event::RedrawRequested(_) => {
-                ctx.global.camera.update();
+                layout.update_cameras();
                 cube.update(&ctx.queue);
-
-                ctx.global.on_resize(&ctx.queue, ctx.size);
+                instances.upload(&ctx);
+                ctx.global.upload_lights(&ctx.queue);
+                if debug_shapes {
+                    queue_debug_shapes(&ctx, &cube, &scan);
+                }
+                if selection.take_changed() {
+                    cube.set_selected(&ctx.queue, selection.is_object_selected(CUBE_ID));
+                }
 
                 let frame = match ctx.surface.get_current_texture() {
                     Ok(frame) => frame,
@@ -109,48 +476,203 @@ fn main() {
                             .expect("Failed to acquire next surface texture!")
                     }
                 };
-                if let Some(average_frametime) = loop_clock.tick() {
-                    log::info!("average_frametime: {average_frametime}");
-                }
+                // logged along with the culling of the views in this frame
+                let average_frametime = loop_clock.tick();
+                let mut cull_stats = CullStats::default();
 
-                let view = frame
+                let window_view = frame
                     .texture
                     .create_view(&wgpu::TextureViewDescriptor::default());
-
                 let mut encoder = ctx
                     .device
                     .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-                {
-                    let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                        label: None,
-                        color_attachments: &[wgpu::RenderPassColorAttachment {
-                            view: &view,
-                            resolve_target: None,
-                            ops: wgpu::Operations {
-                                load: wgpu::LoadOp::Clear(wgpu::Color {
-                                    r: 0.1,
-                                    g: 0.2,
-                                    b: 0.3,
-                                    a: 1.0,
-                                }),
-                                store: true,
-                            },
-                        }],
-                        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                            view: &ctx.depth_texture_view,
-                            depth_ops: Some(Operations {
-                                load: wgpu::LoadOp::Clear(1.0),
-                                store: true,
-                            }),
-                            stencil_ops: None,
-                        }),
+                layout.clear(&mut encoder, &window_view);
+                ctx.queue.submit(Some(encoder.finish()));
+
+                // the uniforms are shared by the views, so each is submitted before the next
+                // one writes its own
+                for index in layout.render_order() {
+                    let view = layout.view(index);
+                    let size = view.size();
+                    ctx.global.update_camera(&ctx.queue, &view.camera, size);
+                    ctx.global.update_shadows(&ctx.queue, &view.camera, size);
+                    axis_widget.update(&ctx.queue, &view.camera);
+                    let screen = view.screen_space();
+                    skybox.update(&ctx.queue, &screen);
+                    grid.update(&ctx.queue, &screen);
+                    post_process.update(&ctx.queue, &screen);
+                    layout.update(&ctx.queue, index);
+                    overlay_lines.clear();
+                    selection.draw_highlight(
+                        &mut overlay_lines,
+                        &screen,
+                        &[cube.selection_target(CUBE_ID)],
+                    );
+                    light_gizmo.draw(&mut overlay_lines, &screen, ctx.global.lights());
+                    if let Some(frame) = gizmo.frame(&selection, &[cube.selection_target(CUBE_ID)])
+                    {
+                        gizmo.draw(&mut overlay_lines, &screen, &frame);
+                    }
+                    if let Some(point) = snap_point {
+                        snapping::draw_snap_point(&mut overlay_lines, &screen, point);
+                    }
+                    // the drag is in the coordinates of the focused view
+                    if let (Some(drag), true) = (&selection_drag, index == layout.focused_index()) {
+                        drag.draw(&mut overlay_lines, &screen);
+                    }
+                    overlay_text.clear();
+                    ctx.debug_draw
+                        .draw(&mut overlay_lines, &mut overlay_text, &screen);
+                    overlay_lines.upload(&ctx);
+                    let frustum = view.camera.frustum(size.width as f32 / size.height as f32);
+                    let mut view_stats = FrameStats {
+                        average_frametime: loop_clock.average_frametime(),
+                        ..FrameStats::default()
+                    };
+                    let cube_visible = frustum.intersects(&cube.world_bounds());
+                    view_stats.cull_stats.record(cube_visible);
+                    let eye = view.camera.position();
+                    view_stats.cull_stats += instances.cull(&ctx, &frustum, eye);
+                    let scan_visible = frustum.intersects(&scan.world_bounds());
+                    view_stats.cull_stats.record(scan_visible);
+                    scan.select_lod(&ctx, &view.camera, size.height as f32);
+                    view_stats.triangles = instances.triangle_count()
+                        + if cube_visible {
+                            cube.triangle_count()
+                        } else {
+                            0
+                        }
+                        + if scan_visible {
+                            scan.triangle_count()
+                        } else {
+                            0
+                        };
+                    cull_stats += view_stats.cull_stats;
+                    if index == layout.focused_index() {
+                        hud.draw(&mut overlay_text, view, &selection, &view_stats);
+                    }
+                    overlay_text.upload(&ctx, size);
+
+                    let mut encoder = ctx
+                        .device
+                        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+                    let mut graph = RenderGraph::new(size);
+                    let window = graph.import_texture(&window_view);
+                    let targets = FrameTargets::new(&mut graph, &ctx);
+                    // the directional cascades and the six-sided maps of point and spot lights
+                    let shadow_maps = [
+                        graph.import_texture(&ctx.global.shadows.cascade_view),
+                        graph.import_texture(&ctx.global.shadows.point_view),
+                    ];
+                    let casters: [&dyn ShadowCaster; 3] = [&cube, &instances, &scan];
+                    graph.add_pass("shadows", &[], &shadow_maps, |encoder, _| {
+                        ctx.global.shadows.render(encoder, &casters);
                     });
-                    rpass.execute_bundles(
-                        [&cube.render_bundle, &base_line.render_bundle].into_iter(),
+                    let scene_writes = [targets.hdr.resources(), vec![targets.depth]].concat();
+                    graph.add_pass(
+                        "scene",
+                        &shadow_maps,
+                        &scene_writes,
+                        |encoder, resources| {
+                            // the lit scene, in HDR
+                            let mut rpass =
+                                encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                                    label: None,
+                                    // the skybox covers everything
+                                    color_attachments: &[resources.color_attachment(
+                                        targets.hdr,
+                                        wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                                    )],
+                                    depth_stencil_attachment: Some(
+                                        wgpu::RenderPassDepthStencilAttachment {
+                                            view: resources.view(targets.depth),
+                                            depth_ops: Some(Operations {
+                                                load: wgpu::LoadOp::Clear(1.0),
+                                                store: true,
+                                            }),
+                                            stencil_ops: None,
+                                        },
+                                    ),
+                                });
+                            let mut draw_list = DrawList::new();
+                            draw_list.push_opaque(&skybox.render_bundle);
+                            draw_list.push_opaque(instances.render_bundle(view.shading));
+                            for (distance, bundle) in instances.blended_bundles(view.shading) {
+                                draw_list.push_transparent(distance, bundle);
+                            }
+                            // each mesh blends or not after its own material
+                            let mut push = |material: &Material, bounds: Bounds, bundle| {
+                                if material.blends(view.shading) {
+                                    let distance = (bounds.sphere.center - eye).magnitude();
+                                    draw_list.push_transparent(distance, bundle);
+                                } else {
+                                    draw_list.push_opaque(bundle);
+                                }
+                            };
+                            if cube_visible {
+                                let bundle = cube.render_bundle(view.shading);
+                                push(&cube.material, cube.world_bounds(), bundle);
+                            }
+                            if scan_visible {
+                                let bundle = scan.render_bundle(view.shading);
+                                push(&scan.material, scan.world_bounds(), bundle);
+                            }
+                            rpass.execute_bundles(draw_list.bundles());
+                        },
                     );
+                    let outlined: [&dyn OutlineCaster; 1] = [&cube];
+                    post_process.add_passes(&mut graph, &ctx, &targets, &outlined);
+                    let overlay_accesses =
+                        [targets.frame.resources(), vec![targets.depth]].concat();
+                    graph.add_pass(
+                        "overlays",
+                        &overlay_accesses,
+                        &overlay_accesses,
+                        |encoder, resources| {
+                            // editor overlays keep their exact colors, drawn after tone mapping
+                            let mut rpass =
+                                encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                                    label: None,
+                                    color_attachments: &[resources
+                                        .color_attachment(targets.frame, wgpu::LoadOp::Load)],
+                                    depth_stencil_attachment: Some(
+                                        wgpu::RenderPassDepthStencilAttachment {
+                                            view: resources.view(targets.depth),
+                                            depth_ops: Some(Operations {
+                                                load: wgpu::LoadOp::Load,
+                                                store: true,
+                                            }),
+                                            stencil_ops: None,
+                                        },
+                                    ),
+                                });
+                            rpass.execute_bundles(
+                                [
+                                    &grid.render_bundle,
+                                    &base_line.render_bundle,
+                                    &overlay_lines.render_bundle,
+                                    &overlay_text.render_bundle,
+                                ]
+                                .into_iter(),
+                            );
+                            axis_widget.draw(&mut rpass, size);
+                        },
+                    );
+                    layout.add_composite_pass(
+                        &mut graph,
+                        &ctx,
+                        index,
+                        targets.frame.resolved,
+                        window,
+                    );
+                    graph.execute(&ctx, &mut texture_pool, &mut encoder);
+                    ctx.queue.submit(Some(encoder.finish()));
+                }
+                texture_pool.end_frame();
+                ctx.debug_draw.end_frame();
+                if let Some(average_frametime) = average_frametime {
+                    log::info!("average_frametime: {average_frametime}, {cull_stats}");
                 }
-
-                ctx.queue.submit(Some(encoder.finish()));
 
                 frame.present();
             }
@@ -165,3 +687,139 @@ fn main() {
         }
     });
 }
+
+fn apply_transform(
+    ctx: &Context,
+    cube: &mut Cube,
+    session: &TransformSession,
+    delta: Matrix4<f32>,
+) {
+    if let Some(model_matrix) = session.object_matrix(CUBE_ID, delta) {
+        cube.model_matrix = model_matrix;
+    }
+    let moved = session.vertex_positions(CUBE_ID, delta);
+    if !moved.is_empty() {
+        cube.move_vertices(&ctx.queue, &moved);
+    }
+}
+
+fn nudge_selection(
+    ctx: &Context,
+    cube: &mut Cube,
+    selection: &Selection,
+    gizmo: &Gizmo,
+    snapping: &Snapping,
+    nudge: Nudge,
+) {
+    let targets = [cube.selection_target(CUBE_ID)];
+    let frame = match gizmo.frame(selection, &targets) {
+        Some(frame) => frame,
+        None => return,
+    };
+    let session = TransformSession::begin(selection, &targets);
+    let delta = snapping.nudge(gizmo.mode, &frame, nudge);
+    let constraint = match delta {
+        GizmoDelta::Translate(offset) => Constraint::Axis(offset.normalize()),
+        _ => Constraint::Free,
+    };
+    let delta = snapping.snap(delta, &frame, constraint, None);
+    apply_transform(ctx, cube, &session, delta.to_matrix(&frame));
+}
+
+/// A flat field of small cubes around the one being edited, which is left uncovered.
+fn add_instance_field(instances: &mut CubeInstances) {
+    let half = INSTANCE_FIELD_SIDE / 2;
+    for x in -half..half {
+        for z in -half..half {
+            if (x, z) == (0, 0) {
+                continue;
+            }
+            let position = Vector3::new(x as f32, 0.0, z as f32) * INSTANCE_FIELD_SPACING;
+            instances.add(Matrix4::from_translation(position) * Matrix4::from_scale(0.5));
+        }
+    }
+}
+
+/// Queues the bounds of the meshes, the normals of the cube's faces and the directions of
+/// the lights for this frame.
+fn queue_debug_shapes(ctx: &Context, cube: &Cube, scan: &LodMesh) {
+    let debug = &ctx.debug_draw;
+    let cube_bounds = cube.world_bounds();
+    debug.bounds(&cube_bounds, [1.0, 0.9, 0.2, 1.0]);
+    debug.text(cube_bounds.aabb.max, "cube", [1.0, 0.9, 0.2, 1.0]);
+    let scan_bounds = scan.world_bounds();
+    debug.bounds(&scan_bounds, [0.2, 0.9, 1.0, 1.0]);
+    debug.text(scan_bounds.aabb.max, "scan", [0.2, 0.9, 1.0, 1.0]);
+
+    let topology = &cube.topology;
+    let world = |vertex: usize| {
+        cube.model_matrix
+            .transform_point(topology.positions[vertex])
+    };
+    for face in &topology.faces {
+        let sum = face
+            .vertices
+            .iter()
+            .fold(Vector3::new(0.0, 0.0, 0.0), |sum, &v| {
+                sum + world(v).to_vec()
+            });
+        let center = Point3::from_vec(sum / face.vertices.len() as f32);
+        // from the placed triangle, so scaling is accounted for
+        let [a, b, c] = face.triangles[0].map(world);
+        let normal = (b - a).cross(c - a).normalize();
+        debug.arrow(center, center + normal * 0.5, [0.3, 1.0, 0.3, 1.0]);
+    }
+
+    for light in ctx.global.lights() {
+        if light.kind != LightKind::Point {
+            let color = [light.color[0], light.color[1], light.color[2], 1.0];
+            debug.arrow(light.position, light.position + light.direction, color);
+        }
+    }
+}
+
+/// White with a grid of round holes cut out by the alpha channel.
+fn perforated_texture(ctx: &Context) -> Texture {
+    let cell = PERFORATION_SIZE / PERFORATION_HOLES;
+    let radius = cell as f32 * 0.3;
+    let texels: Vec<u8> = (0..PERFORATION_SIZE * PERFORATION_SIZE)
+        .flat_map(|i| {
+            let offset = |c: u32| (c % cell) as f32 + 0.5 - cell as f32 / 2.0;
+            let (x, y) = (offset(i % PERFORATION_SIZE), offset(i / PERFORATION_SIZE));
+            let alpha = if x.hypot(y) < radius { 0 } else { 255 };
+            [255, 255, 255, alpha]
+        })
+        .collect();
+    Texture::from_rgba8(ctx, PERFORATION_SIZE, PERFORATION_SIZE, &texels, true)
+}
+
+/// A lumpy UV sphere, dense enough to be worth simplifying, with a UV seam where the
+/// longitudes wrap around and at the poles.
+fn scan_sphere() -> (Vec<[f32; 3]>, Vec<[f32; 2]>, Vec<u32>) {
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    for ring in 0..=SCAN_RINGS {
+        for segment in 0..=SCAN_SEGMENTS {
+            let u = segment as f32 / SCAN_SEGMENTS as f32;
+            let v = ring as f32 / SCAN_RINGS as f32;
+            let azimuth = u * std::f32::consts::TAU;
+            let polar = v * std::f32::consts::PI;
+            let radius = 1.0 + 0.05 * (azimuth * 7.0).sin() * (polar * 5.0).sin();
+            positions.push([
+                radius * polar.sin() * azimuth.cos(),
+                radius * polar.sin() * azimuth.sin(),
+                radius * polar.cos(),
+            ]);
+            uvs.push([u, v]);
+        }
+    }
+    let mut indices = Vec::new();
+    for ring in 0..SCAN_RINGS {
+        for segment in 0..SCAN_SEGMENTS {
+            let a = ring * (SCAN_SEGMENTS + 1) + segment;
+            let b = a + SCAN_SEGMENTS + 1;
+            indices.extend([a, b, a + 1, a + 1, b, b + 1]);
+        }
+    }
+    (positions, uvs, indices)
+}
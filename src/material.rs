@@ -0,0 +1,254 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::shading::ShadingMode;
+use crate::texture::{SamplerOptions, Texture};
+use crate::Context;
+
+use std::mem;
+use std::rc::Rc;
+
+/// Declarations of the material bind group (group 2) and its sampling helpers, prepended to
+/// shaders of objects with a `Material`.
+pub const MATERIAL_WGSL: &str = include_str!("material.wgsl");
+
+/// How the alpha of the base color is used, as in glTF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AlphaMode {
+    /// Ignored.
+    #[default]
+    Opaque,
+    /// Fragments below `Material::alpha_cutoff` are discarded, the rest are opaque.
+    Mask,
+    /// Blended over what is behind, sorted back to front and without writing depth.
+    Blend,
+}
+
+/// Metallic-roughness material following the glTF 2.0 `pbrMetallicRoughness` model. Factors
+/// multiply the corresponding texture when one is present.
+#[derive(Clone)]
+pub struct Material {
+    pub name: String,
+    /// Linear RGBA.
+    pub base_color_factor: [f32; 4],
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    /// Linear RGB.
+    pub emissive_factor: [f32; 3],
+    /// sRGB encoded.
+    pub base_color_texture: Option<Rc<Texture>>,
+    /// Roughness in the green channel and metallic in the blue channel, linear.
+    pub metallic_roughness_texture: Option<Rc<Texture>>,
+    /// sRGB encoded.
+    pub emissive_texture: Option<Rc<Texture>>,
+    /// Tangent-space normals, linear and OpenGL (Y up) oriented.
+    pub normal_texture: Option<Rc<Texture>>,
+    /// Scales the X and Y components of the sampled normals.
+    pub normal_scale: f32,
+    /// Ambient occlusion in the red channel, linear.
+    pub occlusion_texture: Option<Rc<Texture>>,
+    /// How much of the occlusion map is applied, from 0 (none) to 1 (all).
+    pub occlusion_strength: f32,
+    pub alpha_mode: AlphaMode,
+    /// Alpha below which `AlphaMode::Mask` discards.
+    pub alpha_cutoff: f32,
+    pub sampler: SamplerOptions,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct MaterialUniforms {
+    base_color_factor: [f32; 4],
+    emissive_factor: [f32; 3],
+    metallic_factor: f32,
+    roughness_factor: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+    // zero unless masked, so nothing is discarded
+    alpha_cutoff: f32,
+}
+
+/// GPU side of a `Material`, bound as group 2.
+pub struct MaterialBinding {
+    pub uniform_buffer: wgpu::Buffer,
+    pub sampler: wgpu::Sampler,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Default for Material {
+    /// The glTF defaults: white, fully metallic and fully rough.
+    fn default() -> Self {
+        Material {
+            name: String::new(),
+            base_color_factor: [1.0, 1.0, 1.0, 1.0],
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            emissive_factor: [0.0, 0.0, 0.0],
+            base_color_texture: None,
+            metallic_roughness_texture: None,
+            emissive_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            sampler: SamplerOptions::default(),
+        }
+    }
+}
+
+impl Material {
+    /// Non-metallic material of a single color.
+    pub fn dielectric(name: &str, base_color: [f32; 4], roughness: f32) -> Self {
+        Material {
+            name: name.to_owned(),
+            base_color_factor: base_color,
+            metallic_factor: 0.0,
+            roughness_factor: roughness,
+            ..Material::default()
+        }
+    }
+
+    /// Whether meshes with this material are blended when drawn in `mode`, and have to be
+    /// sorted.
+    pub fn blends(&self, mode: ShadingMode) -> bool {
+        self.alpha_mode == AlphaMode::Blend && mode.uses_materials()
+    }
+
+    pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("material"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            mem::size_of::<MaterialUniforms>() as u64
+                        ),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                texture_entry(2),
+                texture_entry(3),
+                texture_entry(4),
+                texture_entry(5),
+                texture_entry(6),
+            ],
+        })
+    }
+
+    /// Uploads the factors and binds the textures. White stands in for missing color maps and
+    /// a flat normal for a missing normal map.
+    pub fn create_binding(&self, ctx: &Context) -> MaterialBinding {
+        let uniform_buffer = ctx
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::bytes_of(&self.to_uniforms()),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
+        let sampler = self.sampler.create_sampler(&ctx.device);
+
+        let white = ctx.textures.solid(ctx, [255, 255, 255, 255]);
+        let flat = ctx.textures.solid(ctx, [128, 128, 255, 255]);
+        let view = |texture| view_or(texture, &white);
+
+        let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&self.name),
+            layout: &ctx.material_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(view(&self.base_color_texture)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(view(
+                        &self.metallic_roughness_texture,
+                    )),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(view(&self.emissive_texture)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(view_or(
+                        &self.normal_texture,
+                        &flat,
+                    )),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::TextureView(view(&self.occlusion_texture)),
+                },
+            ],
+        });
+
+        MaterialBinding {
+            uniform_buffer,
+            sampler,
+            bind_group,
+        }
+    }
+
+    /// Re-uploads the factors of a material whose textures did not change.
+    pub fn write_factors(&self, queue: &wgpu::Queue, binding: &MaterialBinding) {
+        queue.write_buffer(
+            &binding.uniform_buffer,
+            0,
+            bytemuck::bytes_of(&self.to_uniforms()),
+        );
+    }
+
+    fn to_uniforms(&self) -> MaterialUniforms {
+        MaterialUniforms {
+            base_color_factor: self.base_color_factor,
+            emissive_factor: self.emissive_factor,
+            metallic_factor: self.metallic_factor,
+            roughness_factor: self.roughness_factor,
+            normal_scale: self.normal_scale,
+            occlusion_strength: self.occlusion_strength,
+            alpha_cutoff: match self.alpha_mode {
+                AlphaMode::Mask => self.alpha_cutoff,
+                AlphaMode::Opaque | AlphaMode::Blend => 0.0,
+            },
+        }
+    }
+}
+
+fn view_or<'a>(texture: &'a Option<Rc<Texture>>, fallback: &'a Texture) -> &'a wgpu::TextureView {
+    match texture {
+        Some(texture) => &texture.view,
+        None => &fallback.view,
+    }
+}
@@ -0,0 +1,64 @@
+struct MaterialUniforms {
+    base_color_factor: vec4<f32>;
+    emissive_factor: vec3<f32>;
+    metallic_factor: f32;
+    roughness_factor: f32;
+    normal_scale: f32;
+    occlusion_strength: f32;
+    // zero unless the material is alpha masked
+    alpha_cutoff: f32;
+};
+
+[[group(2), binding(0)]]
+var<uniform> u_material: MaterialUniforms;
+[[group(2), binding(1)]]
+var s_material: sampler;
+[[group(2), binding(2)]]
+var t_base_color: texture_2d<f32>;
+// glTF layout: roughness in green, metallic in blue
+[[group(2), binding(3)]]
+var t_metallic_roughness: texture_2d<f32>;
+[[group(2), binding(4)]]
+var t_emissive: texture_2d<f32>;
+[[group(2), binding(5)]]
+var t_normal: texture_2d<f32>;
+// occlusion in red
+[[group(2), binding(6)]]
+var t_occlusion: texture_2d<f32>;
+
+// Whether a fragment of a masked material is cut out.
+fn material_discards(alpha: f32) -> bool {
+    return alpha < u_material.alpha_cutoff;
+}
+
+fn material_base_color(uv: vec2<f32>) -> vec4<f32> {
+    return u_material.base_color_factor * textureSample(t_base_color, s_material, uv);
+}
+
+// metallic in x, roughness in y
+fn material_metallic_roughness(uv: vec2<f32>) -> vec2<f32> {
+    let texel = textureSample(t_metallic_roughness, s_material, uv);
+    let roughness = clamp(u_material.roughness_factor * texel.g, 0.03, 1.0);
+    return vec2<f32>(u_material.metallic_factor * texel.b, roughness);
+}
+
+fn material_emissive(uv: vec2<f32>) -> vec3<f32> {
+    return u_material.emissive_factor * textureSample(t_emissive, s_material, uv).rgb;
+}
+
+// Perturbs the interpolated normal by the tangent-space normal map. `tangent.w` is the
+// handedness of the bitangent.
+fn material_normal(uv: vec2<f32>, normal: vec3<f32>, tangent: vec4<f32>) -> vec3<f32> {
+    let texel = textureSample(t_normal, s_material, uv).xyz * 2.0 - vec3<f32>(1.0);
+    let n = normalize(normal);
+    // re-orthogonalize, interpolation skews the frame
+    let t = normalize(tangent.xyz - n * dot(n, tangent.xyz));
+    let b = cross(n, t) * tangent.w;
+    let tbn = mat3x3<f32>(t, b, n);
+    return normalize(tbn * vec3<f32>(texel.xy * u_material.normal_scale, texel.z));
+}
+
+fn material_occlusion(uv: vec2<f32>) -> f32 {
+    let texel = textureSample(t_occlusion, s_material, uv).r;
+    return 1.0 + u_material.occlusion_strength * (texel - 1.0);
+}
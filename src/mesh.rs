@@ -0,0 +1,348 @@
+use std::collections::{BTreeSet, HashMap};
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+
+mod lod;
+mod simplify;
+pub use lod::{LodChain, LodLevel, MAX_PIXEL_ERROR};
+pub use simplify::{simplify, Simplified, SimplifyTarget};
+
+const WELD_PRECISION: f32 = 1e4;
+const COPLANAR_THRESHOLD: f32 = 1.0 - 1e-4;
+
+/// Editable view of a triangle mesh: coincident vertices are welded and coplanar
+/// neighbouring triangles are merged into polygonal faces.
+#[derive(Debug, Clone, Default)]
+pub struct Topology {
+    pub positions: Vec<Point3<f32>>,
+    /// Welded position index of every vertex the topology was built from.
+    pub vertex_map: Vec<usize>,
+    pub edges: Vec<[usize; 2]>,
+    pub faces: Vec<Face>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Face {
+    pub vertices: Vec<usize>,
+    pub triangles: Vec<[usize; 3]>,
+    pub normal: Vector3<f32>,
+}
+
+impl Topology {
+    pub fn from_triangles(positions: &[[f32; 3]], indices: &[u16]) -> Self {
+        let (vertex_map, unique_positions) = weld(positions);
+
+        let triangles: Vec<[usize; 3]> = indices
+            .chunks_exact(3)
+            .map(|t| {
+                [
+                    vertex_map[t[0] as usize],
+                    vertex_map[t[1] as usize],
+                    vertex_map[t[2] as usize],
+                ]
+            })
+            .collect();
+        let normals: Vec<Vector3<f32>> = triangles
+            .iter()
+            .map(|t| {
+                let [a, b, c] = t.map(|i| unique_positions[i]);
+                (b - a).cross(c - a).normalize()
+            })
+            .collect();
+
+        // group coplanar triangles sharing an edge
+        let mut edge_triangles: HashMap<[usize; 2], Vec<usize>> = HashMap::new();
+        for (i, t) in triangles.iter().enumerate() {
+            for edge in triangle_edges(t) {
+                edge_triangles.entry(sorted(edge)).or_default().push(i);
+            }
+        }
+        let mut groups = UnionFind::new(triangles.len());
+        for adjacent in edge_triangles.values() {
+            for (n, &a) in adjacent.iter().enumerate() {
+                for &b in &adjacent[n + 1..] {
+                    if normals[a].dot(normals[b]) > COPLANAR_THRESHOLD {
+                        groups.union(a, b);
+                    }
+                }
+            }
+        }
+
+        let mut face_of_group = HashMap::new();
+        let mut faces: Vec<Face> = Vec::new();
+        for (i, t) in triangles.iter().enumerate() {
+            let face_index = *face_of_group.entry(groups.find(i)).or_insert_with(|| {
+                faces.push(Face {
+                    vertices: Vec::new(),
+                    triangles: Vec::new(),
+                    normal: normals[i],
+                });
+                faces.len() - 1
+            });
+            faces[face_index].triangles.push(*t);
+        }
+
+        // edges inside a face (e.g. quad diagonals) are not part of the editable topology
+        let mut edges = BTreeSet::new();
+        for face in &mut faces {
+            let mut counts: HashMap<[usize; 2], usize> = HashMap::new();
+            for t in &face.triangles {
+                for edge in triangle_edges(t) {
+                    *counts.entry(sorted(edge)).or_default() += 1;
+                }
+            }
+            let boundary: Vec<[usize; 2]> = counts
+                .into_iter()
+                .filter(|(_, count)| *count == 1)
+                .map(|(edge, _)| edge)
+                .collect();
+            let vertices: BTreeSet<usize> = boundary.iter().flatten().copied().collect();
+            face.vertices = vertices.into_iter().collect();
+            edges.extend(boundary);
+        }
+
+        Topology {
+            positions: unique_positions,
+            vertex_map,
+            edges: edges.into_iter().collect(),
+            faces,
+        }
+    }
+
+    pub fn recompute_normals(&mut self) {
+        for face in &mut self.faces {
+            if let Some([a, b, c]) = face.triangles.first().map(|t| t.map(|i| self.positions[i])) {
+                face.normal = (b - a).cross(c - a).normalize();
+            }
+        }
+    }
+
+    pub fn face_centroid(&self, face: usize) -> Point3<f32> {
+        Point3::centroid(
+            &self.faces[face]
+                .vertices
+                .iter()
+                .map(|&v| self.positions[v])
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    pub fn face_edges(&self, face: usize) -> impl Iterator<Item = &[usize; 2]> {
+        let vertices = &self.faces[face].vertices;
+        self.edges
+            .iter()
+            .filter(move |[a, b]| vertices.contains(a) && vertices.contains(b))
+    }
+
+    pub fn triangles(&self) -> impl Iterator<Item = [Point3<f32>; 3]> + '_ {
+        self.faces
+            .iter()
+            .flat_map(|face| face.triangles.iter())
+            .map(|t| t.map(|i| self.positions[i]))
+    }
+}
+
+/// Merges coincident positions, returning the welded index of every position and the unique
+/// positions.
+pub fn weld(positions: &[[f32; 3]]) -> (Vec<usize>, Vec<Point3<f32>>) {
+    let mut welded = HashMap::new();
+    let mut unique_positions = Vec::new();
+    let vertex_map = positions
+        .iter()
+        .map(|&p| {
+            let key = p.map(|v| (v * WELD_PRECISION).round() as i64);
+            *welded.entry(key).or_insert_with(|| {
+                unique_positions.push(Point3::from(p));
+                unique_positions.len() - 1
+            })
+        })
+        .collect();
+    (vertex_map, unique_positions)
+}
+
+/// Per-vertex normals averaged over the surrounding triangles, weighted by their area. Vertices
+/// at the same position get the same normal, so seams of the other attributes stay smooth.
+pub fn smooth_normals<I: Copy + Into<u32>>(positions: &[[f32; 3]], indices: &[I]) -> Vec<[f32; 3]> {
+    let (vertex_map, unique_positions) = weld(positions);
+    let mut normals = vec![Vector3::new(0.0, 0.0, 0.0); unique_positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| vertex_map[triangle[i].into() as usize]);
+        let [pa, pb, pc] = [a, b, c].map(|i| unique_positions[i]);
+        // the cross product is twice the area
+        let normal = (pb - pa).cross(pc - pa);
+        for i in [a, b, c] {
+            normals[i] += normal;
+        }
+    }
+    vertex_map
+        .iter()
+        .map(|&i| {
+            let normal = normals[i];
+            if normal.magnitude2() > 0.0 {
+                normal.normalize().into()
+            } else {
+                [0.0, 0.0, 1.0]
+            }
+        })
+        .collect()
+}
+
+/// Per-vertex tangents for tangent-space normal mapping, in the spirit of MikkTSpace: the
+/// UV-space tangent and bitangent of each triangle are accumulated at its corners weighted by
+/// the corner angle, then orthogonalized against the vertex normal. `w` holds the handedness
+/// of the bitangent, `cross(normal, tangent) * w`.
+pub fn generate_tangents<I: Copy + Into<u32>>(
+    positions: &[[f32; 3]],
+    normals: &[[f32; 3]],
+    uvs: &[[f32; 2]],
+    indices: &[I],
+) -> Vec<[f32; 4]> {
+    let mut tangents = vec![Vector3::new(0.0, 0.0, 0.0); positions.len()];
+    let mut bitangents = tangents.clone();
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| triangle[i].into() as usize);
+        let [pa, pb, pc] = [a, b, c].map(|i| Point3::from(positions[i]));
+        let [ua, ub, uc] = [a, b, c].map(|i| uvs[i]);
+        let (e1, e2) = (pb - pa, pc - pa);
+        let (du1, dv1) = (ub[0] - ua[0], ub[1] - ua[1]);
+        let (du2, dv2) = (uc[0] - ua[0], uc[1] - ua[1]);
+        let det = du1 * dv2 - du2 * dv1;
+        if det.abs() < f32::EPSILON {
+            // degenerate UVs carry no orientation
+            continue;
+        }
+        let tangent = (e1 * dv2 - e2 * dv1) / det;
+        let bitangent = (e2 * du1 - e1 * du2) / det;
+        for (corner, prev, next) in [(a, pc, pb), (b, pa, pc), (c, pb, pa)] {
+            let p = Point3::from(positions[corner]);
+            let angle = (prev - p).angle(next - p).0;
+            if angle.is_finite() {
+                tangents[corner] += tangent * angle;
+                bitangents[corner] += bitangent * angle;
+            }
+        }
+    }
+
+    tangents
+        .iter()
+        .zip(&bitangents)
+        .zip(normals)
+        .map(|((&tangent, &bitangent), &normal)| {
+            let normal = Vector3::from(normal);
+            let mut t = tangent - normal * normal.dot(tangent);
+            if t.magnitude2() < f32::EPSILON {
+                // no UV gradient: any direction perpendicular to the normal will do
+                let axis = if normal.x.abs() < 0.9 {
+                    Vector3::unit_x()
+                } else {
+                    Vector3::unit_y()
+                };
+                t = axis - normal * normal.dot(axis);
+            }
+            let t = t.normalize();
+            let w = if normal.cross(t).dot(bitangent) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            [t.x, t.y, t.z, w]
+        })
+        .collect()
+}
+
+fn triangle_edges(t: &[usize; 3]) -> [[usize; 2]; 3] {
+    [[t[0], t[1]], [t[1], t[2]], [t[2], t[0]]]
+}
+
+fn sorted([a, b]: [usize; 2]) -> [usize; 2] {
+    [a.min(b), a.max(b)]
+}
+
+struct UnionFind {
+    parents: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(len: usize) -> Self {
+        UnionFind {
+            parents: (0..len).collect(),
+        }
+    }
+
+    fn find(&mut self, i: usize) -> usize {
+        let parent = self.parents[i];
+        if parent == i {
+            return i;
+        }
+        let root = self.find(parent);
+        self.parents[i] = root;
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parents[a] = b;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The tangent and the bitangent the shader derives, `cross(normal, tangent) * w`, of
+    /// every vertex of a unit quad facing +z with the UVs `uv` gives each corner.
+    fn quad_frames(uv: impl Fn([f32; 2]) -> [f32; 2]) -> Vec<(Vector3<f32>, Vector3<f32>, f32)> {
+        let corners = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        let positions = corners.map(|[x, y]| [x, y, 0.0]);
+        let uvs = corners.map(uv);
+        let indices: [u16; 6] = [0, 1, 2, 0, 2, 3];
+        let normal = Vector3::unit_z();
+        generate_tangents(&positions, &[normal.into(); 4], &uvs, &indices)
+            .into_iter()
+            .map(|[x, y, z, w]| {
+                let tangent = Vector3::new(x, y, z);
+                (tangent, normal.cross(tangent) * w, w)
+            })
+            .collect()
+    }
+
+    fn assert_frames(
+        frames: &[(Vector3<f32>, Vector3<f32>, f32)],
+        tangent: Vector3<f32>,
+        bitangent: Vector3<f32>,
+        w: f32,
+    ) {
+        for &frame in frames {
+            assert!(
+                (frame.0 - tangent).magnitude() < 1e-5,
+                "tangent {:?}",
+                frame.0
+            );
+            assert!(
+                (frame.1 - bitangent).magnitude() < 1e-5,
+                "bitangent {:?}",
+                frame.1
+            );
+            assert_eq!(frame.2, w);
+        }
+    }
+
+    #[test]
+    fn tangents_follow_u_and_bitangents_follow_v() {
+        let frames = quad_frames(|[x, y]| [x, y]);
+        assert_frames(&frames, Vector3::unit_x(), Vector3::unit_y(), 1.0);
+    }
+
+    #[test]
+    fn mirrored_uvs_flip_the_handedness() {
+        let frames = quad_frames(|[x, y]| [1.0 - x, y]);
+        assert_frames(&frames, -Vector3::unit_x(), Vector3::unit_y(), -1.0);
+    }
+
+    #[test]
+    fn v_growing_downwards_flips_the_bitangent() {
+        // images are stored top row first, so V commonly grows against the surface's up
+        let frames = quad_frames(|[x, y]| [x, 1.0 - y]);
+        assert_frames(&frames, Vector3::unit_x(), -Vector3::unit_y(), -1.0);
+    }
+}
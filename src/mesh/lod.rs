@@ -0,0 +1,80 @@
+use cgmath::Point3;
+
+use super::{simplify, SimplifyTarget};
+use crate::bounds::BoundingSphere;
+
+/// A level is dropped, ending the chain, if it keeps more than this share of the triangles of
+/// the one before.
+const MAX_KEPT_RATIO: f32 = 0.9;
+/// How far, in pixels, a level may move the surface on screen to be drawn.
+pub const MAX_PIXEL_ERROR: f32 = 1.0;
+
+/// The triangles of one level of detail, indexing the vertices of the full mesh.
+#[derive(Debug, Clone)]
+pub struct LodLevel {
+    pub indices: Vec<u32>,
+    /// How far the surface moved from the full mesh, in its units.
+    pub error: f32,
+}
+
+impl LodLevel {
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+}
+
+/// Successively simplified versions of a mesh sharing its vertices, and which of them to draw
+/// for the size the mesh covers on screen.
+#[derive(Debug, Clone)]
+pub struct LodChain {
+    /// From the full mesh down to the coarsest level.
+    pub levels: Vec<LodLevel>,
+    // diameter of the bounding sphere, to relate the errors to sizes on screen
+    diameter: f32,
+}
+
+impl LodChain {
+    /// Up to `max_levels` levels, each simplified to `ratio` of the triangles of the one
+    /// before. Every level is simplified from the full mesh, so its error is measured against
+    /// the full mesh too.
+    pub fn generate(
+        positions: &[[f32; 3]],
+        indices: &[u32],
+        max_levels: usize,
+        ratio: f32,
+    ) -> Self {
+        let points: Vec<Point3<f32>> = positions.iter().map(|&p| Point3::from(p)).collect();
+        let diameter =
+            BoundingSphere::from_points(&points).map_or(0.0, |sphere| sphere.radius * 2.0);
+        let mut levels = vec![LodLevel {
+            indices: indices.to_vec(),
+            error: 0.0,
+        }];
+        while levels.len() < max_levels {
+            let previous = levels[levels.len() - 1].triangle_count() as f32;
+            let target = SimplifyTarget::triangle_count((previous * ratio) as usize);
+            let simplified = simplify(positions, indices, target);
+            if simplified.indices.len() as f32 / 3.0 > previous * MAX_KEPT_RATIO {
+                break;
+            }
+            levels.push(LodLevel {
+                indices: simplified.indices,
+                error: simplified.error,
+            });
+        }
+        LodChain { levels, diameter }
+    }
+
+    /// The coarsest level off by at most `MAX_PIXEL_ERROR` while the mesh is `screen_size`
+    /// pixels across.
+    pub fn select(&self, screen_size: f32) -> usize {
+        if self.diameter <= 0.0 {
+            return 0;
+        }
+        let pixels_per_unit = screen_size / self.diameter;
+        self.levels
+            .iter()
+            .rposition(|level| level.error * pixels_per_unit <= MAX_PIXEL_ERROR)
+            .unwrap_or(0)
+    }
+}
@@ -0,0 +1,568 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+
+use super::{sorted, weld};
+
+/// Weight of the planes holding borders and seams in place, relative to the surface.
+const CONSTRAINT_WEIGHT: f64 = 10.0;
+/// Smallest cosine between the normals of a triangle before and after a collapse; below it
+/// the triangle counts as flipped.
+const MIN_NORMAL_COSINE: f64 = 0.2;
+
+/// When `simplify` stops: at `triangle_count` triangles, or before moving the surface further
+/// than `max_error`, whichever comes first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimplifyTarget {
+    pub triangle_count: usize,
+    /// In the units of the positions.
+    pub max_error: f32,
+}
+
+impl SimplifyTarget {
+    /// Down to `triangle_count` triangles, however far the surface moves.
+    pub fn triangle_count(triangle_count: usize) -> Self {
+        SimplifyTarget {
+            triangle_count,
+            max_error: f32::INFINITY,
+        }
+    }
+
+    /// As few triangles as moving the surface by at most `max_error` allows.
+    pub fn max_error(max_error: f32) -> Self {
+        SimplifyTarget {
+            triangle_count: 0,
+            max_error,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Simplified {
+    /// The remaining triangles, indexing the vertices of the original mesh.
+    pub indices: Vec<u32>,
+    /// How far the surface moved, as estimated by the quadrics.
+    pub error: f32,
+    /// Whether the triangle count of the target was reached, rather than stopping at
+    /// `max_error` or where no edge could be collapsed anymore.
+    pub reached: bool,
+}
+
+/// Decimates a triangle mesh by collapsing edges in the order of the quadric error metric of
+/// Garland and Heckbert. Vertices are only ever moved onto one of their neighbours, so the
+/// result indexes the original vertices and their UVs stay valid.
+///
+/// Vertices with the same position are welded for the error metric. Edges whose vertices
+/// differ in UVs (or any other attribute) at both ends form a seam; seams and the borders of
+/// open meshes are only collapsed along themselves and held in place by extra planes, and
+/// vertices where several meet never move. A vertex split at a single point, like the pole
+/// of a UV sphere, never moves either, but its neighbours are free to collapse around it.
+pub fn simplify(positions: &[[f32; 3]], indices: &[u32], target: SimplifyTarget) -> Simplified {
+    let mut mesh = DecimationMesh::new(positions, indices);
+    let max_error = f64::from(target.max_error).powi(2);
+    let mut error: f64 = 0.0;
+    while mesh.triangle_count > target.triangle_count {
+        let collapse = match mesh.candidates.pop() {
+            Some(collapse) => collapse,
+            None => break,
+        };
+        if !mesh.is_current(&collapse) {
+            continue;
+        }
+        if collapse.error > max_error {
+            break;
+        }
+        if mesh.collapse(collapse.from, collapse.to) {
+            error = error.max(collapse.error);
+        }
+    }
+
+    Simplified {
+        indices: mesh
+            .triangles
+            .iter()
+            .zip(&mesh.alive)
+            .filter(|(_, &alive)| alive)
+            .flat_map(|(triangle, _)| *triangle)
+            .collect(),
+        error: error.sqrt() as f32,
+        reached: mesh.triangle_count <= target.triangle_count,
+    }
+}
+
+/// Symmetric 4x4 matrix summing the squared distances to a set of weighted planes.
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric {
+    // upper triangle, row by row
+    a: [f64; 10],
+    weight: f64,
+}
+
+impl Quadric {
+    fn from_plane(normal: Vector3<f64>, point: Point3<f64>, weight: f64) -> Self {
+        let d = -normal.dot(point.to_vec());
+        let [a, b, c] = [normal.x, normal.y, normal.z];
+        Quadric {
+            a: [
+                a * a,
+                a * b,
+                a * c,
+                a * d,
+                b * b,
+                b * c,
+                b * d,
+                c * c,
+                c * d,
+                d * d,
+            ]
+            .map(|v| v * weight),
+            weight,
+        }
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (a, b) in self.a.iter_mut().zip(other.a) {
+            *a += b;
+        }
+        self.weight += other.weight;
+    }
+
+    /// Mean squared distance of `p` to the planes.
+    fn error(&self, p: Point3<f64>) -> f64 {
+        if self.weight <= 0.0 {
+            return 0.0;
+        }
+        let [xx, xy, xz, xw, yy, yz, yw, zz, zw, ww] = self.a;
+        let (x, y, z) = (p.x, p.y, p.z);
+        let sum = xx * x * x
+            + yy * y * y
+            + zz * z * z
+            + ww
+            + 2.0 * (xy * x * y + xz * x * z + yz * y * z + xw * x + yw * y + zw * z);
+        (sum / self.weight).max(0.0)
+    }
+}
+
+/// Moving the welded position `from` onto its neighbour `to`.
+struct Collapse {
+    error: f64,
+    from: usize,
+    to: usize,
+    // of `from` and `to` when the error was computed
+    versions: [u32; 2],
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    // reversed, so that the heap pops the cheapest collapse first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.error.total_cmp(&self.error)
+    }
+}
+
+struct DecimationMesh {
+    // per welded position
+    points: Vec<Point3<f64>>,
+    quadrics: Vec<Quadric>,
+    // triangles around each position, including some that have since been removed
+    vertex_triangles: Vec<Vec<usize>>,
+    removed: Vec<bool>,
+    locked: Vec<bool>,
+    // bumped whenever the quadric of a position changes
+    versions: Vec<u32>,
+    constraint_counts: Vec<usize>,
+    // welded position of every vertex
+    welded: Vec<usize>,
+
+    triangles: Vec<[u32; 3]>,
+    alive: Vec<bool>,
+    triangle_count: usize,
+    // border and seam edges, as sorted pairs of positions
+    constrained: HashSet<[usize; 2]>,
+    candidates: BinaryHeap<Collapse>,
+}
+
+impl DecimationMesh {
+    fn new(positions: &[[f32; 3]], indices: &[u32]) -> Self {
+        let (welded, points) = weld(positions);
+        let points: Vec<Point3<f64>> = points.iter().map(|p| p.cast().unwrap()).collect();
+        let triangles: Vec<[u32; 3]> = indices
+            .chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect();
+
+        let mut vertex_triangles = vec![Vec::new(); points.len()];
+        let mut quadrics = vec![Quadric::default(); points.len()];
+        // the vertices on either side of every edge, ordered like the sorted positions
+        let mut edges: HashMap<[usize; 2], Vec<(usize, [u32; 2])>> = HashMap::new();
+        let mut alive = vec![true; triangles.len()];
+        for (t, triangle) in triangles.iter().enumerate() {
+            let corners = triangle.map(|v| welded[v as usize]);
+            let [a, b, c] = corners;
+            if a == b || b == c || c == a {
+                // collapsed already, and in the way of the edge bookkeeping
+                alive[t] = false;
+                continue;
+            }
+            for (i, &p) in corners.iter().enumerate() {
+                vertex_triangles[p].push(t);
+                let (q, next) = (corners[(i + 1) % 3], triangle[(i + 1) % 3]);
+                let pair = if p < q {
+                    [triangle[i], next]
+                } else {
+                    [next, triangle[i]]
+                };
+                edges.entry(sorted([p, q])).or_default().push((t, pair));
+            }
+            let [a, b, c] = corners.map(|p| points[p]);
+            let normal = (b - a).cross(c - a);
+            let area = normal.magnitude() * 0.5;
+            if area > 0.0 {
+                let plane = Quadric::from_plane(normal.normalize(), a, area);
+                for p in corners {
+                    quadrics[p].add(&plane);
+                }
+            }
+        }
+
+        // open and non-manifold edges, and those the triangles on either side index different
+        // vertices for at both ends
+        let mut constrained = HashSet::new();
+        let mut constraint_counts = vec![0; points.len()];
+        for (&[p, q], sides) in &edges {
+            let is_seam = sides.len() != 2 || (0..2).all(|end| sides[0].1[end] != sides[1].1[end]);
+            if !is_seam {
+                continue;
+            }
+            constrained.insert([p, q]);
+            constraint_counts[p] += 1;
+            constraint_counts[q] += 1;
+            // a plane through the edge, perpendicular to each side, keeps it from moving away
+            let edge = points[q] - points[p];
+            for &(t, _) in sides {
+                let [a, b, c] = triangles[t].map(|v| points[welded[v as usize]]);
+                let normal = edge.cross((b - a).cross(c - a));
+                if normal.magnitude2() > 0.0 {
+                    let plane = Quadric::from_plane(
+                        normal.normalize(),
+                        points[p],
+                        CONSTRAINT_WEIGHT * edge.magnitude2(),
+                    );
+                    quadrics[p].add(&plane);
+                    quadrics[q].add(&plane);
+                }
+            }
+        }
+
+        let mut copies = vec![HashSet::new(); points.len()];
+        for (v, &p) in welded.iter().enumerate() {
+            copies[p].insert(v);
+        }
+        let locked = constraint_counts
+            .iter()
+            .zip(&copies)
+            .map(|(&count, copies)| match count {
+                // split without a seam, e.g. where two surfaces touch at a vertex
+                0 => copies.len() > 1,
+                2 => false,
+                _ => true,
+            })
+            .collect();
+
+        let mut mesh = DecimationMesh {
+            quadrics,
+            vertex_triangles,
+            removed: vec![false; points.len()],
+            locked,
+            versions: vec![0; points.len()],
+            constraint_counts,
+            points,
+            welded,
+            triangle_count: alive.iter().filter(|&&alive| alive).count(),
+            alive,
+            triangles,
+            constrained,
+            candidates: BinaryHeap::new(),
+        };
+        for [p, q] in edges.into_keys() {
+            mesh.push_candidate(p, q);
+            mesh.push_candidate(q, p);
+        }
+        mesh
+    }
+
+    fn can_move(&self, from: usize, to: usize) -> bool {
+        !self.locked[from]
+            && (self.constraint_counts[from] == 0 || self.constrained.contains(&sorted([from, to])))
+    }
+
+    fn push_candidate(&mut self, from: usize, to: usize) {
+        if !self.can_move(from, to) {
+            return;
+        }
+        let mut quadric = self.quadrics[from];
+        quadric.add(&self.quadrics[to]);
+        self.candidates.push(Collapse {
+            error: quadric.error(self.points[to]),
+            from,
+            to,
+            versions: [self.versions[from], self.versions[to]],
+        });
+    }
+
+    fn is_current(&self, collapse: &Collapse) -> bool {
+        !self.removed[collapse.from]
+            && !self.removed[collapse.to]
+            && collapse.versions == [self.versions[collapse.from], self.versions[collapse.to]]
+    }
+
+    fn triangles_around(&self, p: usize) -> impl Iterator<Item = usize> + '_ {
+        self.vertex_triangles[p]
+            .iter()
+            .copied()
+            .filter(move |&t| self.alive[t])
+    }
+
+    fn corners(&self, t: usize) -> [usize; 3] {
+        self.triangles[t].map(|v| self.welded[v as usize])
+    }
+
+    fn neighbours(&self, p: usize) -> HashSet<usize> {
+        self.triangles_around(p)
+            .flat_map(|t| self.corners(t))
+            .filter(|&q| q != p)
+            .collect()
+    }
+
+    /// Collapses `from` into `to` unless that would tear a seam, make the mesh non-manifold
+    /// or flip a triangle. Returns whether it did.
+    fn collapse(&mut self, from: usize, to: usize) -> bool {
+        if !self.can_move(from, to) {
+            return false;
+        }
+        // each vertex at `from` becomes the vertex at `to` on the same side of any seam, found
+        // in the triangles along the edge
+        let mut remap = HashMap::new();
+        for t in self.triangles_around(from) {
+            let corners = self.corners(t);
+            if let (Some(f), Some(g)) = (
+                corners.iter().position(|&p| p == from),
+                corners.iter().position(|&p| p == to),
+            ) {
+                let (v, w) = (self.triangles[t][f], self.triangles[t][g]);
+                if *remap.entry(v).or_insert(w) != w {
+                    return false;
+                }
+            }
+        }
+        let moved: Vec<usize> = self
+            .triangles_around(from)
+            .filter(|&t| !self.corners(t).contains(&to))
+            .collect();
+        for &t in &moved {
+            let v = self.triangles[t][self.corners(t).iter().position(|&p| p == from).unwrap()];
+            if !remap.contains_key(&v) {
+                return false;
+            }
+        }
+
+        // the link condition: the only neighbours shared are those opposite the edge
+        let neighbours = self.neighbours(from);
+        let shared = neighbours.intersection(&self.neighbours(to)).count();
+        if shared != self.triangles_around(from).count() - moved.len() {
+            return false;
+        }
+
+        for &t in &moved {
+            let [a, b, c] = self.corners(t).map(|p| self.points[p]);
+            let [d, e, f] = self
+                .corners(t)
+                .map(|p| self.points[if p == from { to } else { p }]);
+            let before = (b - a).cross(c - a);
+            let after = (e - d).cross(f - d);
+            if before.magnitude2() > 0.0
+                && (after.magnitude2() == 0.0
+                    || before.normalize().dot(after.normalize()) < MIN_NORMAL_COSINE)
+            {
+                return false;
+            }
+        }
+
+        for t in std::mem::take(&mut self.vertex_triangles[from]) {
+            if !self.alive[t] {
+                continue;
+            }
+            let corners = self.corners(t);
+            if corners.contains(&to) {
+                self.alive[t] = false;
+                self.triangle_count -= 1;
+                continue;
+            }
+            for (v, p) in self.triangles[t].iter_mut().zip(corners) {
+                if p == from {
+                    *v = remap[&*v];
+                }
+            }
+            self.vertex_triangles[to].push(t);
+        }
+        let alive = &self.alive;
+        self.vertex_triangles[to].retain(|&t| alive[t]);
+        let quadric = self.quadrics[from];
+        self.quadrics[to].add(&quadric);
+        self.removed[from] = true;
+        self.versions[to] += 1;
+
+        // the seams and borders through `from` now run through `to`
+        for &n in &neighbours {
+            if self.constrained.remove(&sorted([from, n])) {
+                self.constraint_counts[n] -= 1;
+                if n != to && self.constrained.insert(sorted([to, n])) {
+                    self.constraint_counts[n] += 1;
+                    self.constraint_counts[to] += 1;
+                }
+            }
+        }
+        if !matches!(self.constraint_counts[to], 0 | 2) {
+            self.locked[to] = true;
+        }
+
+        for n in self.neighbours(to) {
+            self.push_candidate(to, n);
+            self.push_candidate(n, to);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Vector2;
+
+    const GRID_SIZE: u32 = 16;
+    const SPHERE_RINGS: u32 = 64;
+    const SPHERE_SEGMENTS: u32 = 128;
+
+    /// `GRID_SIZE` squared quads over the unit square, facing +z.
+    fn grid() -> (Vec<[f32; 3]>, Vec<u32>) {
+        let positions = (0..=GRID_SIZE)
+            .flat_map(|y| (0..=GRID_SIZE).map(move |x| [x, y]))
+            .map(|[x, y]| {
+                [
+                    x as f32 / GRID_SIZE as f32,
+                    y as f32 / GRID_SIZE as f32,
+                    0.0,
+                ]
+            })
+            .collect();
+        let indices = (0..GRID_SIZE)
+            .flat_map(|y| (0..GRID_SIZE).map(move |x| y * (GRID_SIZE + 1) + x))
+            .flat_map(|a| {
+                let b = a + GRID_SIZE + 1;
+                [a, a + 1, b, a + 1, b + 1, b]
+            })
+            .collect();
+        (positions, indices)
+    }
+
+    /// A unit UV sphere, with a seam where the longitudes wrap around.
+    fn uv_sphere() -> (Vec<[f32; 3]>, Vec<[f32; 2]>, Vec<u32>) {
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        for ring in 0..=SPHERE_RINGS {
+            for segment in 0..=SPHERE_SEGMENTS {
+                let u = segment as f32 / SPHERE_SEGMENTS as f32;
+                let v = ring as f32 / SPHERE_RINGS as f32;
+                let (azimuth, polar) = (u * std::f32::consts::TAU, v * std::f32::consts::PI);
+                positions.push([
+                    polar.sin() * azimuth.cos(),
+                    polar.sin() * azimuth.sin(),
+                    polar.cos(),
+                ]);
+                uvs.push([u, v]);
+            }
+        }
+        let mut indices = Vec::new();
+        for ring in 0..SPHERE_RINGS {
+            for segment in 0..SPHERE_SEGMENTS {
+                let a = ring * (SPHERE_SEGMENTS + 1) + segment;
+                let b = a + SPHERE_SEGMENTS + 1;
+                indices.extend([a, b, a + 1, a + 1, b, b + 1]);
+            }
+        }
+        (positions, uvs, indices)
+    }
+
+    #[test]
+    fn grid_reaches_its_target_and_keeps_its_border() {
+        let (positions, indices) = grid();
+        let simplified = simplify(&positions, &indices, SimplifyTarget::triangle_count(32));
+
+        assert!(simplified.reached);
+        assert!(simplified.indices.len() / 3 <= 32);
+        // still covering the whole square, without flipped triangles
+        let mut area = 0.0;
+        for triangle in simplified.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| Point3::from(positions[triangle[i] as usize]));
+            let doubled = (b - a).cross(c - a).z;
+            assert!(doubled > 0.0, "triangle {:?} is flipped", triangle);
+            area += doubled / 2.0;
+        }
+        assert!((area - 1.0).abs() < 1e-4, "covering {} of the square", area);
+        let last = GRID_SIZE * (GRID_SIZE + 2);
+        for corner in [0, GRID_SIZE, last - GRID_SIZE, last] {
+            assert!(simplified.indices.contains(&corner));
+        }
+    }
+
+    #[test]
+    fn uv_sphere_keeps_its_seam_uvs() {
+        let (positions, uvs, indices) = uv_sphere();
+        for target in [500, 100] {
+            let simplified = simplify(&positions, &indices, SimplifyTarget::triangle_count(target));
+
+            assert!(simplified.reached, "stopped short of {} triangles", target);
+            // across the seam, a triangle would stretch over the whole width of the texture and
+            // overlap the others
+            let area: f32 = simplified
+                .indices
+                .chunks_exact(3)
+                .map(|triangle| {
+                    let [a, b, c] = [0, 1, 2].map(|i| Vector2::from(uvs[triangle[i] as usize]));
+                    (b - a).perp_dot(c - a).abs() / 2.0
+                })
+                .sum();
+            assert!(area <= 1.0, "covering {} of the texture", area);
+        }
+    }
+
+    #[test]
+    fn max_error_stops_before_moving_the_surface_further() {
+        let (positions, _, indices) = uv_sphere();
+        let fine = simplify(&positions, &indices, SimplifyTarget::max_error(0.001));
+        let coarse = simplify(&positions, &indices, SimplifyTarget::max_error(0.01));
+
+        assert!(!fine.reached && !coarse.reached);
+        assert!(fine.error <= 0.001 && coarse.error <= 0.01);
+        let counts = [indices.len(), fine.indices.len(), coarse.indices.len()];
+        assert!(
+            counts[0] > counts[1] && counts[1] > counts[2],
+            "{:?}",
+            counts
+        );
+    }
+}
@@ -0,0 +1,709 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+use winit::dpi::PhysicalSize;
+
+use crate::context::HDR_FORMAT;
+use crate::render_graph::{FrameTargets, RenderGraph, TextureDesc};
+use crate::tone_mapping::ToneMapping;
+use crate::{Context, ScreenSpace};
+
+use std::borrow::Cow;
+use std::f32::consts::PI;
+use std::mem;
+
+/// Format of the selection mask the outline is detected from.
+pub const MASK_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+const AO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+const SSAO_KERNEL_SIZE: usize = 16;
+/// Mip levels of the bloom chain, the first at half the viewport size.
+const BLOOM_MIP_LEVELS: u32 = 6;
+
+const FLAG_FXAA: u32 = 1;
+const FLAG_OUTLINE: u32 = 2;
+
+/// Something drawn into the selection mask, which `PostProcess` outlines.
+pub trait OutlineCaster {
+    /// Draws the selected parts with a `MASK_FORMAT` pipeline; the global bind group is set.
+    fn draw_outline_mask<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>);
+}
+
+/// Which effects run, and how strongly.
+#[derive(Debug, Clone, Copy)]
+pub struct PostProcessSettings {
+    pub fxaa: bool,
+    pub ssao: bool,
+    pub bloom: bool,
+    pub outline: bool,
+    /// World-space radius of the hemisphere SSAO samples.
+    pub ssao_radius: f32,
+    pub ssao_intensity: f32,
+    /// Radiance above which pixels bloom, before exposure.
+    pub bloom_threshold: f32,
+    pub bloom_intensity: f32,
+    pub outline_color: [f32; 4],
+}
+
+impl Default for PostProcessSettings {
+    fn default() -> Self {
+        PostProcessSettings {
+            // MSAA already smooths the edges by default
+            fxaa: false,
+            ssao: true,
+            bloom: true,
+            outline: true,
+            ssao_radius: 0.5,
+            ssao_intensity: 1.0,
+            bloom_threshold: 1.0,
+            bloom_intensity: 0.6,
+            outline_color: [1.0, 0.6, 0.1, 1.0],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct PostProcessUniforms {
+    vp_matrix: [f32; 16],
+    inverse_vp_matrix: [f32; 16],
+    ssao_kernel: [[f32; 4]; SSAO_KERNEL_SIZE],
+    outline_color: [f32; 4],
+    texel_size: [f32; 2],
+    ssao_radius: f32,
+    ssao_intensity: f32,
+    bloom_threshold: f32,
+    bloom_knee: f32,
+    bloom_intensity: f32,
+    flags: u32,
+}
+
+/// Fullscreen render graph passes between the lit scene and the editor overlays: SSAO and bloom on the
+/// HDR target, tone mapping, then FXAA and the selection outline into the frame.
+pub struct PostProcess {
+    pub settings: PostProcessSettings,
+    pub tone_mapping: ToneMapping,
+
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    depth_bind_group_layout: wgpu::BindGroupLayout,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    present_bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+
+    ssao_pipeline: wgpu::RenderPipeline,
+    blur_pipeline: wgpu::RenderPipeline,
+    ao_composite_pipeline: wgpu::RenderPipeline,
+    bloom_prefilter_pipeline: wgpu::RenderPipeline,
+    bloom_downsample_pipeline: wgpu::RenderPipeline,
+    bloom_upsample_pipeline: wgpu::RenderPipeline,
+    bloom_composite_pipeline: wgpu::RenderPipeline,
+    present_pipeline: wgpu::RenderPipeline,
+}
+
+impl PostProcess {
+    pub fn new(ctx: &Context) -> Self {
+        let device = &ctx.device;
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("post process"),
+            contents: bytemuck::bytes_of(&PostProcessUniforms::zeroed()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let uniform_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("post process uniforms"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            mem::size_of::<PostProcessUniforms>() as u64,
+                        ),
+                    },
+                    count: None,
+                }],
+            });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("post process uniforms"),
+            layout: &uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let multisampled = ctx.sample_count > 1;
+        let depth_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("ssao depth"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled,
+                    },
+                    count: None,
+                }],
+            });
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let sampler_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+        let texture_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("post process source"),
+                entries: &[texture_entry(0), sampler_entry(1)],
+            });
+        let present_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("post process present"),
+                entries: &[texture_entry(0), texture_entry(1), sampler_entry(2)],
+            });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("post process"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..wgpu::SamplerDescriptor::default()
+        });
+
+        let common = include_str!("post_process/common.wgsl");
+        // the depth target is only multisampled with MSAA, which changes its binding type
+        let depth_declaration = format!(
+            "[[group(1), binding(0)]]\nvar t_depth: {};\n",
+            if multisampled {
+                "texture_depth_multisampled_2d"
+            } else {
+                "texture_depth_2d"
+            }
+        );
+        let ssao_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("post_process/ssao.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(
+                [
+                    common,
+                    &depth_declaration,
+                    include_str!("post_process/ssao.wgsl"),
+                ]
+                .concat(),
+            )),
+        });
+        let filter_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("post_process/filter.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(
+                [common, include_str!("post_process/filter.wgsl")].concat(),
+            )),
+        });
+        let present_module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("post_process/present.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(
+                [common, include_str!("post_process/present.wgsl")].concat(),
+            )),
+        });
+
+        let pipeline_layout = |layout: &wgpu::BindGroupLayout| {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&uniform_bind_group_layout, layout],
+                push_constant_ranges: &[],
+            })
+        };
+        let depth_layout = pipeline_layout(&depth_bind_group_layout);
+        let texture_layout = pipeline_layout(&texture_bind_group_layout);
+        let present_layout = pipeline_layout(&present_bind_group_layout);
+
+        // the AO is multiplied in, the bloom added
+        let multiply = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::Zero,
+                dst_factor: wgpu::BlendFactor::Src,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::Zero,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+        };
+        let add = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: multiply.alpha,
+        };
+        let target = |format, blend| wgpu::ColorTargetState {
+            format,
+            blend,
+            write_mask: wgpu::ColorWrites::ALL,
+        };
+        let single_sample = wgpu::MultisampleState::default();
+
+        let ssao_pipeline = create_pipeline(
+            ctx,
+            "ssao",
+            &depth_layout,
+            &ssao_module,
+            "fs_ssao",
+            target(AO_FORMAT, None),
+            single_sample,
+        );
+        let filter_pipeline = |label, entry_point, target| {
+            create_pipeline(
+                ctx,
+                label,
+                &texture_layout,
+                &filter_module,
+                entry_point,
+                target,
+                single_sample,
+            )
+        };
+        let blur_pipeline = filter_pipeline("ssao blur", "fs_blur", target(AO_FORMAT, None));
+        let ao_composite_pipeline = filter_pipeline(
+            "ssao composite",
+            "fs_ao_composite",
+            target(HDR_FORMAT, Some(multiply)),
+        );
+        let bloom_prefilter_pipeline = filter_pipeline(
+            "bloom prefilter",
+            "fs_bloom_prefilter",
+            target(HDR_FORMAT, None),
+        );
+        let bloom_downsample_pipeline = filter_pipeline(
+            "bloom downsample",
+            "fs_bloom_downsample",
+            target(HDR_FORMAT, None),
+        );
+        let bloom_upsample_pipeline = filter_pipeline(
+            "bloom upsample",
+            "fs_bloom_upsample",
+            target(HDR_FORMAT, Some(add)),
+        );
+        let bloom_composite_pipeline = filter_pipeline(
+            "bloom composite",
+            "fs_bloom_composite",
+            target(HDR_FORMAT, Some(add)),
+        );
+        // drawn into the frame, which the overlays share, so with MSAA it is multisampled
+        let present_pipeline = create_pipeline(
+            ctx,
+            "post process present",
+            &present_layout,
+            &present_module,
+            "fs_main",
+            target(ctx.surface_config.format, None),
+            ctx.multisample_state(),
+        );
+
+        PostProcess {
+            settings: PostProcessSettings::default(),
+            tone_mapping: ToneMapping::new(ctx),
+            uniform_buffer,
+            uniform_bind_group,
+            depth_bind_group_layout,
+            texture_bind_group_layout,
+            present_bind_group_layout,
+            sampler,
+            ssao_pipeline,
+            blur_pipeline,
+            ao_composite_pipeline,
+            bloom_prefilter_pipeline,
+            bloom_downsample_pipeline,
+            bloom_upsample_pipeline,
+            bloom_composite_pipeline,
+            present_pipeline,
+        }
+    }
+
+    /// Rebuilds the pipelines after the MSAA sample count changed, keeping the settings and
+    /// the tone mapping.
+    pub fn recreate_pipelines(&mut self, ctx: &Context) {
+        let previous = mem::replace(self, PostProcess::new(ctx));
+        self.settings = previous.settings;
+        self.tone_mapping = previous.tone_mapping;
+    }
+
+    /// Uploads the settings and the camera of `screen`.
+    pub fn update(&self, queue: &wgpu::Queue, screen: &ScreenSpace) {
+        let settings = &self.settings;
+        let mut flags = 0;
+        if settings.fxaa {
+            flags |= FLAG_FXAA;
+        }
+        if settings.outline {
+            flags |= FLAG_OUTLINE;
+        }
+        let uniforms = PostProcessUniforms {
+            vp_matrix: *screen.vp_matrix().as_ref(),
+            inverse_vp_matrix: *screen.inverse_vp_matrix().as_ref(),
+            ssao_kernel: ssao_kernel(),
+            outline_color: settings.outline_color,
+            texel_size: [1.0 / screen.width(), 1.0 / screen.height()],
+            ssao_radius: settings.ssao_radius,
+            ssao_intensity: settings.ssao_intensity,
+            bloom_threshold: settings.bloom_threshold,
+            bloom_knee: settings.bloom_threshold * 0.5,
+            bloom_intensity: settings.bloom_intensity,
+            flags,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    /// Adds the enabled effects to `graph`, running on the HDR target of `targets` and
+    /// writing the result into its frame. `outlined` draw the selection mask.
+    pub fn add_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        ctx: &'a Context,
+        targets: &FrameTargets,
+        outlined: &'a [&'a dyn OutlineCaster],
+    ) {
+        let settings = self.settings;
+        let (hdr, depth, frame) = (targets.hdr.resolved, targets.depth, targets.frame);
+
+        // bound by the present pass even without the outline, so it is always cleared
+        let mask = graph.create_texture(TextureDesc::new("selection mask", MASK_FORMAT));
+        graph.add_pass("selection mask", &[], &[mask], move |encoder, resources| {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("selection mask"),
+                color_attachments: &[clear_attachment(resources.view(mask))],
+                depth_stencil_attachment: None,
+            });
+            if settings.outline {
+                rpass.set_bind_group(0, &ctx.global.bind_group, &[]);
+                for caster in outlined {
+                    caster.draw_outline_mask(&mut rpass);
+                }
+            }
+        });
+
+        let ao = settings.ssao.then(|| {
+            let ao_raw = graph.create_texture(TextureDesc::new("ssao", AO_FORMAT));
+            let ao = graph.create_texture(TextureDesc::new("ssao blurred", AO_FORMAT));
+            graph.add_pass("ssao", &[depth], &[ao_raw], move |encoder, resources| {
+                let depth_bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("ssao depth"),
+                    layout: &self.depth_bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(resources.view(depth)),
+                    }],
+                });
+                self.draw(
+                    encoder,
+                    "ssao",
+                    clear_attachment(resources.view(ao_raw)),
+                    &self.ssao_pipeline,
+                    &depth_bind_group,
+                );
+            });
+            graph.add_pass("ssao blur", &[ao_raw], &[ao], move |encoder, resources| {
+                self.draw(
+                    encoder,
+                    "ssao blur",
+                    clear_attachment(resources.view(ao)),
+                    &self.blur_pipeline,
+                    &self.create_texture_bind_group(ctx, resources.view(ao_raw)),
+                );
+            });
+            ao
+        });
+
+        // declared before the occlusion is multiplied in, so the prefilter reads the HDR
+        // target without it
+        let bloom = settings.bloom.then(|| {
+            let mip_level_count = bloom_mip_level_count(graph.size());
+            let bloom = graph.create_texture(TextureDesc {
+                size_divisor: 2,
+                mip_level_count,
+                ..TextureDesc::new("bloom", HDR_FORMAT)
+            });
+            graph.add_pass("bloom", &[hdr], &[bloom], move |encoder, resources| {
+                let bloom = resources.texture(bloom);
+                self.render_bloom(ctx, encoder, resources.view(hdr), bloom, mip_level_count);
+            });
+            bloom
+        });
+
+        if let Some(ao) = ao {
+            graph.add_pass(
+                "ssao composite",
+                &[hdr, ao],
+                &[hdr],
+                move |encoder, resources| {
+                    self.draw(
+                        encoder,
+                        "ssao composite",
+                        load_attachment(resources.view(hdr)),
+                        &self.ao_composite_pipeline,
+                        &self.create_texture_bind_group(ctx, resources.view(ao)),
+                    );
+                },
+            );
+        }
+        if let Some(bloom) = bloom {
+            graph.add_pass(
+                "bloom composite",
+                &[hdr, bloom],
+                &[hdr],
+                move |encoder, resources| {
+                    let first_mip = resources
+                        .texture(bloom)
+                        .create_view(&mip_view_descriptor(0));
+                    self.draw(
+                        encoder,
+                        "bloom composite",
+                        load_attachment(resources.view(hdr)),
+                        &self.bloom_composite_pipeline,
+                        &self.create_texture_bind_group(ctx, &first_mip),
+                    );
+                },
+            );
+        }
+
+        let color =
+            graph.create_texture(TextureDesc::new("tone mapped", ctx.surface_config.format));
+        graph.add_pass(
+            "tone mapping",
+            &[hdr],
+            &[color],
+            move |encoder, resources| {
+                self.tone_mapping
+                    .render(ctx, encoder, resources.view(hdr), resources.view(color));
+            },
+        );
+
+        // FXAA and the outline are toggled in the shader; the pass also copies into the frame
+        graph.add_pass(
+            "post process present",
+            &[color, mask],
+            &frame.resources(),
+            move |encoder, resources| {
+                let present_bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("post process present"),
+                    layout: &self.present_bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(resources.view(color)),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(resources.view(mask)),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::Sampler(&self.sampler),
+                        },
+                    ],
+                });
+                self.draw(
+                    encoder,
+                    "post process present",
+                    resources.color_attachment(frame, wgpu::LoadOp::Clear(wgpu::Color::BLACK)),
+                    &self.present_pipeline,
+                    &present_bind_group,
+                );
+            },
+        );
+    }
+
+    /// Thresholds `hdr` into the first mip of `bloom`, blurs it down the mip chain and adds
+    /// the mips back up into the first one.
+    fn render_bloom(
+        &self,
+        ctx: &Context,
+        encoder: &mut wgpu::CommandEncoder,
+        hdr: &wgpu::TextureView,
+        bloom: &wgpu::Texture,
+        mip_level_count: u32,
+    ) {
+        let views: Vec<_> = (0..mip_level_count)
+            .map(|mip| bloom.create_view(&mip_view_descriptor(mip)))
+            .collect();
+        let bind_groups: Vec<_> = views
+            .iter()
+            .map(|view| self.create_texture_bind_group(ctx, view))
+            .collect();
+
+        self.draw(
+            encoder,
+            "bloom prefilter",
+            clear_attachment(&views[0]),
+            &self.bloom_prefilter_pipeline,
+            &self.create_texture_bind_group(ctx, hdr),
+        );
+        for mip in 1..views.len() {
+            self.draw(
+                encoder,
+                "bloom downsample",
+                clear_attachment(&views[mip]),
+                &self.bloom_downsample_pipeline,
+                &bind_groups[mip - 1],
+            );
+        }
+        for mip in (0..views.len() - 1).rev() {
+            self.draw(
+                encoder,
+                "bloom upsample",
+                load_attachment(&views[mip]),
+                &self.bloom_upsample_pipeline,
+                &bind_groups[mip + 1],
+            );
+        }
+    }
+
+    fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        label: &str,
+        attachment: wgpu::RenderPassColorAttachment,
+        pipeline: &wgpu::RenderPipeline,
+        bind_group: &wgpu::BindGroup,
+    ) {
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[attachment],
+            depth_stencil_attachment: None,
+        });
+        rpass.set_pipeline(pipeline);
+        rpass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        rpass.set_bind_group(1, bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+
+    fn create_texture_bind_group(
+        &self,
+        ctx: &Context,
+        view: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("post process source"),
+            layout: &self.texture_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        })
+    }
+}
+
+fn create_pipeline(
+    ctx: &Context,
+    label: &str,
+    layout: &wgpu::PipelineLayout,
+    module: &wgpu::ShaderModule,
+    entry_point: &str,
+    target: wgpu::ColorTargetState,
+    multisample: wgpu::MultisampleState,
+) -> wgpu::RenderPipeline {
+    ctx.device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample,
+            fragment: Some(wgpu::FragmentState {
+                module,
+                entry_point,
+                targets: &[target],
+            }),
+            multiview: None,
+        })
+}
+
+/// Mip levels of the bloom chain in a viewport of `size`.
+fn bloom_mip_level_count(size: PhysicalSize<u32>) -> u32 {
+    let bloom_size = (size.width / 2).min(size.height / 2).max(1);
+    BLOOM_MIP_LEVELS.min(32 - bloom_size.leading_zeros())
+}
+
+fn mip_view_descriptor(mip: u32) -> wgpu::TextureViewDescriptor<'static> {
+    wgpu::TextureViewDescriptor {
+        label: Some("bloom mip"),
+        base_mip_level: mip,
+        mip_level_count: std::num::NonZeroU32::new(1),
+        ..wgpu::TextureViewDescriptor::default()
+    }
+}
+
+fn clear_attachment(view: &wgpu::TextureView) -> wgpu::RenderPassColorAttachment<'_> {
+    wgpu::RenderPassColorAttachment {
+        view,
+        resolve_target: None,
+        ops: wgpu::Operations {
+            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+            store: true,
+        },
+    }
+}
+
+fn load_attachment(view: &wgpu::TextureView) -> wgpu::RenderPassColorAttachment<'_> {
+    wgpu::RenderPassColorAttachment {
+        view,
+        resolve_target: None,
+        ops: wgpu::Operations {
+            load: wgpu::LoadOp::Load,
+            store: true,
+        },
+    }
+}
+
+/// Hemisphere around +z: Hammersley directions, cosine-distributed, at lengths spread by
+/// the base 3 radical inverse and biased towards the center.
+fn ssao_kernel() -> [[f32; 4]; SSAO_KERNEL_SIZE] {
+    let mut kernel = [[0.0; 4]; SSAO_KERNEL_SIZE];
+    for (i, sample) in kernel.iter_mut().enumerate() {
+        let u = (i as f32 + 0.5) / SSAO_KERNEL_SIZE as f32;
+        let v = (i as u32).reverse_bits() as f32 / 2f32.powi(32);
+        let (radius, phi) = (u.sqrt(), 2.0 * PI * v);
+        let mut t = 0.0;
+        let (mut n, mut f) = (i + 1, 1.0 / 3.0);
+        while n > 0 {
+            t += (n % 3) as f32 * f;
+            n /= 3;
+            f /= 3.0;
+        }
+        let length = 0.1 + 0.9 * t * t;
+        *sample = [
+            radius * phi.cos() * length,
+            radius * phi.sin() * length,
+            (1.0 - u).sqrt() * length,
+            0.0,
+        ];
+    }
+    kernel
+}
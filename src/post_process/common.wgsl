@@ -0,0 +1,34 @@
+struct PostProcessUniforms {
+    vp_matrix: mat4x4<f32>;
+    inverse_vp_matrix: mat4x4<f32>;
+    // hemisphere around +z, scaled by the radius
+    ssao_kernel: array<vec4<f32>, 16>;
+    outline_color: vec4<f32>;
+    texel_size: vec2<f32>;
+    ssao_radius: f32;
+    ssao_intensity: f32;
+    bloom_threshold: f32;
+    bloom_knee: f32;
+    bloom_intensity: f32;
+    // 1: FXAA, 2: selection outline
+    flags: u32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> u_post: PostProcessUniforms;
+
+struct VertexOutput {
+    [[location(0)]] uv: vec2<f32>;
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+// one triangle covering the whole target
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.uv = uv;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    return out;
+}
+
@@ -0,0 +1,96 @@
+[[group(1), binding(0)]]
+var t_source: texture_2d<f32>;
+[[group(1), binding(1)]]
+var s_source: sampler;
+
+fn source(uv: vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(t_source, s_source, uv, 0.0);
+}
+
+fn source_texel_size() -> vec2<f32> {
+    return vec2<f32>(1.0) / vec2<f32>(textureDimensions(t_source));
+}
+
+// 4x4 box filter over the rotated SSAO samples, four bilinear taps on pixel corners
+[[stage(fragment)]]
+fn fs_blur(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let texel = source_texel_size();
+    let ao = source(in.uv + vec2<f32>(-0.5, -0.5) * texel).r
+        + source(in.uv + vec2<f32>(1.5, -0.5) * texel).r
+        + source(in.uv + vec2<f32>(-0.5, 1.5) * texel).r
+        + source(in.uv + vec2<f32>(1.5, 1.5) * texel).r;
+    return vec4<f32>(vec3<f32>(ao * 0.25), 1.0);
+}
+
+// multiplied into the HDR target by the blend state
+[[stage(fragment)]]
+fn fs_ao_composite(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(vec3<f32>(source(in.uv).r), 1.0);
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// four bilinear taps, each averaging 2x2 source texels
+fn downsample(uv: vec2<f32>) -> array<vec3<f32>, 4> {
+    let texel = source_texel_size();
+    return array<vec3<f32>, 4>(
+        source(uv + vec2<f32>(-1.0, -1.0) * texel).rgb,
+        source(uv + vec2<f32>(1.0, -1.0) * texel).rgb,
+        source(uv + vec2<f32>(-1.0, 1.0) * texel).rgb,
+        source(uv + vec2<f32>(1.0, 1.0) * texel).rgb,
+    );
+}
+
+// soft threshold: a quadratic curve of width 2 * knee around the threshold
+fn bloom_threshold(color: vec3<f32>) -> vec3<f32> {
+    let brightness = max(color.r, max(color.g, color.b));
+    let knee = u_post.bloom_knee;
+    var soft = clamp(brightness - u_post.bloom_threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 0.0001);
+    let contribution = max(soft, brightness - u_post.bloom_threshold) / max(brightness, 0.0001);
+    return color * contribution;
+}
+
+[[stage(fragment)]]
+fn fs_bloom_prefilter(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    var taps = downsample(in.uv);
+    // Karis average, so single very bright pixels do not flicker
+    var color = vec3<f32>(0.0);
+    var weight_sum = 0.0;
+    for (var i: i32 = 0; i < 4; i = i + 1) {
+        let weight = 1.0 / (1.0 + luminance(taps[i]));
+        color = color + taps[i] * weight;
+        weight_sum = weight_sum + weight;
+    }
+    return vec4<f32>(bloom_threshold(color / weight_sum), 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_bloom_downsample(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    var taps = downsample(in.uv);
+    return vec4<f32>((taps[0] + taps[1] + taps[2] + taps[3]) * 0.25, 1.0);
+}
+
+// 3x3 tent filter, added onto the next larger mip by the blend state
+[[stage(fragment)]]
+fn fs_bloom_upsample(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let texel = source_texel_size();
+    var color = source(in.uv).rgb * 4.0;
+    color = color + (source(in.uv + vec2<f32>(-texel.x, 0.0)).rgb
+        + source(in.uv + vec2<f32>(texel.x, 0.0)).rgb
+        + source(in.uv + vec2<f32>(0.0, -texel.y)).rgb
+        + source(in.uv + vec2<f32>(0.0, texel.y)).rgb) * 2.0;
+    color = color + source(in.uv + vec2<f32>(-texel.x, -texel.y)).rgb
+        + source(in.uv + vec2<f32>(texel.x, -texel.y)).rgb
+        + source(in.uv + vec2<f32>(-texel.x, texel.y)).rgb
+        + source(in.uv + vec2<f32>(texel.x, texel.y)).rgb;
+    return vec4<f32>(color / 16.0, 1.0);
+}
+
+// added onto the HDR target by the blend state
+[[stage(fragment)]]
+fn fs_bloom_composite(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(source(in.uv).rgb * u_post.bloom_intensity, 1.0);
+}
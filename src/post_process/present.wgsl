@@ -0,0 +1,87 @@
+[[group(1), binding(0)]]
+var t_color: texture_2d<f32>;
+[[group(1), binding(1)]]
+var t_mask: texture_2d<f32>;
+[[group(1), binding(2)]]
+var s_color: sampler;
+
+let FXAA_SPAN_MAX: f32 = 8.0;
+let FXAA_REDUCE_MUL: f32 = 0.125;
+let FXAA_REDUCE_MIN: f32 = 0.0078125;
+let OUTLINE_WIDTH: i32 = 2;
+
+fn color(uv: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(t_color, s_color, uv, 0.0).rgb;
+}
+
+// perceptual luma; the square root stands in for the sRGB encoding of the linear samples
+fn luma(color: vec3<f32>) -> f32 {
+    return sqrt(dot(color, vec3<f32>(0.299, 0.587, 0.114)));
+}
+
+// Lottes' FXAA, the variant blurring along the edge direction estimated from four diagonals
+fn fxaa(uv: vec2<f32>) -> vec3<f32> {
+    let texel = u_post.texel_size;
+    let luma_nw = luma(color(uv + vec2<f32>(-1.0, -1.0) * texel));
+    let luma_ne = luma(color(uv + vec2<f32>(1.0, -1.0) * texel));
+    let luma_sw = luma(color(uv + vec2<f32>(-1.0, 1.0) * texel));
+    let luma_se = luma(color(uv + vec2<f32>(1.0, 1.0) * texel));
+    let luma_m = luma(color(uv));
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    var direction = vec2<f32>(
+        (luma_sw + luma_se) - (luma_nw + luma_ne),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let direction_reduce = max(
+        (luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * FXAA_REDUCE_MUL,
+        FXAA_REDUCE_MIN,
+    );
+    let inverse_direction_min = 1.0 / (min(abs(direction.x), abs(direction.y)) + direction_reduce);
+    direction = clamp(
+        direction * inverse_direction_min,
+        vec2<f32>(-FXAA_SPAN_MAX),
+        vec2<f32>(FXAA_SPAN_MAX),
+    ) * texel;
+
+    let color_a = 0.5 * (color(uv + direction * (1.0 / 3.0 - 0.5))
+        + color(uv + direction * (2.0 / 3.0 - 0.5)));
+    let color_b = color_a * 0.5 + 0.25 * (color(uv - direction * 0.5)
+        + color(uv + direction * 0.5));
+    let luma_b = luma(color_b);
+    // the wider blend crossed into another edge
+    if (luma_b < luma_min || luma_b > luma_max) {
+        return color_a;
+    }
+    return color_b;
+}
+
+// how much of the outline covers `pixel`: selected pixels nearby, but not under it
+fn outline(pixel: vec2<i32>) -> f32 {
+    let size = textureDimensions(t_mask);
+    let center = textureLoad(t_mask, pixel, 0).r;
+    var edge = 0.0;
+    for (var y: i32 = -OUTLINE_WIDTH; y <= OUTLINE_WIDTH; y = y + 1) {
+        for (var x: i32 = -OUTLINE_WIDTH; x <= OUTLINE_WIDTH; x = x + 1) {
+            let neighbor = clamp(pixel + vec2<i32>(x, y), vec2<i32>(0), size - vec2<i32>(1));
+            edge = max(edge, textureLoad(t_mask, neighbor, 0).r);
+        }
+    }
+    return edge * (1.0 - center);
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    var result: vec3<f32>;
+    if ((u_post.flags & 1u) != 0u) {
+        result = fxaa(in.uv);
+    } else {
+        result = color(in.uv);
+    }
+    if ((u_post.flags & 2u) != 0u) {
+        let coverage = outline(vec2<i32>(in.position.xy)) * u_post.outline_color.a;
+        result = mix(result, u_post.outline_color.rgb, coverage);
+    }
+    return vec4<f32>(result, 1.0);
+}
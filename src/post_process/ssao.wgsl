@@ -0,0 +1,88 @@
+let SSAO_SAMPLES: i32 = 16;
+let PI: f32 = 3.14159265359;
+
+// world position of the surface seen through the center of `pixel`
+fn world_position(pixel: vec2<i32>, depth: f32) -> vec3<f32> {
+    let uv = (vec2<f32>(pixel) + vec2<f32>(0.5)) * u_post.texel_size;
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world = u_post.inverse_vp_matrix * ndc;
+    return world.xyz / world.w;
+}
+
+fn world_position_at(pixel: vec2<i32>) -> vec3<f32> {
+    return world_position(pixel, textureLoad(t_depth, pixel, 0));
+}
+
+// of the two one-sided differences, the shorter one is less likely to cross an edge
+fn shorter(a: vec3<f32>, b: vec3<f32>) -> vec3<f32> {
+    if (dot(a, a) < dot(b, b)) {
+        return a;
+    }
+    return b;
+}
+
+// Jimenez's interleaved gradient noise, rotating the kernel per pixel
+fn interleaved_gradient_noise(pixel: vec2<f32>) -> f32 {
+    return fract(52.9829189 * fract(dot(pixel, vec2<f32>(0.06711056, 0.00583715))));
+}
+
+[[stage(fragment)]]
+fn fs_ssao(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let pixel = vec2<i32>(in.position.xy);
+    let size = textureDimensions(t_depth);
+    let depth = textureLoad(t_depth, pixel, 0);
+    // nothing but sky
+    if (depth >= 1.0) {
+        return vec4<f32>(1.0);
+    }
+
+    let position = world_position(pixel, depth);
+    let dx = shorter(
+        world_position_at(min(pixel + vec2<i32>(1, 0), size - vec2<i32>(1))) - position,
+        position - world_position_at(max(pixel - vec2<i32>(1, 0), vec2<i32>(0))),
+    );
+    let dy = shorter(
+        world_position_at(min(pixel + vec2<i32>(0, 1), size - vec2<i32>(1))) - position,
+        position - world_position_at(max(pixel - vec2<i32>(0, 1), vec2<i32>(0))),
+    );
+    // screen x goes right and y down, so this faces the camera
+    let normal = normalize(cross(dy, dx));
+
+    let angle = interleaved_gradient_noise(in.position.xy) * 2.0 * PI;
+    var helper = vec3<f32>(1.0, 0.0, 0.0);
+    if (abs(normal.x) > 0.9) {
+        helper = vec3<f32>(0.0, 1.0, 0.0);
+    }
+    let t = normalize(cross(helper, normal));
+    let b = cross(normal, t);
+    let tangent = t * cos(angle) + b * sin(angle);
+    let bitangent = cross(normal, tangent);
+
+    let clip_position = u_post.vp_matrix * vec4<f32>(position, 1.0);
+    let bias = 0.025 * u_post.ssao_radius;
+    var occlusion = 0.0;
+    for (var i: i32 = 0; i < SSAO_SAMPLES; i = i + 1) {
+        let offset = u_post.ssao_kernel[i].xyz;
+        let sample_position = position
+            + (tangent * offset.x + bitangent * offset.y + normal * offset.z) * u_post.ssao_radius;
+        let clip = u_post.vp_matrix * vec4<f32>(sample_position, 1.0);
+        let ndc = clip.xy / clip.w;
+        let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+        if (clip.w <= 0.0 || any(uv < vec2<f32>(0.0)) || any(uv >= vec2<f32>(1.0))) {
+            continue;
+        }
+
+        let sample_pixel = vec2<i32>(uv * vec2<f32>(size));
+        let scene_position = world_position_at(sample_pixel);
+        // clip w is the view depth, so these compare distances along the view direction
+        let scene_w = (u_post.vp_matrix * vec4<f32>(scene_position, 1.0)).w;
+        if (scene_w < clip.w - bias) {
+            // surfaces far in front of the sample do not occlude it
+            let range = u_post.ssao_radius / max(abs(clip_position.w - scene_w), 0.0001);
+            occlusion = occlusion + min(range, 1.0);
+        }
+    }
+
+    let ambient = 1.0 - occlusion / f32(SSAO_SAMPLES) * u_post.ssao_intensity;
+    return vec4<f32>(vec3<f32>(clamp(ambient, 0.0, 1.0)), 1.0);
+}
@@ -0,0 +1,509 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use winit::dpi::PhysicalSize;
+
+use crate::context::HDR_FORMAT;
+use crate::Context;
+
+/// Handle of a texture in a `RenderGraph`, valid for the graph that returned it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ResourceId(usize);
+
+/// Texture allocated by the graph, sized after the viewport it renders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureDesc {
+    pub label: &'static str,
+    pub format: wgpu::TextureFormat,
+    /// The size of the graph is divided by this, keeping at least one texel.
+    pub size_divisor: u32,
+    pub mip_level_count: u32,
+    pub sample_count: u32,
+}
+
+impl TextureDesc {
+    /// Sized like the graph, single-sampled and without mips.
+    pub fn new(label: &'static str, format: wgpu::TextureFormat) -> Self {
+        TextureDesc {
+            label,
+            format,
+            size_divisor: 1,
+            mip_level_count: 1,
+            sample_count: 1,
+        }
+    }
+
+    fn extent(&self, size: PhysicalSize<u32>) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: (size.width / self.size_divisor).max(1),
+            height: (size.height / self.size_divisor).max(1),
+            depth_or_array_layers: 1,
+        }
+    }
+}
+
+enum Resource<'a> {
+    /// Owned outside the graph, e.g. the frame or the shadow maps.
+    Imported(&'a wgpu::TextureView),
+    Created(TextureDesc),
+}
+
+type Execute<'a> = Box<dyn FnOnce(&mut wgpu::CommandEncoder, &PassResources) + 'a>;
+
+struct Pass<'a> {
+    label: &'static str,
+    reads: Vec<ResourceId>,
+    writes: Vec<ResourceId>,
+    execute: Execute<'a>,
+}
+
+/// The passes rendering one viewport and the textures they read and write. Passes are ordered
+/// by their dependencies, passes that contribute nothing to an imported texture are culled,
+/// and created textures are taken from a `TexturePool`, following the size of the viewport.
+///
+/// A pass runs after the passes declared before it that access what it writes, and after
+/// the last pass declared before it writing what it reads; reading a texture no earlier
+/// pass writes waits for every pass writing it.
+pub struct RenderGraph<'a> {
+    size: PhysicalSize<u32>,
+    resources: Vec<Resource<'a>>,
+    passes: Vec<Pass<'a>>,
+}
+
+impl<'a> RenderGraph<'a> {
+    /// A graph rendering a viewport of `size`.
+    pub fn new(size: PhysicalSize<u32>) -> Self {
+        RenderGraph {
+            size,
+            resources: Vec::new(),
+            passes: Vec::new(),
+        }
+    }
+
+    pub fn size(&self) -> PhysicalSize<u32> {
+        self.size
+    }
+
+    /// Adds a texture owned elsewhere. Imported textures are the outputs of the graph.
+    pub fn import_texture(&mut self, view: &'a wgpu::TextureView) -> ResourceId {
+        self.resources.push(Resource::Imported(view));
+        ResourceId(self.resources.len() - 1)
+    }
+
+    /// Adds a texture allocated by the graph when a pass that is not culled uses it.
+    pub fn create_texture(&mut self, desc: TextureDesc) -> ResourceId {
+        self.resources.push(Resource::Created(desc));
+        ResourceId(self.resources.len() - 1)
+    }
+
+    pub fn add_pass(
+        &mut self,
+        label: &'static str,
+        reads: &[ResourceId],
+        writes: &[ResourceId],
+        execute: impl FnOnce(&mut wgpu::CommandEncoder, &PassResources) + 'a,
+    ) {
+        self.passes.push(Pass {
+            label,
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+            execute: Box::new(execute),
+        });
+    }
+
+    /// Records the passes into `encoder`, allocating their textures from `pool`.
+    ///
+    /// # Panics
+    ///
+    /// If the dependencies of the passes form a cycle.
+    pub fn execute(
+        self,
+        ctx: &Context,
+        pool: &mut TexturePool,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        let order = schedule(&self.passes, self.resources.len());
+        let outputs: Vec<_> = self
+            .resources
+            .iter()
+            .map(|resource| matches!(resource, Resource::Imported(_)))
+            .collect();
+        let live = cull(&self.passes, &outputs, &order);
+
+        pool.begin_graph();
+        let mut slots = vec![None; self.resources.len()];
+        for pass in order.iter().filter(|&&pass| live[pass]) {
+            let pass = &self.passes[*pass];
+            for &ResourceId(resource) in pass.reads.iter().chain(&pass.writes) {
+                if let (Resource::Created(desc), None) =
+                    (&self.resources[resource], slots[resource])
+                {
+                    slots[resource] = Some(pool.acquire(ctx, desc, self.size));
+                }
+            }
+        }
+
+        let resources = PassResources {
+            views: self
+                .resources
+                .iter()
+                .zip(&slots)
+                .map(|(resource, slot)| match (resource, slot) {
+                    (Resource::Imported(view), _) => Some(*view),
+                    (Resource::Created(_), Some(slot)) => Some(&pool.entries[*slot].view),
+                    (Resource::Created(_), None) => None,
+                })
+                .collect(),
+            textures: slots
+                .iter()
+                .map(|slot| slot.map(|slot| &pool.entries[slot].texture))
+                .collect(),
+        };
+        let mut passes: Vec<_> = self.passes.into_iter().map(Some).collect();
+        for pass in order.into_iter().filter(|&pass| live[pass]) {
+            let pass = passes[pass].take().unwrap();
+            encoder.push_debug_group(pass.label);
+            (pass.execute)(encoder, &resources);
+            encoder.pop_debug_group();
+        }
+    }
+}
+
+/// Indices of `passes` in execution order, declaration order where they are free.
+fn schedule(passes: &[Pass], resource_count: usize) -> Vec<usize> {
+    let pass_count = passes.len();
+    let mut dependents = vec![Vec::new(); pass_count];
+    let mut dependency_counts = vec![0; pass_count];
+    let mut depend = |pass: usize, on: usize| {
+        if pass != on && !dependents[on].contains(&pass) {
+            dependents[on].push(pass);
+            dependency_counts[pass] += 1;
+        }
+    };
+
+    for resource in 0..resource_count {
+        let id = ResourceId(resource);
+        let writers: Vec<_> = (0..pass_count)
+            .filter(|&pass| passes[pass].writes.contains(&id))
+            .collect();
+        // readers that see a write declared before them
+        let mut ordered_readers = Vec::new();
+        for (pass, declared) in passes.iter().enumerate() {
+            let last_writer = writers.iter().rev().find(|&&writer| writer < pass);
+            if declared.writes.contains(&id) {
+                for &earlier in writers.iter().chain(&ordered_readers) {
+                    if earlier < pass {
+                        depend(pass, earlier);
+                    }
+                }
+            } else if declared.reads.contains(&id) {
+                match last_writer {
+                    Some(&writer) => {
+                        depend(pass, writer);
+                        ordered_readers.push(pass);
+                    }
+                    None => writers.iter().for_each(|&writer| depend(pass, writer)),
+                }
+            }
+        }
+    }
+
+    let mut ready: BinaryHeap<_> = (0..pass_count)
+        .filter(|&pass| dependency_counts[pass] == 0)
+        .map(Reverse)
+        .collect();
+    let mut order = Vec::with_capacity(pass_count);
+    while let Some(Reverse(pass)) = ready.pop() {
+        order.push(pass);
+        for &dependent in &dependents[pass] {
+            dependency_counts[dependent] -= 1;
+            if dependency_counts[dependent] == 0 {
+                ready.push(Reverse(dependent));
+            }
+        }
+    }
+    if order.len() != pass_count {
+        let stuck: Vec<_> = (0..pass_count)
+            .filter(|pass| !order.contains(pass))
+            .map(|pass| passes[pass].label)
+            .collect();
+        panic!("render graph has a dependency cycle between {:?}", stuck);
+    }
+    order
+}
+
+/// Whether each pass contributes to one of the `outputs`, flagged per resource.
+fn cull(passes: &[Pass], outputs: &[bool], order: &[usize]) -> Vec<bool> {
+    let mut needed = outputs.to_vec();
+    let mut live = vec![false; passes.len()];
+    for &pass in order.iter().rev() {
+        let declared = &passes[pass];
+        if declared
+            .writes
+            .iter()
+            .any(|&ResourceId(resource)| needed[resource])
+        {
+            live[pass] = true;
+            for &ResourceId(resource) in &declared.reads {
+                needed[resource] = true;
+            }
+        }
+    }
+    live
+}
+
+/// Views of the textures of a graph, for the passes to record with.
+pub struct PassResources<'r> {
+    views: Vec<Option<&'r wgpu::TextureView>>,
+    textures: Vec<Option<&'r wgpu::Texture>>,
+}
+
+impl<'r> PassResources<'r> {
+    /// # Panics
+    ///
+    /// If no pass that ran declared `resource`.
+    pub fn view(&self, ResourceId(resource): ResourceId) -> &'r wgpu::TextureView {
+        self.views[resource].expect("texture not declared by a pass")
+    }
+
+    /// The texture behind a created resource, e.g. to view single mips.
+    ///
+    /// # Panics
+    ///
+    /// If `resource` was imported, or no pass that ran declared it.
+    pub fn texture(&self, ResourceId(resource): ResourceId) -> &'r wgpu::Texture {
+        self.textures[resource].expect("texture not created by the graph")
+    }
+
+    /// Attachment rendering into `target`, through `msaa` and resolving into `target` if set.
+    pub fn color_attachment(
+        &self,
+        target: ColorTarget,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) -> wgpu::RenderPassColorAttachment<'r> {
+        let ops = wgpu::Operations { load, store: true };
+        match target.msaa {
+            Some(msaa) => wgpu::RenderPassColorAttachment {
+                view: self.view(msaa),
+                resolve_target: Some(self.view(target.resolved)),
+                ops,
+            },
+            None => wgpu::RenderPassColorAttachment {
+                view: self.view(target.resolved),
+                resolve_target: None,
+                ops,
+            },
+        }
+    }
+}
+
+/// Color target of the MSAA passes: with MSAA, drawn into a multisampled texture and resolved.
+#[derive(Debug, Clone, Copy)]
+pub struct ColorTarget {
+    pub resolved: ResourceId,
+    pub msaa: Option<ResourceId>,
+}
+
+impl ColorTarget {
+    fn new(
+        graph: &mut RenderGraph,
+        ctx: &Context,
+        resolved: ResourceId,
+        desc: TextureDesc,
+    ) -> Self {
+        let msaa = (ctx.sample_count > 1).then(|| {
+            graph.create_texture(TextureDesc {
+                sample_count: ctx.sample_count,
+                ..desc
+            })
+        });
+        ColorTarget { resolved, msaa }
+    }
+
+    /// The resolved texture and the multisampled one, if any, for declaring accesses.
+    pub fn resources(&self) -> Vec<ResourceId> {
+        std::iter::once(self.resolved).chain(self.msaa).collect()
+    }
+}
+
+/// Targets every viewport renders to: the lit scene in HDR, the frame with the overlays, in
+/// the format of the window, and the depth they share, multisampled with MSAA.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameTargets {
+    pub frame: ColorTarget,
+    pub hdr: ColorTarget,
+    pub depth: ResourceId,
+}
+
+impl FrameTargets {
+    pub fn new(graph: &mut RenderGraph, ctx: &Context) -> Self {
+        let frame_desc = TextureDesc::new("frame", ctx.surface_config.format);
+        let frame_view = graph.create_texture(frame_desc);
+        let frame = ColorTarget::new(
+            graph,
+            ctx,
+            frame_view,
+            TextureDesc {
+                label: "frame msaa",
+                ..frame_desc
+            },
+        );
+        let hdr_desc = TextureDesc::new("hdr", HDR_FORMAT);
+        let hdr_view = graph.create_texture(hdr_desc);
+        let hdr = ColorTarget::new(
+            graph,
+            ctx,
+            hdr_view,
+            TextureDesc {
+                label: "hdr msaa",
+                ..hdr_desc
+            },
+        );
+        let depth = graph.create_texture(TextureDesc {
+            sample_count: ctx.sample_count,
+            ..TextureDesc::new("depth", wgpu::TextureFormat::Depth32Float)
+        });
+        FrameTargets { frame, hdr, depth }
+    }
+}
+
+struct PoolEntry {
+    desc: TextureDesc,
+    extent: wgpu::Extent3d,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    // by the graph being executed
+    used: bool,
+    // by any graph since the last `end_frame`
+    kept: bool,
+}
+
+/// Textures of render graphs, kept between frames. Graphs executed one after another share
+/// them, so each must be submitted before the next one is executed. Textures no graph used in
+/// a frame, e.g. those of the previous viewport sizes, are released by `end_frame`.
+#[derive(Default)]
+pub struct TexturePool {
+    entries: Vec<PoolEntry>,
+}
+
+impl TexturePool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn begin_graph(&mut self) {
+        for entry in &mut self.entries {
+            entry.used = false;
+        }
+    }
+
+    /// Index of an unused entry matching `desc` in a graph of `size`.
+    fn acquire(&mut self, ctx: &Context, desc: &TextureDesc, size: PhysicalSize<u32>) -> usize {
+        let extent = desc.extent(size);
+        if let Some(index) = self
+            .entries
+            .iter()
+            .position(|entry| !entry.used && entry.desc == *desc && entry.extent == extent)
+        {
+            self.entries[index].used = true;
+            self.entries[index].kept = true;
+            return index;
+        }
+
+        let texture = ctx.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(desc.label),
+            size: extent,
+            mip_level_count: desc.mip_level_count,
+            sample_count: desc.sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: desc.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.entries.push(PoolEntry {
+            desc: *desc,
+            extent,
+            texture,
+            view,
+            used: true,
+            kept: true,
+        });
+        self.entries.len() - 1
+    }
+
+    /// Releases the textures no graph used since the last call.
+    pub fn end_frame(&mut self) {
+        self.entries.retain(|entry| entry.kept);
+        for entry in &mut self.entries {
+            entry.kept = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pass(label: &'static str, reads: &[usize], writes: &[usize]) -> Pass<'static> {
+        Pass {
+            label,
+            reads: reads.iter().map(|&resource| ResourceId(resource)).collect(),
+            writes: writes
+                .iter()
+                .map(|&resource| ResourceId(resource))
+                .collect(),
+            execute: Box::new(|_, _| {}),
+        }
+    }
+
+    #[test]
+    fn independent_passes_keep_declaration_order() {
+        let passes = [
+            pass("a", &[], &[0]),
+            pass("b", &[], &[1]),
+            pass("c", &[], &[2]),
+        ];
+        assert_eq!(schedule(&passes, 3), [0, 1, 2]);
+    }
+
+    #[test]
+    fn read_before_any_write_waits_for_the_writers() {
+        let passes = [pass("reader", &[0], &[1]), pass("writer", &[], &[0])];
+        assert_eq!(schedule(&passes, 2), [1, 0]);
+    }
+
+    #[test]
+    fn write_after_read_waits_for_the_reader() {
+        // "read" also waits for "late", so without the write-after-read dependency
+        // "overwrite" would run before it
+        let passes = [
+            pass("write", &[], &[0]),
+            pass("read", &[0, 2], &[1]),
+            pass("overwrite", &[], &[0]),
+            pass("late", &[], &[2]),
+        ];
+        assert_eq!(schedule(&passes, 3), [0, 3, 1, 2]);
+    }
+
+    #[test]
+    fn passes_not_reaching_an_output_are_culled() {
+        let passes = [
+            pass("source", &[], &[1]),
+            pass("output", &[1], &[0]),
+            pass("unused", &[], &[2]),
+            pass("unused reader", &[2], &[2]),
+        ];
+        let order = schedule(&passes, 3);
+        assert_eq!(
+            cull(&passes, &[true, false, false], &order),
+            [true, true, false, false]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "dependency cycle")]
+    fn cycles_panic() {
+        let passes = [pass("a", &[1], &[0]), pass("b", &[0], &[1])];
+        schedule(&passes, 2);
+    }
+}
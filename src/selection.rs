@@ -0,0 +1,568 @@
+use std::collections::BTreeSet;
+
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix4, MetricSpace, Point2, Point3, SquareMatrix, Transform,
+};
+use winit::event::ModifiersState;
+
+use crate::mesh::Topology;
+use crate::shapes::LineBatch;
+use crate::ScreenSpace;
+
+const PICK_RADIUS: f32 = 8.0;
+const DRAG_THRESHOLD: f32 = 4.0;
+
+const HIGHLIGHT_COLOR: [f32; 4] = [1.0, 0.6, 0.1, 1.0];
+const DRAG_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.8];
+const MARKER_SIZE_IN_PIXELS: f32 = 4.0;
+// NDC depth the drag outline is drawn at, just in front of the near plane
+const OUTLINE_DEPTH: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionMode {
+    Vertex,
+    Edge,
+    Face,
+    Object,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectOp {
+    Replace,
+    Add,
+    Subtract,
+    Toggle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Element {
+    Object,
+    Vertex(usize),
+    Edge(usize),
+    Face(usize),
+}
+
+#[derive(Debug, Clone)]
+pub enum Region {
+    Point(Point2<f32>),
+    Box { min: Point2<f32>, max: Point2<f32> },
+    Lasso(Vec<Point2<f32>>),
+}
+
+/// An object that can be picked, with its topology in object space.
+pub struct SelectionTarget<'a> {
+    pub object: usize,
+    pub model_matrix: Matrix4<f32>,
+    pub topology: &'a Topology,
+}
+
+/// Selected objects and sub-elements. Each mode keeps its own selection, so switching
+/// between e.g. vertex and face mode does not lose either.
+#[derive(Debug)]
+pub struct Selection {
+    mode: SelectionMode,
+    selected: BTreeSet<(usize, Element)>,
+    changed: bool,
+}
+
+/// Snapshot of the selection taken when a transform starts, so that every drag update is
+/// applied to the original state instead of accumulating.
+#[derive(Debug)]
+pub struct TransformSession {
+    objects: Vec<(usize, Matrix4<f32>)>,
+    vertices: Vec<VertexSnapshot>,
+}
+
+#[derive(Debug)]
+struct VertexSnapshot {
+    object: usize,
+    model_matrix: Matrix4<f32>,
+    positions: Vec<(usize, Point3<f32>)>,
+}
+
+/// Mouse drag in progress, turned into a box or lasso region on release.
+#[derive(Debug, Clone)]
+pub struct SelectionDrag {
+    path: Vec<Point2<f32>>,
+    lasso: bool,
+}
+
+impl SelectOp {
+    /// Shift adds, Ctrl subtracts and both together toggle.
+    pub fn from_modifiers(modifiers: ModifiersState) -> Self {
+        match (modifiers.shift(), modifiers.ctrl()) {
+            (true, true) => SelectOp::Toggle,
+            (true, false) => SelectOp::Add,
+            (false, true) => SelectOp::Subtract,
+            (false, false) => SelectOp::Replace,
+        }
+    }
+}
+
+impl Element {
+    pub fn mode(&self) -> SelectionMode {
+        match self {
+            Element::Object => SelectionMode::Object,
+            Element::Vertex(_) => SelectionMode::Vertex,
+            Element::Edge(_) => SelectionMode::Edge,
+            Element::Face(_) => SelectionMode::Face,
+        }
+    }
+}
+
+impl Region {
+    pub fn contains(&self, p: Point2<f32>) -> bool {
+        match self {
+            Region::Point(center) => center.distance(p) <= PICK_RADIUS,
+            Region::Box { min, max } => {
+                (min.x..=max.x).contains(&p.x) && (min.y..=max.y).contains(&p.y)
+            }
+            Region::Lasso(path) => {
+                // even-odd rule
+                let mut inside = false;
+                for (i, a) in path.iter().enumerate() {
+                    let b = path[(i + 1) % path.len()];
+                    if (a.y > p.y) != (b.y > p.y)
+                        && p.x < (b.x - a.x) * (p.y - a.y) / (b.y - a.y) + a.x
+                    {
+                        inside = !inside;
+                    }
+                }
+                inside
+            }
+        }
+    }
+}
+
+impl Default for Selection {
+    fn default() -> Self {
+        Selection {
+            mode: SelectionMode::Object,
+            selected: BTreeSet::new(),
+            changed: false,
+        }
+    }
+}
+
+impl Selection {
+    pub fn mode(&self) -> SelectionMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: SelectionMode) {
+        self.changed |= self.mode != mode;
+        self.mode = mode;
+    }
+
+    pub fn is_object_selected(&self, object: usize) -> bool {
+        self.selected.contains(&(object, Element::Object))
+    }
+
+    pub fn selected_objects(&self) -> impl Iterator<Item = usize> + '_ {
+        self.selected
+            .iter()
+            .filter(|(_, element)| *element == Element::Object)
+            .map(|(object, _)| *object)
+    }
+
+    /// Selected elements of the current mode.
+    pub fn elements(&self) -> impl Iterator<Item = (usize, Element)> + '_ {
+        let mode = self.mode;
+        self.selected
+            .iter()
+            .copied()
+            .filter(move |(_, element)| element.mode() == mode)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements().next().is_none()
+    }
+
+    pub fn clear(&mut self) {
+        let mode = self.mode;
+        let len = self.selected.len();
+        self.selected.retain(|(_, element)| element.mode() != mode);
+        self.changed |= len != self.selected.len();
+    }
+
+    /// Returns whether anything changed since the last call, so highlights are only rebuilt on demand.
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    pub fn select(
+        &mut self,
+        region: &Region,
+        op: SelectOp,
+        screen: &ScreenSpace,
+        targets: &[SelectionTarget],
+    ) {
+        let hits = self.hit_test(region, screen, targets);
+        if op == SelectOp::Replace {
+            self.clear();
+        }
+        for hit in hits {
+            let changed = match op {
+                SelectOp::Replace | SelectOp::Add => self.selected.insert(hit),
+                SelectOp::Subtract => self.selected.remove(&hit),
+                SelectOp::Toggle => self.selected.remove(&hit) || self.selected.insert(hit),
+            };
+            self.changed |= changed;
+        }
+    }
+
+    /// Welded vertices of `target` moved by transforming the current sub-element selection.
+    pub fn affected_vertices(&self, target: &SelectionTarget) -> BTreeSet<usize> {
+        self.elements()
+            .filter(|(object, _)| *object == target.object)
+            .flat_map(|(_, element)| match element {
+                Element::Object => Vec::new(),
+                Element::Vertex(i) => vec![i],
+                Element::Edge(i) => target.topology.edges[i].to_vec(),
+                Element::Face(i) => target.topology.faces[i].vertices.clone(),
+            })
+            .collect()
+    }
+
+    /// Center of the current selection in world space, together with the model matrix of the
+    /// first object it touches.
+    pub fn pivot(&self, targets: &[SelectionTarget]) -> Option<(Point3<f32>, Matrix4<f32>)> {
+        let mut points = Vec::new();
+        let mut model_matrix = None;
+        for target in targets {
+            let len = points.len();
+            if self.mode == SelectionMode::Object {
+                if self.is_object_selected(target.object) {
+                    points.push(target.model_matrix.transform_point(Point3::origin()));
+                }
+            } else {
+                points.extend(self.affected_vertices(target).into_iter().map(|i| {
+                    target
+                        .model_matrix
+                        .transform_point(target.topology.positions[i])
+                }));
+            }
+            if points.len() > len {
+                model_matrix.get_or_insert(target.model_matrix);
+            }
+        }
+        model_matrix.map(|model_matrix| (Point3::centroid(&points), model_matrix))
+    }
+
+    /// Queues lines highlighting the selected sub-elements. Selected objects are highlighted
+    /// through their own wireframe instead.
+    pub fn draw_highlight(
+        &self,
+        lines: &mut LineBatch,
+        screen: &ScreenSpace,
+        targets: &[SelectionTarget],
+    ) {
+        for (object, element) in self.elements() {
+            let target = match targets.iter().find(|target| target.object == object) {
+                Some(target) => target,
+                None => continue,
+            };
+            let topology = target.topology;
+            let world = |i: usize| target.model_matrix.transform_point(topology.positions[i]);
+            let marker = |lines: &mut LineBatch, p: Point3<f32>| {
+                lines.cross(
+                    p,
+                    MARKER_SIZE_IN_PIXELS * screen.pixel_size_at(p),
+                    HIGHLIGHT_COLOR,
+                )
+            };
+            match element {
+                Element::Object => {}
+                Element::Vertex(i) => marker(lines, world(i)),
+                Element::Edge(i) => {
+                    let [a, b] = topology.edges[i];
+                    lines.line(world(a), world(b), HIGHLIGHT_COLOR);
+                }
+                Element::Face(i) => {
+                    for &[a, b] in topology.face_edges(i) {
+                        lines.line(world(a), world(b), HIGHLIGHT_COLOR);
+                    }
+                    let centroid = target
+                        .model_matrix
+                        .transform_point(topology.face_centroid(i));
+                    marker(lines, centroid);
+                }
+            }
+        }
+    }
+
+    fn hit_test(
+        &self,
+        region: &Region,
+        screen: &ScreenSpace,
+        targets: &[SelectionTarget],
+    ) -> Vec<(usize, Element)> {
+        match (region, self.mode) {
+            (Region::Point(p), SelectionMode::Object | SelectionMode::Face) => {
+                Self::ray_cast(*p, screen, targets)
+                    .map(|(object, face)| match self.mode {
+                        SelectionMode::Face => (object, Element::Face(face)),
+                        _ => (object, Element::Object),
+                    })
+                    .into_iter()
+                    .collect()
+            }
+            (Region::Point(p), SelectionMode::Vertex) => targets
+                .iter()
+                .flat_map(|target| {
+                    let project = projector(screen, target.model_matrix);
+                    target
+                        .topology
+                        .positions
+                        .iter()
+                        .enumerate()
+                        .filter_map(move |(i, &v)| {
+                            project(v).map(|q| (q.distance(*p), target.object, Element::Vertex(i)))
+                        })
+                })
+                .filter(|(distance, _, _)| *distance <= PICK_RADIUS)
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+                .map(|(_, object, element)| (object, element))
+                .into_iter()
+                .collect(),
+            (Region::Point(p), SelectionMode::Edge) => targets
+                .iter()
+                .flat_map(|target| {
+                    let project = projector(screen, target.model_matrix);
+                    let topology = target.topology;
+                    topology
+                        .edges
+                        .iter()
+                        .enumerate()
+                        .filter_map(move |(i, [a, b])| {
+                            let a = project(topology.positions[*a])?;
+                            let b = project(topology.positions[*b])?;
+                            Some((
+                                distance_to_segment(*p, a, b),
+                                target.object,
+                                Element::Edge(i),
+                            ))
+                        })
+                })
+                .filter(|(distance, _, _)| *distance <= PICK_RADIUS)
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+                .map(|(_, object, element)| (object, element))
+                .into_iter()
+                .collect(),
+            (region, mode) => {
+                let mut hits = Vec::new();
+                for target in targets {
+                    let project = projector(screen, target.model_matrix);
+                    let topology = target.topology;
+                    let inside = |v: Point3<f32>| project(v).is_some_and(|q| region.contains(q));
+                    match mode {
+                        SelectionMode::Object => {
+                            if topology.positions.iter().any(|&v| inside(v)) {
+                                hits.push((target.object, Element::Object));
+                            }
+                        }
+                        SelectionMode::Vertex => hits.extend(
+                            (0..topology.positions.len())
+                                .filter(|&i| inside(topology.positions[i]))
+                                .map(|i| (target.object, Element::Vertex(i))),
+                        ),
+                        SelectionMode::Edge => hits.extend(
+                            topology
+                                .edges
+                                .iter()
+                                .enumerate()
+                                .filter(|(_, [a, b])| {
+                                    inside(topology.positions[*a]) && inside(topology.positions[*b])
+                                })
+                                .map(|(i, _)| (target.object, Element::Edge(i))),
+                        ),
+                        SelectionMode::Face => hits.extend(
+                            (0..topology.faces.len())
+                                .filter(|&i| inside(topology.face_centroid(i)))
+                                .map(|i| (target.object, Element::Face(i))),
+                        ),
+                    }
+                }
+                hits
+            }
+        }
+    }
+
+    /// Front-most `(object, face)` under the cursor.
+    fn ray_cast(
+        p: Point2<f32>,
+        screen: &ScreenSpace,
+        targets: &[SelectionTarget],
+    ) -> Option<(usize, usize)> {
+        let ray = screen.ray(p);
+        targets
+            .iter()
+            .flat_map(|target| {
+                target
+                    .topology
+                    .faces
+                    .iter()
+                    .enumerate()
+                    .flat_map(move |(face_index, face)| {
+                        face.triangles.iter().filter_map(move |t| {
+                            let triangle = t.map(|i| {
+                                target
+                                    .model_matrix
+                                    .transform_point(target.topology.positions[i])
+                            });
+                            ray.intersect_triangle(triangle)
+                                .map(|distance| (distance, target.object, face_index))
+                        })
+                    })
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, object, face)| (object, face))
+    }
+}
+
+impl TransformSession {
+    pub fn begin(selection: &Selection, targets: &[SelectionTarget]) -> Self {
+        let mut objects = Vec::new();
+        let mut vertices = Vec::new();
+        for target in targets {
+            if selection.mode() == SelectionMode::Object {
+                if selection.is_object_selected(target.object) {
+                    objects.push((target.object, target.model_matrix));
+                }
+                continue;
+            }
+            let positions: Vec<(usize, Point3<f32>)> = selection
+                .affected_vertices(target)
+                .into_iter()
+                .map(|i| (i, target.topology.positions[i]))
+                .collect();
+            if !positions.is_empty() {
+                vertices.push(VertexSnapshot {
+                    object: target.object,
+                    model_matrix: target.model_matrix,
+                    positions,
+                });
+            }
+        }
+        TransformSession { objects, vertices }
+    }
+
+    /// Whether geometry built from `vertices` of `object` moves along with this session.
+    pub fn moves(&self, object: usize, vertices: &[usize]) -> bool {
+        self.objects.iter().any(|(o, _)| *o == object)
+            || self.vertices.iter().any(|snapshot| {
+                snapshot.object == object
+                    && snapshot.positions.iter().any(|(i, _)| vertices.contains(i))
+            })
+    }
+
+    /// New model matrix of `object` under the world-space transform `delta`, if the object
+    /// is transformed as a whole.
+    pub fn object_matrix(&self, object: usize, delta: Matrix4<f32>) -> Option<Matrix4<f32>> {
+        self.objects
+            .iter()
+            .find(|(o, _)| *o == object)
+            .map(|(_, start)| delta * start)
+    }
+
+    /// New object-space positions of the welded vertices of `object` under the world-space
+    /// transform `delta`.
+    pub fn vertex_positions(
+        &self,
+        object: usize,
+        delta: Matrix4<f32>,
+    ) -> Vec<(usize, Point3<f32>)> {
+        self.vertices
+            .iter()
+            .filter(|snapshot| snapshot.object == object)
+            .flat_map(|snapshot| {
+                let inverse = snapshot
+                    .model_matrix
+                    .invert()
+                    .expect("failed to calculate inverse matrix of model matrix");
+                let object_delta = inverse * delta * snapshot.model_matrix;
+                snapshot
+                    .positions
+                    .iter()
+                    .map(move |&(i, p)| (i, object_delta.transform_point(p)))
+            })
+            .collect()
+    }
+}
+
+impl SelectionDrag {
+    pub fn start(p: Point2<f32>, lasso: bool) -> Self {
+        SelectionDrag {
+            path: vec![p],
+            lasso,
+        }
+    }
+
+    pub fn extend(&mut self, p: Point2<f32>) {
+        self.path.push(p);
+    }
+
+    pub fn is_lasso(&self) -> bool {
+        self.lasso
+    }
+
+    pub fn is_click(&self) -> bool {
+        let start = self.path[0];
+        self.path.iter().all(|p| p.distance(start) < DRAG_THRESHOLD)
+    }
+
+    pub fn region(&self) -> Region {
+        let start = self.path[0];
+        let end = *self.path.last().unwrap();
+        if self.is_click() {
+            Region::Point(end)
+        } else if self.lasso {
+            Region::Lasso(self.path.clone())
+        } else {
+            Region::Box {
+                min: Point2::new(start.x.min(end.x), start.y.min(end.y)),
+                max: Point2::new(start.x.max(end.x), start.y.max(end.y)),
+            }
+        }
+    }
+
+    pub fn draw(&self, lines: &mut LineBatch, screen: &ScreenSpace) {
+        let outline: Vec<Point3<f32>> = self
+            .outline()
+            .into_iter()
+            .map(|p| screen.unproject(p, OUTLINE_DEPTH))
+            .collect();
+        lines.polyline_closed(&outline, DRAG_COLOR);
+    }
+
+    /// Closed outline of the drag in pixels.
+    pub fn outline(&self) -> Vec<Point2<f32>> {
+        match self.region() {
+            Region::Point(_) => Vec::new(),
+            Region::Box { min, max } => vec![
+                min,
+                Point2::new(max.x, min.y),
+                max,
+                Point2::new(min.x, max.y),
+            ],
+            Region::Lasso(path) => path,
+        }
+    }
+}
+
+fn projector(
+    screen: &ScreenSpace,
+    model_matrix: Matrix4<f32>,
+) -> impl Fn(Point3<f32>) -> Option<Point2<f32>> + '_ {
+    move |p| screen.project(model_matrix.transform_point(p))
+}
+
+pub(crate) fn distance_to_segment(p: Point2<f32>, a: Point2<f32>, b: Point2<f32>) -> f32 {
+    let ab = b - a;
+    let t = if ab.magnitude2() > 0.0 {
+        ((p - a).dot(ab) / ab.magnitude2()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    p.distance(a + ab * t)
+}
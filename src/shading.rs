@@ -0,0 +1,38 @@
+/// How a viewport draws meshes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShadingMode {
+    /// Edges only, seen through the mesh.
+    Wireframe,
+    /// Flat-shaded gray, lit from the camera.
+    Solid,
+    /// Lit with the materials, with the wireframe on top.
+    #[default]
+    Textured,
+    /// World-space normals, after normal mapping, as colors.
+    Normals,
+    /// A checkerboard in texture space, showing stretching and seams.
+    UvChecker,
+    /// Distance to the camera, white up close.
+    Depth,
+}
+
+impl ShadingMode {
+    pub const ALL: [ShadingMode; 6] = [
+        ShadingMode::Wireframe,
+        ShadingMode::Solid,
+        ShadingMode::Textured,
+        ShadingMode::Normals,
+        ShadingMode::UvChecker,
+        ShadingMode::Depth,
+    ];
+
+    /// Whether meshes are drawn with their materials, including their transparency.
+    pub fn uses_materials(self) -> bool {
+        self == ShadingMode::Textured
+    }
+
+    /// Whether the wireframe is drawn, alone or over the shaded mesh.
+    pub fn draws_wireframe(self) -> bool {
+        matches!(self, ShadingMode::Wireframe | ShadingMode::Textured)
+    }
+}
@@ -1,5 +1,17 @@
+mod axis_widget;
 mod base_line;
 mod cube;
+mod grid;
+mod line_batch;
+mod skybox;
+mod text_batch;
 
+pub use axis_widget::{AxisWidget, Viewport};
 pub use base_line::BaseLine;
-pub use cube::Cube;
+pub use cube::{
+    benchmark_instancing, Cube, CubeInstances, InstanceId, InstancingBenchmark, LodMesh,
+};
+pub use grid::{Grid, GridPlane};
+pub use line_batch::{circle_points, perpendicular_basis, LineBatch, LineVertex};
+pub use skybox::Skybox;
+pub use text_batch::TextBatch;
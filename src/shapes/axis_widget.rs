@@ -0,0 +1,314 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{
+    EuclideanSpace, Matrix4, MetricSpace, Point2, Point3, SquareMatrix, Transform, Vector3, Vector4,
+};
+use wgpu::{util::DeviceExt, BufferUsages, PipelineLayoutDescriptor, VertexAttribute};
+use winit::dpi::PhysicalSize;
+
+use super::{circle_points, LineVertex};
+use crate::{Camera, Context};
+
+use std::borrow::Cow;
+use std::mem;
+
+const WIDGET_SIZE_IN_PIXELS: u32 = 110;
+const WIDGET_MARGIN_IN_PIXELS: u32 = 10;
+/// Half the side of the square the axes are drawn in, in axis lengths.
+const WIDGET_EXTENT: f32 = 1.35;
+const TIP_RADIUS: f32 = 0.2;
+const NEGATIVE_TIP_RADIUS: f32 = 0.13;
+const TIP_SEGMENTS: usize = 24;
+const NEGATIVE_AXIS_DIMMING: f32 = 0.45;
+const HOVER_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+// matches the colors of the world axes drawn by `BaseLine`
+const AXIS_COLORS: [[f32; 4]; 3] = [
+    [1.0, 0.0, 0.0, 1.0],
+    [0.0, 0.0, 1.0, 1.0],
+    [0.0, 1.0, 0.0, 1.0],
+];
+/// A line and a tip circle for each of the six axis directions.
+const VERTEX_CAPACITY: usize = 6 * (2 + TIP_SEGMENTS * 2);
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct AxisWidgetUniforms {
+    transform: [f32; 16],
+}
+
+/// Viewport in window pixels as `(x, y, width, height)`.
+pub type Viewport = (u32, u32, u32, u32);
+
+/// World axes drawn in a corner of the window, rotating with the camera. Clicking the end of an
+/// axis snaps the camera to look along it.
+pub struct AxisWidget {
+    transform: Matrix4<f32>,
+    hovered: Option<usize>,
+    vertex_count: u32,
+
+    pub uniform_buffer: wgpu::Buffer,
+    pub vertex_buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+    pub render_pipeline: wgpu::RenderPipeline,
+}
+
+impl AxisWidget {
+    pub fn new(ctx: &Context) -> Self {
+        let uniform_buffer = ctx
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::bytes_of(&AxisWidgetUniforms::zeroed()),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            });
+
+        let vertex_buffer = ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (VERTEX_CAPACITY * mem::size_of::<LineVertex>()) as u64,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout =
+            ctx.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: None,
+                    entries: &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(
+                                mem::size_of::<AxisWidgetUniforms>() as u64,
+                            ),
+                        },
+                        count: None,
+                    }],
+                });
+
+        let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let shader_module = ctx
+            .device
+            .create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: Some("axis_widget/axis_widget.wgsl"),
+                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                    "axis_widget/axis_widget.wgsl"
+                ))),
+            });
+
+        let pipeline_layout = ctx
+            .device
+            .create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = ctx
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: "vs_main",
+                    buffers: &[wgpu::VertexBufferLayout {
+                        array_stride: mem::size_of::<LineVertex>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &[
+                            VertexAttribute {
+                                format: wgpu::VertexFormat::Float32x3,
+                                offset: 0,
+                                shader_location: 0,
+                            },
+                            VertexAttribute {
+                                format: wgpu::VertexFormat::Float32x4,
+                                offset: 4 * 3,
+                                shader_location: 1,
+                            },
+                        ],
+                    }],
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::LineList,
+                    ..wgpu::PrimitiveState::default()
+                },
+                // drawn in the main pass, so it has to accept its depth attachment; the axes are
+                // ordered back to front on the CPU instead
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: wgpu::TextureFormat::Depth32Float,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: ctx.multisample_state(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point: "fs_main",
+                    targets: &[wgpu::ColorTargetState {
+                        format: ctx.surface_config.format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                }),
+                multiview: None,
+            });
+
+        AxisWidget {
+            transform: Matrix4::identity(),
+            hovered: None,
+            vertex_count: 0,
+            uniform_buffer,
+            vertex_buffer,
+            bind_group,
+            render_pipeline,
+        }
+    }
+
+    /// Rebuilds the pipeline after the MSAA sample count changed. The axes are rebuilt by the
+    /// next `update`.
+    pub fn recreate_pipelines(&mut self, ctx: &Context) {
+        *self = AxisWidget {
+            hovered: self.hovered,
+            ..AxisWidget::new(ctx)
+        };
+    }
+
+    /// Square in the top-right corner of a window of `size`, or `None` if it does not fit.
+    pub fn viewport(size: PhysicalSize<u32>) -> Option<Viewport> {
+        let side = WIDGET_SIZE_IN_PIXELS;
+        let margin = WIDGET_MARGIN_IN_PIXELS;
+        (size.width >= side + margin && size.height >= side + margin)
+            .then(|| (size.width - side - margin, margin, side, side))
+    }
+
+    /// Follows the orientation of `camera` and rebuilds the axes.
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        // keep only the rotation of the view
+        let mut view_matrix = camera.view_matrix();
+        view_matrix.w = Vector4::new(0.0, 0.0, 0.0, 1.0);
+        // orthographic, with view-space z in [-1, 1] mapped into the [0, 1] depth range
+        let projection_matrix = Matrix4::from_translation(Vector3::new(0.0, 0.0, 0.5))
+            * Matrix4::from_nonuniform_scale(1.0 / WIDGET_EXTENT, 1.0 / WIDGET_EXTENT, 0.25);
+        self.transform = projection_matrix * view_matrix;
+
+        let uniforms = AxisWidgetUniforms {
+            transform: *self.transform.as_ref(),
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+
+        let mut directions: Vec<usize> = (0..6).collect();
+        // farthest first, since there is no depth test
+        directions.sort_by(|&a, &b| {
+            let depth = |i: usize| view_matrix.transform_vector(axis_direction(i)).z;
+            depth(a).total_cmp(&depth(b))
+        });
+
+        let mut lines = Vec::with_capacity(VERTEX_CAPACITY);
+        let mut line = |a: Point3<f32>, b: Point3<f32>, color: [f32; 4]| {
+            lines.push(LineVertex {
+                a_position: a.into(),
+                a_color: color,
+            });
+            lines.push(LineVertex {
+                a_position: b.into(),
+                a_color: color,
+            });
+        };
+        for i in directions {
+            let positive = i < 3;
+            let tip = Point3::from_vec(axis_direction(i));
+            let color = if self.hovered == Some(i) {
+                HOVER_COLOR
+            } else if positive {
+                AXIS_COLORS[i % 3]
+            } else {
+                let [r, g, b, a] = AXIS_COLORS[i % 3];
+                let dim = NEGATIVE_AXIS_DIMMING;
+                [r * dim, g * dim, b * dim, a]
+            };
+            line(Point3::origin(), tip, color);
+
+            let radius = if positive {
+                TIP_RADIUS
+            } else {
+                NEGATIVE_TIP_RADIUS
+            };
+            // billboarded, i.e. facing the camera
+            let points = circle_points(tip, camera.direction(), radius, TIP_SEGMENTS);
+            for (j, &a) in points.iter().enumerate() {
+                line(a, points[(j + 1) % points.len()], color);
+            }
+        }
+
+        self.vertex_count = lines.len() as u32;
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&lines));
+    }
+
+    /// Direction of the axis end under `cursor`, preferring ends closer to the viewer.
+    pub fn pick(&self, cursor: Point2<f32>, size: PhysicalSize<u32>) -> Option<Vector3<f32>> {
+        self.pick_index(cursor, size).map(axis_direction)
+    }
+
+    /// Highlights the axis end under `cursor`.
+    pub fn hover(&mut self, cursor: Point2<f32>, size: PhysicalSize<u32>) {
+        self.hovered = self.pick_index(cursor, size);
+    }
+
+    pub fn is_hovered(&self) -> bool {
+        self.hovered.is_some()
+    }
+
+    /// Draws into the corner viewport. Render bundles cannot set the viewport, so this records
+    /// directly into the pass and must come after the bundles of the scene.
+    pub fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>, size: PhysicalSize<u32>) {
+        let (x, y, width, height) = match Self::viewport(size) {
+            Some(viewport) => viewport,
+            None => return,
+        };
+        rpass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+        rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.draw(0..self.vertex_count, 0..1);
+        rpass.set_viewport(0.0, 0.0, size.width as f32, size.height as f32, 0.0, 1.0);
+    }
+
+    fn pick_index(&self, cursor: Point2<f32>, size: PhysicalSize<u32>) -> Option<usize> {
+        let (x, y, width, height) = Self::viewport(size)?;
+        let pick_radius = TIP_RADIUS / WIDGET_EXTENT * 0.5 * width as f32;
+        (0..6)
+            .map(|i| {
+                let ndc = self
+                    .transform
+                    .transform_point(Point3::from_vec(axis_direction(i)));
+                let pixel = Point2::new(
+                    x as f32 + (ndc.x + 1.0) * 0.5 * width as f32,
+                    y as f32 + (1.0 - ndc.y) * 0.5 * height as f32,
+                );
+                (i, pixel.distance(cursor), ndc.z)
+            })
+            .filter(|&(_, distance, _)| distance <= pick_radius)
+            .max_by(|a, b| a.2.total_cmp(&b.2))
+            .map(|(i, _, _)| i)
+    }
+}
+
+/// +X, +Y, +Z, -X, -Y, -Z for `i` in `0..6`.
+fn axis_direction(i: usize) -> Vector3<f32> {
+    let axis = [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()][i % 3];
+    if i < 3 {
+        axis
+    } else {
+        -axis
+    }
+}
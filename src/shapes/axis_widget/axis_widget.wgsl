@@ -0,0 +1,26 @@
+struct VertexOutput {
+    [[location(0)]] color: vec4<f32>;
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+struct Locals {
+    transform: mat4x4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> r_locals: Locals;
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] a_position: vec3<f32>,
+    [[location(1)]] a_color: vec4<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.color = a_color;
+    out.position = r_locals.transform * vec4<f32>(a_position, 1.0);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return in.color;
+}
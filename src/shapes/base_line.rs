@@ -89,7 +89,7 @@ impl BaseLine {
                     stencil: wgpu::StencilState::default(),
                     bias: wgpu::DepthBiasState::default(),
                 }),
-                multisample: wgpu::MultisampleState::default(),
+                multisample: ctx.multisample_state(),
                 fragment: Some(wgpu::FragmentState {
                     module: &shader_module,
                     entry_point: "fs_main",
@@ -110,7 +110,7 @@ impl BaseLine {
                         depth_read_only: false,
                         stencil_read_only: true,
                     }),
-                    sample_count: 1,
+                    sample_count: ctx.sample_count,
                     ..wgpu::RenderBundleEncoderDescriptor::default()
                 });
         render_bundle_encoder.set_pipeline(&render_pipeline);
@@ -1,70 +1,99 @@
-use crate::Context;
-use std::f32::consts::PI;
-use std::io::Cursor;
+use crate::bounds::Bounds;
+use crate::context::HDR_FORMAT;
+use crate::material::{AlphaMode, Material, MaterialBinding, MATERIAL_WGSL};
+use crate::mesh::{generate_tangents, Topology};
+use crate::post_process::{OutlineCaster, MASK_FORMAT};
+use crate::selection::SelectionTarget;
+use crate::shading::ShadingMode;
+use crate::texture::TextureError;
+use crate::{lighting_wgsl, Context, ShadowCaster, Shadows};
+use std::borrow::Cow;
 use std::mem;
-use std::{borrow::Cow, mem::size_of_val};
 
 use bytemuck::{Pod, Zeroable};
-use cgmath::{InnerSpace, Matrix, Matrix4, Rad, SquareMatrix, Vector3};
+use cgmath::{InnerSpace, Matrix, Matrix4, Point3, SquareMatrix};
 use wgpu::util::DeviceExt;
 
+mod benchmark;
+mod instances;
+mod lod_mesh;
 mod polygon;
+pub use benchmark::{benchmark_instancing, InstancingBenchmark};
+pub use instances::{CubeInstances, InstanceId};
+pub use lod_mesh::LodMesh;
 use polygon::{Vertex, INDICES, VERTICES};
 use wgpu::{
-    BindGroup, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType,
-    Buffer, BufferUsages, Extent3d, Face, Features, MultisampleState, PipelineLayoutDescriptor,
-    RenderBundle, RenderBundleDescriptor, RenderBundleEncoderDescriptor, RenderPipeline,
-    ShaderSource, ShaderStages, Texture, TextureDescriptor, TextureDimension, TextureFormat,
-    TextureUsages, TextureViewDescriptor,
+    BindGroup, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, Buffer, BufferUsages,
+    Face, Features, MultisampleState, PipelineLayoutDescriptor, RenderBundle,
+    RenderBundleDescriptor, RenderBundleEncoderDescriptor, RenderPipeline, ShaderSource,
+    ShaderStages, TextureFormat,
 };
 
-fn create_texels(size: usize) -> Vec<u8> {
-    (0..size * size)
-        .map(|id| {
-            let cx = 3.0 * (id % size) as f32 / (size - 1) as f32 - 2.0;
-            let cy = 2.0 * (id / size) as f32 / (size - 1) as f32 - 1.0;
-            let (mut x, mut y, mut count) = (cx, cy, 0);
-            while count < 0xFF && x * x + y * y < 4.0 {
-                let old_x = x;
-                x = x * x - y * y + cx;
-                y = 2.0 * old_x * y + cy;
-                count += 1;
-            }
-            count
-        })
-        .collect()
-}
-
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
 pub struct CubeUniforms {
     model_matrix: [f32; 16],
     model_matrix_inverted_transposed: [f32; 16],
+    wire_color: [f32; 4],
+}
+
+/// Vertex of the wireframe fallback; every triangle has its own three.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct WireVertex {
+    a_pos: [f32; 3],
+    a_barycentric: [f32; 3],
 }
 
+/// Byte offset of the arguments of the non-indexed wireframe draw in the indirect buffers of
+/// `LodMesh` and `CubeInstances`, after the five of the indexed one.
+const NON_INDEXED_ARGS_OFFSET: wgpu::BufferAddress = 5 * mem::size_of::<u32>() as u64;
+
+const WIRE_COLOR: [f32; 4] = [0.0, 0.5, 0.0, 0.5];
+const SELECTED_WIRE_COLOR: [f32; 4] = [1.0, 0.6, 0.1, 0.9];
+
 pub struct Cube {
     pub model_matrix: Matrix4<f32>,
+    pub topology: Topology,
+    vertices: Vec<Vertex>,
+    // object space, following `move_vertices`
+    bounds: Bounds,
+    selected: bool,
 
-    pub texture: Texture,
+    pub material: Material,
+    pub material_binding: MaterialBinding,
     pub index_buffer: Buffer,
     pub vertex_buffer: Buffer,
     pub uniform_buffer: Buffer,
     pub bind_group: BindGroup,
-    pub pipeline_cube: RenderPipeline,
-    pub pipeline_wire: Option<RenderPipeline>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    // indexed by `ShadingMode`, `None` for the wireframe-only mode
+    pub pipelines_fill: Vec<Option<RenderPipeline>>,
+    // back faces, then front faces of blended materials
+    pub pipelines_blend: Vec<RenderPipeline>,
+    pub pipeline_wire: RenderPipeline,
+    // without `POLYGON_MODE_LINE` the wireframe is drawn from barycentric coordinates
+    wire_vertex_buffer: Option<Buffer>,
+    // solid, then cutting out the fragments of masked materials
+    pub pipelines_shadow: Vec<RenderPipeline>,
+    pub pipeline_mask: RenderPipeline,
+    pipeline_layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
     pub num_indicies: usize,
-    pub render_bundle: RenderBundle,
+    // indexed by `ShadingMode`
+    render_bundles: Vec<RenderBundle>,
 }
 
 impl Cube {
     pub fn new(ctx: &Context) -> Self {
-        let vertex_size = mem::size_of::<Vertex>();
+        let mut vertices = VERTICES.to_vec();
+        Self::update_tangents(&mut vertices);
         let vertex_buffer = ctx
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(VERTICES),
-                usage: BufferUsages::VERTEX,
+                contents: bytemuck::cast_slice(&vertices),
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
             });
 
         let index_buffer = ctx
@@ -75,107 +104,47 @@ impl Cube {
                 usage: BufferUsages::INDEX,
             });
 
-        // create texture
-        let raw_image = include_bytes!("cube/Wood_Floor_011_basecolor.png");
-        let decoder = png::Decoder::new(Cursor::new(raw_image));
-        let mut reader = decoder.read_info().unwrap();
-        let mut buf = vec![0; reader.output_buffer_size()];
-        let info = reader.next_frame(&mut buf).unwrap();
-        let image_buffer = &buf[..info.buffer_size()];
-
-        let texture_format = match info {
-            png::OutputInfo {
-                bit_depth,
-                color_type: png::ColorType::Rgba,
-                ..
-            } => match bit_depth {
-                png::BitDepth::Eight => Some(TextureFormat::Rgba8Uint),
-                png::BitDepth::Sixteen => Some(TextureFormat::Rgba16Uint),
-                _ => None,
-            },
-            _ => None,
-        };
-        let texture_format = texture_format.unwrap_or_else(|| panic!("info: {:?}", info));
-
-        // let texels = create_texels(size as usize);
-        let texture_extent = Extent3d {
-            width: info.width,
-            height: info.height,
-            depth_or_array_layers: 1,
-        };
-        let texture = ctx.device.create_texture(&TextureDescriptor {
-            label: None,
-            size: texture_extent,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: texture_format,
-            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        let material = Self::wood_material(ctx).unwrap_or_else(|error| {
+            log::error!("{}", error);
+            Material::dielectric("wood", [0.55, 0.35, 0.2, 1.0], 0.6)
         });
-        let texture_view = texture.create_view(&TextureViewDescriptor::default());
-        ctx.queue.write_texture(
-            texture.as_image_copy(),
-            image_buffer,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(std::num::NonZeroU32::new(info.width * 4 /* Rgba */).unwrap()),
-                rows_per_image: None,
-            },
-            texture_extent,
-        );
+        let material_binding = material.create_binding(ctx);
 
         let model_matrix = Matrix4::identity();
 
         let uniform_buffer = ctx.device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            size: mem::size_of::<[f32; 32]>() as u64,
+            size: mem::size_of::<CubeUniforms>() as u64,
             mapped_at_creation: false,
         });
 
         Self::update_inner(&ctx.queue, &uniform_buffer, model_matrix);
+        Self::write_wire_color(&ctx.queue, &uniform_buffer, WIRE_COLOR);
 
         let bind_group_layout = ctx
             .device
             .create_bind_group_layout(&BindGroupLayoutDescriptor {
                 label: None,
-                entries: &[
-                    BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: ShaderStages::FRAGMENT,
-                        ty: BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Uint,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: ShaderStages::VERTEX,
-                        ty: BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: wgpu::BufferSize::new(
-                                mem::size_of::<[f32; 32]>() as u64
-                            ),
-                        },
-                        count: None,
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            mem::size_of::<CubeUniforms>() as u64
+                        ),
                     },
-                ],
+                    count: None,
+                }],
             });
         let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(&texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: uniform_buffer.as_entire_binding(),
-                },
-            ],
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
             label: None,
         });
 
@@ -183,7 +152,11 @@ impl Cube {
             .device
             .create_pipeline_layout(&PipelineLayoutDescriptor {
                 label: None,
-                bind_group_layouts: &[&ctx.global.bind_group_layout, &bind_group_layout],
+                bind_group_layouts: &[
+                    &ctx.global.bind_group_layout,
+                    &bind_group_layout,
+                    &ctx.material_bind_group_layout,
+                ],
                 push_constant_ranges: &[],
             });
 
@@ -191,33 +164,34 @@ impl Cube {
             .device
             .create_shader_module(&wgpu::ShaderModuleDescriptor {
                 label: Some("cube/cube.wgsl"),
-                source: ShaderSource::Wgsl(Cow::Borrowed(include_str!("cube/cube.wgsl"))),
+                source: ShaderSource::Wgsl(Cow::Owned(
+                    [
+                        &lighting_wgsl(),
+                        MATERIAL_WGSL,
+                        include_str!("cube/cube.wgsl"),
+                    ]
+                    .concat(),
+                )),
             });
 
-        let vertex_buffers = [wgpu::VertexBufferLayout {
-            array_stride: vertex_size as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x3,
-                    offset: 0,
-                    shader_location: 0,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x2,
-                    offset: size_of_val(&VERTICES[0].a_pos) as u64,
-                    shader_location: 1,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x3,
-                    offset: size_of_val(&VERTICES[0].a_pos) as u64
-                        + size_of_val(&VERTICES[0].a_uv) as u64,
-                    shader_location: 2,
-                },
-            ],
-        }];
+        let vertex_buffers = [Self::vertex_buffer_layout()];
+        let (pipelines_fill, pipelines_blend, pipeline_wire) =
+            Self::create_pipelines(ctx, &pipeline_layout, &shader, false);
+        let wire_vertex_buffer = (!ctx.device.features().contains(Features::POLYGON_MODE_LINE))
+            .then(|| {
+                ctx.device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("Wire Vertex Buffer"),
+                        contents: bytemuck::cast_slice(&Self::wire_vertices(&vertices, INDICES)),
+                        usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                    })
+            });
 
-        let pipeline_cube = ctx
+        let pipelines_shadow =
+            Self::create_shadow_pipelines(ctx, &bind_group_layout, &shader, false);
+
+        // the selection mask, outlined by post-processing
+        let pipeline_mask = ctx
             .device
             .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
                 label: None,
@@ -229,96 +203,50 @@ impl Cube {
                 },
                 fragment: Some(wgpu::FragmentState {
                     module: &shader,
-                    entry_point: "fs_main",
-                    targets: &[ctx.surface_config.format.into()],
+                    entry_point: "fs_mask",
+                    targets: &[MASK_FORMAT.into()],
                 }),
                 primitive: wgpu::PrimitiveState {
                     cull_mode: Some(wgpu::Face::Back),
                     ..Default::default()
                 },
-                depth_stencil: Some(wgpu::DepthStencilState {
-                    format: TextureFormat::Depth32Float,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::LessEqual,
-                    stencil: wgpu::StencilState::default(),
-                    bias: wgpu::DepthBiasState::default(),
-                }),
+                depth_stencil: None,
                 multisample: MultisampleState::default(),
                 multiview: None,
             });
 
-        let pipeline_wire = ctx
-            .device
-            .features()
-            .contains(Features::POLYGON_MODE_LINE)
-            .then(|| {
-                ctx.device
-                    .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                        label: None,
-                        layout: Some(&pipeline_layout),
-                        vertex: wgpu::VertexState {
-                            module: &shader,
-                            entry_point: "vs_main",
-                            buffers: &vertex_buffers,
-                        },
-                        fragment: Some(wgpu::FragmentState {
-                            module: &shader,
-                            entry_point: "fs_wire",
-                            targets: &[wgpu::ColorTargetState {
-                                format: ctx.surface_config.format,
-                                blend: Some(wgpu::BlendState {
-                                    color: wgpu::BlendComponent {
-                                        operation: wgpu::BlendOperation::Add,
-                                        src_factor: wgpu::BlendFactor::SrcAlpha,
-                                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                                    },
-                                    alpha: wgpu::BlendComponent::REPLACE,
-                                }),
-                                write_mask: wgpu::ColorWrites::ALL,
-                            }],
-                        }),
-                        primitive: wgpu::PrimitiveState {
-                            front_face: wgpu::FrontFace::Ccw,
-                            cull_mode: Some(Face::Back),
-                            polygon_mode: wgpu::PolygonMode::Line,
-                            ..Default::default()
-                        },
-                        depth_stencil: Some(wgpu::DepthStencilState {
-                            format: TextureFormat::Depth32Float,
-                            depth_write_enabled: false,
-                            depth_compare: wgpu::CompareFunction::Always,
-                            stencil: wgpu::StencilState::default(),
-                            bias: wgpu::DepthBiasState::default(),
-                        }),
-                        multiview: None,
-                        multisample: wgpu::MultisampleState::default(),
-                    })
-            });
-
         let num_indicies = INDICES.len();
 
-        let render_bundle = Self::create_render_bundle(
-            ctx,
-            &pipeline_cube,
-            pipeline_wire.as_ref(),
-            &bind_group,
-            &index_buffer,
-            &vertex_buffer,
-            num_indicies,
-        );
+        let positions: Vec<[f32; 3]> = VERTICES.iter().map(|v| v.a_pos).collect();
+        let topology = Topology::from_triangles(&positions, INDICES);
+        let bounds = Bounds::from_points(&topology.positions).expect("the cube has no vertices");
 
-        Cube {
+        let mut cube = Cube {
             model_matrix,
-            texture,
+            topology,
+            vertices,
+            bounds,
+            selected: false,
+            material,
+            material_binding,
             index_buffer,
             vertex_buffer,
-            pipeline_cube,
+            pipelines_fill,
+            pipelines_blend,
             pipeline_wire,
+            wire_vertex_buffer,
+            pipelines_shadow,
+            pipeline_mask,
+            pipeline_layout,
+            shader,
             bind_group,
-            render_bundle,
+            bind_group_layout,
+            render_bundles: Vec::new(),
             num_indicies,
             uniform_buffer,
-        }
+        };
+        cube.recreate_render_bundles(ctx);
+        cube
     }
 
     fn update_inner(queue: &wgpu::Queue, uniform_buffer: &Buffer, model_matrix: Matrix4<f32>) {
@@ -337,58 +265,487 @@ impl Cube {
         );
     }
 
-    pub fn update(&mut self, queue: &wgpu::Queue) {
-        let delta_rotation = Matrix4::from_axis_angle(Vector3::new(0.0, 0.0, 1.0), Rad(0.3));
-        // let delta_rotation = Matrix4::from_axis_angle(
-        //     Vector3::new(1.0, 1.0, 1.0).normalize(),
-        //     Rad(PI / 180.0 / 5.0),
-        // );
-
-        self.model_matrix = delta_rotation * self.model_matrix;
-        //
-        // self.model_matrix =
-        //     Matrix4::from_translation(Vector3::new(0.05, 0.0, 0.0)) * self.model_matrix;
-        // if self.model_matrix.w.x > 1.0 {
-        //     self.model_matrix.w.x = -1.0;
-        // }
-        Self::update_inner(queue, &self.uniform_buffer, self.model_matrix);
+    fn write_wire_color(queue: &wgpu::Queue, uniform_buffer: &Buffer, wire_color: [f32; 4]) {
+        queue.write_buffer(
+            uniform_buffer,
+            mem::size_of::<[f32; 32]>() as u64,
+            bytemuck::cast_slice(&wire_color),
+        );
     }
 
-    pub fn create_render_bundle(
-        ctx: &Context,
-        pipeline_cube: &RenderPipeline,
-        pipeline_wire: Option<&RenderPipeline>,
-        bind_group: &BindGroup,
-        index_buffer: &Buffer,
-        vertex_buffer: &Buffer,
-        num_indicies: usize,
-    ) -> RenderBundle {
+    /// Recolors the wireframe overlay and outlines the cube to highlight it as selected.
+    pub fn set_selected(&mut self, queue: &wgpu::Queue, selected: bool) {
+        self.selected = selected;
+        let wire_color = if selected {
+            SELECTED_WIRE_COLOR
+        } else {
+            WIRE_COLOR
+        };
+        Self::write_wire_color(queue, &self.uniform_buffer, wire_color);
+    }
+
+    pub fn selection_target(&self, object: usize) -> SelectionTarget<'_> {
+        SelectionTarget {
+            object,
+            model_matrix: self.model_matrix,
+            topology: &self.topology,
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.num_indicies / 3
+    }
+
+    /// Bounding volumes in world space, to cull the cube with.
+    pub fn world_bounds(&self) -> Bounds {
+        self.bounds.transform(&self.model_matrix)
+    }
+
+    /// Moves welded vertices to new object-space positions and re-uploads the vertex buffer
+    /// with flat normals recomputed.
+    pub fn move_vertices(&mut self, queue: &wgpu::Queue, moved: &[(usize, Point3<f32>)]) {
+        for &(i, position) in moved {
+            self.topology.positions[i] = position;
+        }
+        self.topology.recompute_normals();
+        if let Some(bounds) = Bounds::from_points(&self.topology.positions) {
+            self.bounds = bounds;
+        }
+        for (vertex, &welded) in self.vertices.iter_mut().zip(&self.topology.vertex_map) {
+            vertex.a_pos = self.topology.positions[welded].into();
+        }
+        for triangle in INDICES.chunks_exact(3) {
+            let [a, b, c] =
+                [0, 1, 2].map(|i| Point3::from(self.vertices[triangle[i] as usize].a_pos));
+            let normal: [f32; 3] = (b - a).cross(c - a).normalize().into();
+            for &i in triangle {
+                self.vertices[i as usize].a_normal = normal;
+            }
+        }
+        Self::update_tangents(&mut self.vertices);
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+        if let Some(wire_vertex_buffer) = &self.wire_vertex_buffer {
+            let wire_vertices = Self::wire_vertices(&self.vertices, INDICES);
+            queue.write_buffer(wire_vertex_buffer, 0, bytemuck::cast_slice(&wire_vertices));
+        }
+    }
+
+    fn vertex_buffer_layout() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+            0 => Float32x3,
+            1 => Float32x2,
+            2 => Float32x3,
+            3 => Float32x4,
+        ];
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBUTES,
+        }
+    }
+
+    /// The triangles `indices` makes of `vertices`, unindexed, with each corner at one
+    /// barycentric coordinate.
+    fn wire_vertices<I: Copy + Into<u32>>(vertices: &[Vertex], indices: &[I]) -> Vec<WireVertex> {
+        const CORNERS: [[f32; 3]; 3] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        indices
+            .iter()
+            .zip(CORNERS.iter().cycle())
+            .map(|(&i, &a_barycentric)| WireVertex {
+                a_pos: vertices[i.into() as usize].a_pos,
+                a_barycentric,
+            })
+            .collect()
+    }
+
+    /// An encoder of the HDR scene pass, with the global bind group, the uniforms of the mesh
+    /// in `bind_group` and its material set. Its buffers are left to the caller.
+    fn begin_render_bundle<'a>(
+        ctx: &'a Context,
+        bind_group: &'a BindGroup,
+        material_binding: &'a MaterialBinding,
+    ) -> wgpu::RenderBundleEncoder<'a> {
         let mut render_bundle_encoder =
             ctx.device
                 .create_render_bundle_encoder(&RenderBundleEncoderDescriptor {
                     label: None,
-                    color_formats: &[ctx.surface_config.format],
+                    color_formats: &[HDR_FORMAT],
                     depth_stencil: Some(wgpu::RenderBundleDepthStencil {
                         format: TextureFormat::Depth32Float,
                         depth_read_only: false,
                         stencil_read_only: true,
                     }),
-                    sample_count: 1,
+                    sample_count: ctx.sample_count,
                     multiview: None,
                 });
-
-        render_bundle_encoder.set_pipeline(pipeline_cube);
         render_bundle_encoder.set_bind_group(0, &ctx.global.bind_group, &[]);
         render_bundle_encoder.set_bind_group(1, bind_group, &[]);
-        render_bundle_encoder.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        render_bundle_encoder.set_vertex_buffer(0, vertex_buffer.slice(..));
-        render_bundle_encoder.draw_indexed(0..(num_indicies as u32), 0, 0..1);
+        render_bundle_encoder.set_bind_group(2, &material_binding.bind_group, &[]);
+        render_bundle_encoder
+    }
 
-        if let Some(pipe) = pipeline_wire {
-            render_bundle_encoder.set_pipeline(pipe);
-            render_bundle_encoder.draw_indexed(0..(num_indicies as u32), 0, 0..1);
+    /// A filled pipeline per shading mode, the two drawing blended materials and the wireframe
+    /// pipeline, drawn into the HDR target with the context's MSAA sample count.
+    ///
+    /// `instanced` pipelines place the cube by the model matrices of an instance buffer, bound
+    /// after the vertices, see `CubeInstances`.
+    fn create_pipelines(
+        ctx: &Context,
+        pipeline_layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        instanced: bool,
+    ) -> (
+        Vec<Option<RenderPipeline>>,
+        Vec<RenderPipeline>,
+        RenderPipeline,
+    ) {
+        let instance_buffers = instanced.then(instances::instance_buffer_layout);
+        let vertex_buffers: Vec<_> = std::iter::once(Self::vertex_buffer_layout())
+            .chain(instance_buffers.clone())
+            .collect();
+        let vertex_entry_point = if instanced { "vs_instanced" } else { "vs_main" };
+        // blended meshes only test against the depth of the opaque ones
+        let create_fill_pipeline = |entry_point, cull_mode, blend: Option<wgpu::BlendState>| {
+            ctx.device
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: None,
+                    layout: Some(pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: shader,
+                        entry_point: vertex_entry_point,
+                        buffers: &vertex_buffers,
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: shader,
+                        entry_point,
+                        targets: &[wgpu::ColorTargetState {
+                            format: HDR_FORMAT,
+                            blend,
+                            write_mask: wgpu::ColorWrites::ALL,
+                        }],
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        cull_mode: Some(cull_mode),
+                        ..Default::default()
+                    },
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: TextureFormat::Depth32Float,
+                        depth_write_enabled: blend.is_none(),
+                        depth_compare: wgpu::CompareFunction::LessEqual,
+                        stencil: wgpu::StencilState::default(),
+                        bias: wgpu::DepthBiasState::default(),
+                    }),
+                    multisample: ctx.multisample_state(),
+                    multiview: None,
+                })
+        };
+        let pipelines_fill = ShadingMode::ALL
+            .iter()
+            .map(|&mode| {
+                let entry_point = match mode {
+                    ShadingMode::Wireframe => return None,
+                    ShadingMode::Solid => "fs_solid",
+                    ShadingMode::Textured => "fs_main",
+                    ShadingMode::Normals => "fs_normals",
+                    ShadingMode::UvChecker => "fs_uv_checker",
+                    ShadingMode::Depth => "fs_depth",
+                };
+                Some(create_fill_pipeline(entry_point, Face::Back, None))
+            })
+            .collect();
+        // the far side first, so that the near side blends over it
+        let pipelines_blend = [Face::Front, Face::Back]
+            .into_iter()
+            .map(|cull_mode| {
+                create_fill_pipeline("fs_main", cull_mode, Some(wgpu::BlendState::ALPHA_BLENDING))
+            })
+            .collect();
+
+        const WIRE_ATTRIBUTES: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![
+            0 => Float32x3,
+            1 => Float32x3,
+        ];
+        let wire_vertex_buffers: Vec<_> = std::iter::once(wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<WireVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &WIRE_ATTRIBUTES,
+        })
+        .chain(instance_buffers)
+        .collect();
+        let (vertex_entry_point, buffers, fragment_entry_point, polygon_mode) =
+            if ctx.device.features().contains(Features::POLYGON_MODE_LINE) {
+                (
+                    vertex_entry_point,
+                    &vertex_buffers[..],
+                    "fs_wire",
+                    wgpu::PolygonMode::Line,
+                )
+            } else {
+                (
+                    if instanced {
+                        "vs_wire_barycentric_instanced"
+                    } else {
+                        "vs_wire_barycentric"
+                    },
+                    &wire_vertex_buffers[..],
+                    "fs_wire_barycentric",
+                    wgpu::PolygonMode::Fill,
+                )
+            };
+        let pipeline_wire = ctx
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: shader,
+                    entry_point: vertex_entry_point,
+                    buffers,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: shader,
+                    entry_point: fragment_entry_point,
+                    targets: &[wgpu::ColorTargetState {
+                        format: HDR_FORMAT,
+                        blend: Some(wgpu::BlendState {
+                            color: wgpu::BlendComponent {
+                                operation: wgpu::BlendOperation::Add,
+                                src_factor: wgpu::BlendFactor::SrcAlpha,
+                                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                            },
+                            alpha: wgpu::BlendComponent::REPLACE,
+                        }),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: Some(Face::Back),
+                    polygon_mode,
+                    ..Default::default()
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: TextureFormat::Depth32Float,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multiview: None,
+                multisample: ctx.multisample_state(),
+            });
+
+        (pipelines_fill, pipelines_blend, pipeline_wire)
+    }
+
+    /// The pipelines filling the triangles of a mesh with `material` in `mode`, to draw with
+    /// one after the other: none for the wireframe alone, and the back and then the front
+    /// faces of a blended material.
+    fn fill_pipelines<'a>(
+        pipelines_fill: &'a [Option<RenderPipeline>],
+        pipelines_blend: &'a [RenderPipeline],
+        material: &Material,
+        mode: ShadingMode,
+    ) -> Vec<&'a RenderPipeline> {
+        if material.blends(mode) {
+            pipelines_blend.iter().collect()
+        } else {
+            pipelines_fill[mode as usize].iter().collect()
+        }
+    }
+
+    /// Depth only, reusing the vertex stage with the light's view bound as the globals: one
+    /// without a fragment stage, and one discarding the fragments masked materials cut out.
+    /// Blended materials cast solid shadows.
+    fn create_shadow_pipelines(
+        ctx: &Context,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        shader: &wgpu::ShaderModule,
+        instanced: bool,
+    ) -> Vec<RenderPipeline> {
+        let shadow_pipeline_layout = ctx
+            .device
+            .create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[
+                    &ctx.global.shadows.pass_bind_group_layout,
+                    bind_group_layout,
+                    &ctx.material_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            });
+        let vertex_buffers: Vec<_> = std::iter::once(Self::vertex_buffer_layout())
+            .chain(instanced.then(instances::instance_buffer_layout))
+            .collect();
+        let masked_fragment = wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_shadow_masked",
+            targets: &[],
+        };
+        [None, Some(masked_fragment)]
+            .into_iter()
+            .map(|fragment| {
+                ctx.device
+                    .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                        label: None,
+                        layout: Some(&shadow_pipeline_layout),
+                        vertex: wgpu::VertexState {
+                            module: shader,
+                            entry_point: if instanced { "vs_instanced" } else { "vs_main" },
+                            buffers: &vertex_buffers,
+                        },
+                        fragment,
+                        primitive: wgpu::PrimitiveState::default(),
+                        depth_stencil: Some(Shadows::depth_stencil_state()),
+                        multisample: MultisampleState::default(),
+                        multiview: None,
+                    })
+            })
+            .collect()
+    }
+
+    /// The shadow pipeline of `pipelines_shadow` a mesh with `material` is drawn with, to
+    /// bind along with the material.
+    fn shadow_pipeline<'a>(
+        pipelines_shadow: &'a [RenderPipeline],
+        material: &Material,
+    ) -> &'a RenderPipeline {
+        match material.alpha_mode {
+            AlphaMode::Mask => &pipelines_shadow[1],
+            AlphaMode::Opaque | AlphaMode::Blend => &pipelines_shadow[0],
+        }
+    }
+
+    /// Rebuilds the pipelines and render bundles after the MSAA sample count changed.
+    pub fn recreate_pipelines(&mut self, ctx: &Context) {
+        let (pipelines_fill, pipelines_blend, pipeline_wire) =
+            Self::create_pipelines(ctx, &self.pipeline_layout, &self.shader, false);
+        self.pipelines_fill = pipelines_fill;
+        self.pipelines_blend = pipelines_blend;
+        self.pipeline_wire = pipeline_wire;
+        self.recreate_render_bundles(ctx);
+    }
+
+    fn recreate_render_bundles(&mut self, ctx: &Context) {
+        self.render_bundles = ShadingMode::ALL
+            .iter()
+            .map(|&mode| self.create_render_bundle(ctx, mode))
+            .collect();
+    }
+
+    /// Draws the cube in the HDR scene pass of a viewport shaded with `mode`.
+    pub fn render_bundle(&self, mode: ShadingMode) -> &RenderBundle {
+        &self.render_bundles[mode as usize]
+    }
+
+    fn update_tangents(vertices: &mut [Vertex]) {
+        let positions: Vec<_> = vertices.iter().map(|v| v.a_pos).collect();
+        let normals: Vec<_> = vertices.iter().map(|v| v.a_normal).collect();
+        let uvs: Vec<_> = vertices.iter().map(|v| v.a_uv).collect();
+        let tangents = generate_tangents(&positions, &normals, &uvs, INDICES);
+        for (vertex, tangent) in vertices.iter_mut().zip(tangents) {
+            vertex.a_tangent = tangent;
+        }
+    }
+
+    /// The wood texture the cube is shipped with, as a rough dielectric. The normal and
+    /// ambient occlusion maps of the set are not shipped, but are used when they are put
+    /// next to it.
+    fn wood_material(ctx: &Context) -> Result<Material, TextureError> {
+        let texture =
+            ctx.textures
+                .load_asset(ctx, "textures/Wood_Floor_011_basecolor.png", true)?;
+        let optional_map = |path| match ctx.textures.load_asset(ctx, path, false) {
+            Ok(texture) => Some(texture),
+            Err(error) => {
+                let missing = matches!(
+                    &error,
+                    TextureError::Io { source, .. } if source.kind() == std::io::ErrorKind::NotFound
+                );
+                if missing {
+                    log::debug!("{}", error);
+                } else {
+                    log::warn!("{}", error);
+                }
+                None
+            }
+        };
+        Ok(Material {
+            base_color_texture: Some(texture),
+            normal_texture: optional_map("textures/Wood_Floor_011_normal.png"),
+            occlusion_texture: optional_map("textures/Wood_Floor_011_ambientOcclusion.png"),
+            ..Material::dielectric("wood", [1.0, 1.0, 1.0, 1.0], 0.6)
+        })
+    }
+
+    /// Assigns a new material, rebuilding its bind group and the render bundle.
+    pub fn set_material(&mut self, ctx: &Context, material: Material) {
+        self.material_binding = material.create_binding(ctx);
+        self.material = material;
+        self.recreate_render_bundles(ctx);
+    }
+
+    /// Uploads `model_matrix`. The cube stays where the gizmo and the other edits leave it;
+    /// it no longer spins by itself every frame, which made it impossible to place.
+    pub fn update(&mut self, queue: &wgpu::Queue) {
+        Self::update_inner(queue, &self.uniform_buffer, self.model_matrix);
+    }
+
+    fn create_render_bundle(&self, ctx: &Context, mode: ShadingMode) -> RenderBundle {
+        let mut render_bundle_encoder =
+            Self::begin_render_bundle(ctx, &self.bind_group, &self.material_binding);
+        render_bundle_encoder
+            .set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_bundle_encoder.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        for pipeline_fill in Self::fill_pipelines(
+            &self.pipelines_fill,
+            &self.pipelines_blend,
+            &self.material,
+            mode,
+        ) {
+            render_bundle_encoder.set_pipeline(pipeline_fill);
+            render_bundle_encoder.draw_indexed(0..(self.num_indicies as u32), 0, 0..1);
+        }
+
+        if mode.draws_wireframe() {
+            render_bundle_encoder.set_pipeline(&self.pipeline_wire);
+            match &self.wire_vertex_buffer {
+                Some(wire_vertex_buffer) => {
+                    render_bundle_encoder.set_vertex_buffer(0, wire_vertex_buffer.slice(..));
+                    render_bundle_encoder.draw(0..(self.num_indicies as u32), 0..1);
+                }
+                None => render_bundle_encoder.draw_indexed(0..(self.num_indicies as u32), 0, 0..1),
+            }
         }
 
         render_bundle_encoder.finish(&RenderBundleDescriptor { label: None })
     }
 }
+
+impl ShadowCaster for Cube {
+    fn draw_shadow<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>) {
+        rpass.set_pipeline(Self::shadow_pipeline(
+            &self.pipelines_shadow,
+            &self.material,
+        ));
+        rpass.set_bind_group(1, &self.bind_group, &[]);
+        rpass.set_bind_group(2, &self.material_binding.bind_group, &[]);
+        rpass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.draw_indexed(0..(self.num_indicies as u32), 0, 0..1);
+    }
+}
+
+impl OutlineCaster for Cube {
+    fn draw_outline_mask<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>) {
+        if !self.selected {
+            return;
+        }
+        rpass.set_pipeline(&self.pipeline_mask);
+        rpass.set_bind_group(1, &self.bind_group, &[]);
+        rpass.set_bind_group(2, &self.material_binding.bind_group, &[]);
+        rpass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.draw_indexed(0..(self.num_indicies as u32), 0, 0..1);
+    }
+}
@@ -0,0 +1,242 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use cgmath::{Matrix, Matrix4, Rad, SquareMatrix, Vector3};
+use wgpu::util::DeviceExt;
+use wgpu::{BufferUsages, TextureFormat};
+use winit::dpi::PhysicalSize;
+
+use super::{Cube, CubeInstances, CubeUniforms, WIRE_COLOR};
+use crate::bounds::CullStats;
+use crate::context::HDR_FORMAT;
+use crate::shading::ShadingMode;
+use crate::{Camera, Context};
+
+const WARMUP_FRAMES: u32 = 5;
+const MEASURED_FRAMES: u32 = 60;
+/// Distance between neighbouring copies.
+const SPACING: f32 = 2.5;
+/// Flat shaded, so both ways of drawing run the same single pipeline.
+const SHADING: ShadingMode = ShadingMode::Solid;
+
+/// Time taken to draw `count` copies of the cube with a uniform buffer, bind group and draw
+/// call each, like as many `Cube`s, and with `CubeInstances`.
+#[derive(Debug, Clone, Copy)]
+pub struct InstancingBenchmark {
+    pub count: usize,
+    /// Copies in the view of the camera, the same both ways.
+    pub cull_stats: CullStats,
+    /// Creating the buffers and bind groups, or adding the instances.
+    pub per_object_setup: Duration,
+    pub instanced_setup: Duration,
+    /// Moving every copy, uploading the transforms and drawing, until the GPU is done.
+    pub per_object_frame: Duration,
+    pub instanced_frame: Duration,
+}
+
+impl fmt::Display for InstancingBenchmark {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} copies ({} culled): per-object {:.2?} setup, {:.2?}/frame; instanced {:.2?} setup, {:.2?}/frame ({:.1}x)",
+            self.count,
+            self.cull_stats.culled,
+            self.per_object_setup,
+            self.per_object_frame,
+            self.instanced_setup,
+            self.instanced_frame,
+            self.per_object_frame.as_secs_f64() / self.instanced_frame.as_secs_f64().max(1e-9),
+        )
+    }
+}
+
+/// Renders `count` spinning copies of `cube` both ways through `camera` into an offscreen
+/// target of `size`, the size of the view the camera belongs to, culling those out of view
+/// and blocking until the GPU finishes each frame. Leaves the camera uniforms looking through
+/// `camera`.
+pub fn benchmark_instancing(
+    ctx: &Context,
+    cube: &Cube,
+    camera: &Camera,
+    size: PhysicalSize<u32>,
+    count: usize,
+) -> InstancingBenchmark {
+    let size = PhysicalSize::new(size.width.max(1), size.height.max(1));
+    ctx.global.update_camera(&ctx.queue, camera, size);
+    let frustum = camera.frustum(size.width as f32 / size.height as f32);
+    let extent = wgpu::Extent3d {
+        width: size.width,
+        height: size.height,
+        depth_or_array_layers: 1,
+    };
+    let create_target = |format| {
+        ctx.device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("instancing benchmark"),
+                size: extent,
+                mip_level_count: 1,
+                sample_count: ctx.sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    };
+    let color = create_target(HDR_FORMAT);
+    let depth = create_target(TextureFormat::Depth32Float);
+    let create_encoder = || {
+        ctx.device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None })
+    };
+
+    // per object: what `count` cubes would allocate and draw
+    let start = Instant::now();
+    let objects: Vec<_> = (0..count)
+        .map(|i| {
+            let uniform_buffer = ctx
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: None,
+                    contents: bytemuck::bytes_of(&object_uniforms(copy_transform(i, count, 0))),
+                    usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                });
+            let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &cube.bind_group_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                }],
+                label: None,
+            });
+            (uniform_buffer, bind_group)
+        })
+        .collect();
+    let per_object_setup = start.elapsed();
+    let mut cull_stats = CullStats::default();
+    let per_object_frame = time_frames(ctx, |frame| {
+        // only the objects in view are updated and drawn, like any culled scene would
+        let mut visible = Vec::with_capacity(count);
+        cull_stats = CullStats::default();
+        for (i, (uniform_buffer, bind_group)) in objects.iter().enumerate() {
+            let model_matrix = copy_transform(i, count, frame);
+            let in_view = frustum.intersects(&cube.bounds.transform(&model_matrix));
+            cull_stats.record(in_view);
+            if in_view {
+                let uniforms = object_uniforms(model_matrix);
+                ctx.queue
+                    .write_buffer(uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+                visible.push(bind_group);
+            }
+        }
+        let mut encoder = create_encoder();
+        {
+            let mut rpass = begin_pass(&mut encoder, &color, &depth);
+            if let Some(pipeline) = &cube.pipelines_fill[SHADING as usize] {
+                rpass.set_pipeline(pipeline);
+            }
+            rpass.set_bind_group(0, &ctx.global.bind_group, &[]);
+            rpass.set_bind_group(2, &cube.material_binding.bind_group, &[]);
+            rpass.set_index_buffer(cube.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            rpass.set_vertex_buffer(0, cube.vertex_buffer.slice(..));
+            for &bind_group in &visible {
+                rpass.set_bind_group(1, bind_group, &[]);
+                rpass.draw_indexed(0..cube.num_indicies as u32, 0, 0..1);
+            }
+        }
+        ctx.queue.submit(Some(encoder.finish()));
+    });
+    drop(objects);
+
+    let start = Instant::now();
+    let mut instances = CubeInstances::new(ctx, cube, cube.material.clone());
+    let ids: Vec<_> = (0..count)
+        .map(|i| instances.add(copy_transform(i, count, 0)))
+        .collect();
+    instances.upload(ctx);
+    let instanced_setup = start.elapsed();
+    let instanced_frame = time_frames(ctx, |frame| {
+        for (i, &id) in ids.iter().enumerate() {
+            instances.set_transform(id, copy_transform(i, count, frame));
+        }
+        instances.upload(ctx);
+        instances.cull(ctx, &frustum, camera.position());
+        let mut encoder = create_encoder();
+        begin_pass(&mut encoder, &color, &depth)
+            .execute_bundles(std::iter::once(instances.render_bundle(SHADING)));
+        ctx.queue.submit(Some(encoder.finish()));
+    });
+
+    InstancingBenchmark {
+        count,
+        cull_stats,
+        per_object_setup,
+        instanced_setup,
+        per_object_frame,
+        instanced_frame,
+    }
+}
+
+fn begin_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    color: &'a wgpu::TextureView,
+    depth: &'a wgpu::TextureView,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("instancing benchmark"),
+        color_attachments: &[wgpu::RenderPassColorAttachment {
+            view: color,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: true,
+            },
+        }],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: depth,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(1.0),
+                store: true,
+            }),
+            stencil_ops: None,
+        }),
+    })
+}
+
+/// Average time of the measured frames, each waited for.
+fn time_frames(ctx: &Context, mut frame: impl FnMut(u32)) -> Duration {
+    let mut start = Instant::now();
+    for i in 0..WARMUP_FRAMES + MEASURED_FRAMES {
+        if i == WARMUP_FRAMES {
+            start = Instant::now();
+        }
+        frame(i);
+        ctx.device.poll(wgpu::Maintain::Wait);
+    }
+    start.elapsed() / MEASURED_FRAMES
+}
+
+/// Copy `i` of `count` on a square grid around the origin, turned a bit further every frame.
+fn copy_transform(i: usize, count: usize, frame: u32) -> Matrix4<f32> {
+    let side = (count as f32).sqrt().ceil() as usize;
+    let offset = (side as f32 - 1.0) * 0.5;
+    let position = Vector3::new(
+        ((i % side) as f32 - offset) * SPACING,
+        0.0,
+        ((i / side) as f32 - offset) * SPACING,
+    );
+    Matrix4::from_translation(position)
+        * Matrix4::from_angle_y(Rad(frame as f32 * 0.02 + i as f32))
+        * Matrix4::from_scale(0.5)
+}
+
+fn object_uniforms(model_matrix: Matrix4<f32>) -> CubeUniforms {
+    let model_matrix_inverted_transposed = model_matrix
+        .invert()
+        .expect("failed to calculate inverse matrix of copy transform")
+        .transpose();
+    CubeUniforms {
+        model_matrix: *model_matrix.as_ref(),
+        model_matrix_inverted_transposed: *model_matrix_inverted_transposed.as_ref(),
+        wire_color: WIRE_COLOR,
+    }
+}
@@ -1,18 +1,17 @@
 struct GlobalUniforms {
     vp_matrix: mat4x4<f32>;
     camera_position: vec3<f32>;
-    point_light_position: vec3<f32>;
-    inf_light_direction: vec3<f32>;
 };
 
 struct LocalUniforms {
     model_matrix: mat4x4<f32>;
     model_matrix_inverted: mat4x4<f32>;
+    wire_color: vec4<f32>;
 };
 
 [[group(0), binding(0)]]
 var<uniform> u_global: GlobalUniforms;
-[[group(1), binding(1)]]
+[[group(1), binding(0)]]
 var<uniform> u_local: LocalUniforms;
 
 
@@ -20,49 +19,205 @@ struct VertexOutput {
     [[location(0)]] uv: vec2<f32>;
     [[location(1)]] normal: vec3<f32>;
     [[location(2)]] pos: vec3<f32>;
+    [[location(3)]] tangent: vec4<f32>;
     [[builtin(position)]] position: vec4<f32>;
 };
 
+struct InstanceInput {
+    [[location(4)]] model_matrix_0: vec4<f32>;
+    [[location(5)]] model_matrix_1: vec4<f32>;
+    [[location(6)]] model_matrix_2: vec4<f32>;
+    [[location(7)]] model_matrix_3: vec4<f32>;
+    [[location(8)]] normal_matrix_0: vec3<f32>;
+    [[location(9)]] normal_matrix_1: vec3<f32>;
+    [[location(10)]] normal_matrix_2: vec3<f32>;
+};
+
+fn instance_model_matrix(instance: InstanceInput) -> mat4x4<f32> {
+    return mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+}
+
+fn transform_vertex(
+    model_matrix: mat4x4<f32>,
+    normal_matrix: mat3x3<f32>,
+    a_position: vec3<f32>,
+    a_uv: vec2<f32>,
+    a_normal: vec3<f32>,
+    a_tangent: vec4<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.uv = a_uv;
+    out.normal = normal_matrix * a_normal;
+    let tangent = (model_matrix * vec4<f32>(a_tangent.xyz, 0.0)).xyz;
+    out.tangent = vec4<f32>(tangent, a_tangent.w);
+    let world_position = model_matrix * vec4<f32>(a_position, 1.0);
+    out.position = u_global.vp_matrix * world_position;
+    out.pos = world_position.xyz;
+    return out;
+}
+
 [[stage(vertex)]]
 fn vs_main(
     [[location(0)]] a_position: vec3<f32>,
     [[location(1)]] a_uv: vec2<f32>,
     [[location(2)]] a_normal: vec3<f32>,
+    [[location(3)]] a_tangent: vec4<f32>,
 ) -> VertexOutput {
-    var out: VertexOutput;
-    out.uv = a_uv;
-    out.normal = (u_local.model_matrix_inverted * vec4<f32>(a_normal, 1.0)).xyz;
-    out.position =  u_global.vp_matrix * u_local.model_matrix * vec4<f32>(a_position, 1.0);
-    out.pos = a_position;
-    return out;
+    let inverted = u_local.model_matrix_inverted;
+    let normal_matrix = mat3x3<f32>(inverted[0].xyz, inverted[1].xyz, inverted[2].xyz);
+    return transform_vertex(
+        u_local.model_matrix,
+        normal_matrix,
+        a_position,
+        a_uv,
+        a_normal,
+        a_tangent,
+    );
 }
 
-[[group(1), binding(0)]]
-var r_color: texture_2d<u32>;
+// placed by a per-instance model matrix instead of the local uniforms
+[[stage(vertex)]]
+fn vs_instanced(
+    [[location(0)]] a_position: vec3<f32>,
+    [[location(1)]] a_uv: vec2<f32>,
+    [[location(2)]] a_normal: vec3<f32>,
+    [[location(3)]] a_tangent: vec4<f32>,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
+    return transform_vertex(
+        instance_model_matrix(instance),
+        normal_matrix,
+        a_position,
+        a_uv,
+        a_normal,
+        a_tangent,
+    );
+}
 
 [[stage(fragment)]]
 fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
-    let tex = textureLoad(r_color, vec2<i32>(in.uv * 1024.0), 0);
-    let color = vec3<f32>(tex.rgb) / 255.0;
-    // let v = f32(tex.x) / 255.0;
-    // let color = vec3<f32>(1.0 - (v * 5.0), 1.0 - (v * 15.0), 1.0 - (v * 50.0));
-
-    let normal = normalize(in.normal);
-    let incidence = normalize(in.pos - u_global.point_light_position);
-    let reflected = reflect(incidence, normal);
-    let half_vector = normalize(incidence + in.pos - u_global.camera_position);
+    let base_color = material_base_color(in.uv);
+    if (material_discards(base_color.a)) {
+        discard;
+    }
+    let metallic_roughness = material_metallic_roughness(in.uv);
+    let normal = material_normal(in.uv, in.normal, in.tangent);
+    let lit = shade_lights(
+        base_color.rgb,
+        metallic_roughness.x,
+        metallic_roughness.y,
+        material_occlusion(in.uv),
+        in.pos,
+        normal,
+        u_global.camera_position,
+    );
 
-    let ambient = color * vec3<f32>(0.4);
-    let diffuse = color * max(dot(-incidence, normal), 0.0);
-    let specular = pow(max(dot(-half_vector, normal), 0.0), 50.0);
+    return vec4<f32>(lit + material_emissive(in.uv), base_color.a);
+}
 
-    return vec4<f32>(ambient + diffuse + specular, 1.0);
-    // return vec4<f32>(specular, 1.0);
-    // return vec4<f32>(color, 1.0);
+// writes depth only, for the shadows of masked materials
+[[stage(fragment)]]
+fn fs_shadow_masked(in: VertexOutput) {
+    if (material_discards(material_base_color(in.uv).a)) {
+        discard;
+    }
 }
 
 [[stage(fragment)]]
 fn fs_wire() -> [[location(0)]] vec4<f32> {
-    return vec4<f32>(0.0, 0.5, 0.0, 0.5);
+    return u_local.wire_color;
+}
+
+// lit from the camera, so every face the viewer sees is visible
+fn headlight(normal: vec3<f32>, position: vec3<f32>) -> f32 {
+    let view = normalize(u_global.camera_position - position);
+    return 0.2 + 0.8 * abs(dot(normal, view));
+}
+
+[[stage(fragment)]]
+fn fs_solid(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // flat, from the screen-space derivatives of the position
+    let normal = normalize(cross(dpdx(in.pos), dpdy(in.pos)));
+    return vec4<f32>(vec3<f32>(0.8 * headlight(normal, in.pos)), 1.0);
 }
 
+[[stage(fragment)]]
+fn fs_normals(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let normal = material_normal(in.uv, in.normal, in.tangent);
+    return vec4<f32>(normal * 0.5 + vec3<f32>(0.5), 1.0);
+}
+
+let UV_CHECKER_CELLS: f32 = 8.0;
+
+[[stage(fragment)]]
+fn fs_uv_checker(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let cell = vec2<i32>(floor(in.uv * UV_CHECKER_CELLS));
+    let checker = mix(0.25, 0.85, f32((cell.x + cell.y) & 1));
+    // tinted by the coordinates, so the orientation shows
+    let tint = vec3<f32>(0.6 + 0.4 * fract(in.uv), 0.8);
+    let lighting = headlight(normalize(in.normal), in.pos);
+    return vec4<f32>(checker * tint * lighting, 1.0);
+}
+
+let DEPTH_VISUALIZATION_RANGE: f32 = 20.0;
+
+[[stage(fragment)]]
+fn fs_depth(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let depth = distance(in.pos, u_global.camera_position) / DEPTH_VISUALIZATION_RANGE;
+    return vec4<f32>(vec3<f32>(1.0 - clamp(depth, 0.0, 1.0)), 1.0);
+}
+
+struct WireOutput {
+    [[location(0)]] barycentric: vec3<f32>;
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+// fallback without line rasterization: triangles that only cover their edges
+[[stage(vertex)]]
+fn vs_wire_barycentric(
+    [[location(0)]] a_position: vec3<f32>,
+    [[location(1)]] a_barycentric: vec3<f32>,
+) -> WireOutput {
+    var out: WireOutput;
+    out.barycentric = a_barycentric;
+    out.position = u_global.vp_matrix * u_local.model_matrix * vec4<f32>(a_position, 1.0);
+    return out;
+}
+
+[[stage(vertex)]]
+fn vs_wire_barycentric_instanced(
+    [[location(0)]] a_position: vec3<f32>,
+    [[location(1)]] a_barycentric: vec3<f32>,
+    instance: InstanceInput,
+) -> WireOutput {
+    var out: WireOutput;
+    out.barycentric = a_barycentric;
+    out.position = u_global.vp_matrix * instance_model_matrix(instance) * vec4<f32>(a_position, 1.0);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_wire_barycentric(in: WireOutput) -> [[location(0)]] vec4<f32> {
+    // distance to the nearest edge, in pixels
+    let edge = in.barycentric / max(fwidth(in.barycentric), vec3<f32>(0.0001));
+    let coverage = 1.0 - clamp(min(edge.x, min(edge.y, edge.z)) - 0.5, 0.0, 1.0);
+    if (coverage <= 0.0) {
+        discard;
+    }
+    return vec4<f32>(u_local.wire_color.rgb, u_local.wire_color.a * coverage);
+}
+
+[[stage(fragment)]]
+fn fs_mask() -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(1.0);
+}
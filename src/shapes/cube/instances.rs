@@ -0,0 +1,490 @@
+use std::collections::HashMap;
+use std::mem;
+use std::ops::Range;
+
+use bytemuck::{Pod, Zeroable};
+use cgmath::{InnerSpace, Matrix, Matrix3, Matrix4, Point3, SquareMatrix};
+use wgpu::util::DeviceExt;
+use wgpu::{
+    BindGroup, Buffer, BufferUsages, Features, RenderBundle, RenderBundleDescriptor, RenderPipeline,
+};
+
+use super::polygon::{INDICES, VERTICES};
+use super::{Cube, CubeUniforms, NON_INDEXED_ARGS_OFFSET, WIRE_COLOR};
+use crate::bounds::{Bounds, CullStats, Frustum};
+use crate::material::{AlphaMode, Material, MaterialBinding};
+use crate::shading::ShadingMode;
+use crate::{Context, ShadowCaster};
+
+/// Instances the buffer has room for before it first grows.
+const INITIAL_CAPACITY: usize = 64;
+/// Byte offset of the arguments drawing each visible instance on its own, after the four of
+/// the wireframe draw.
+const INSTANCE_ARGS_OFFSET: wgpu::BufferAddress =
+    NON_INDEXED_ARGS_OFFSET + 4 * mem::size_of::<u32>() as u64;
+const INSTANCE_ARGS_SIZE: wgpu::BufferAddress = 5 * mem::size_of::<u32>() as u64;
+
+/// Per-instance vertex data: the model matrix and the matrix transforming normals.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct InstanceRaw {
+    model_matrix: [[f32; 4]; 4],
+    normal_matrix: [[f32; 3]; 3],
+}
+
+impl InstanceRaw {
+    fn new(model_matrix: Matrix4<f32>) -> Self {
+        let normal_matrix = model_matrix
+            .invert()
+            .expect("failed to calculate inverse matrix of instance transform")
+            .transpose();
+        let normal_matrix = Matrix3::from_cols(
+            normal_matrix.x.truncate(),
+            normal_matrix.y.truncate(),
+            normal_matrix.z.truncate(),
+        );
+        InstanceRaw {
+            model_matrix: model_matrix.into(),
+            normal_matrix: normal_matrix.into(),
+        }
+    }
+}
+
+/// Layout of the instance buffer, following the vertices at locations 4 to 10.
+pub(super) fn instance_buffer_layout() -> wgpu::VertexBufferLayout<'static> {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 7] = wgpu::vertex_attr_array![
+        4 => Float32x4,
+        5 => Float32x4,
+        6 => Float32x4,
+        7 => Float32x4,
+        8 => Float32x3,
+        9 => Float32x3,
+        10 => Float32x3,
+    ];
+    wgpu::VertexBufferLayout {
+        array_stride: mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Instance,
+        attributes: &ATTRIBUTES,
+    }
+}
+
+/// Handle of an instance of `CubeInstances`, valid until the instance is removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InstanceId(u32);
+
+/// Copies of the cube mesh, all drawn by a single instanced draw with one material. Each copy
+/// is placed by its own model matrix in a per-instance vertex buffer instead of a uniform
+/// buffer and bind group of its own.
+///
+/// Edits are collected on the CPU and written by `upload`, once per frame, as one range of the
+/// buffer. The instances are kept packed, so removing one moves the last into its place.
+///
+/// Shadows are cast by every instance, but each viewport only draws those `cull` found in its
+/// frustum, packed into a second buffer and counted by an indirect draw, so the render
+/// bundles stay valid whichever are visible.
+///
+/// A blended material cannot be drawn by a single draw: the instances have to cover each
+/// other, and the other blended meshes, from the farthest to the nearest. The visible
+/// instances are then packed farthest first, and each is drawn by a render bundle of its own,
+/// back faces then front faces, to sort among the other blended meshes; see `blended_bundles`.
+pub struct CubeInstances {
+    pub material: Material,
+    pub material_binding: MaterialBinding,
+    // in the order of the instance buffer
+    instances: Vec<InstanceRaw>,
+    // world-space bounds of each instance, in the same order
+    bounds: Vec<Bounds>,
+    mesh_bounds: Bounds,
+    // scratch space for the instances that passed `cull`, and their squared distances to the
+    // eye with their slots
+    visible: Vec<InstanceRaw>,
+    visible_order: Vec<(f32, usize)>,
+    ids: Vec<InstanceId>,
+    // position in `instances` of every live instance
+    slots: HashMap<InstanceId, usize>,
+    next_id: u32,
+    // part of `instances` edited since the last upload
+    dirty: Option<Range<usize>>,
+
+    instance_buffer: Buffer,
+    visible_buffer: Buffer,
+    indirect_buffer: Buffer,
+    capacity: usize,
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    wire_vertex_buffer: Option<Buffer>,
+    // the identity transform and the wire color, for the fragment stages shared with `Cube`
+    _uniform_buffer: Buffer,
+    bind_group: BindGroup,
+    pipelines_fill: Vec<Option<RenderPipeline>>,
+    pipelines_blend: Vec<RenderPipeline>,
+    pipeline_wire: RenderPipeline,
+    pipelines_shadow: Vec<RenderPipeline>,
+    // indexed by `ShadingMode`
+    render_bundles: Vec<RenderBundle>,
+    // drawing the visible instances one by one, farthest first, with a blended material
+    instance_bundles: Vec<RenderBundle>,
+}
+
+impl CubeInstances {
+    /// No instances yet, drawn with `material` and the shader and pipeline layout of `cube`.
+    pub fn new(ctx: &Context, cube: &Cube, material: Material) -> Self {
+        let mut vertices = VERTICES.to_vec();
+        Cube::update_tangents(&mut vertices);
+        let positions: Vec<_> = vertices.iter().map(|v| Point3::from(v.a_pos)).collect();
+        let mesh_bounds = Bounds::from_points(&positions).expect("the cube has no vertices");
+        let vertex_buffer = ctx
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Instanced Vertex Buffer"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: BufferUsages::VERTEX,
+            });
+        let index_buffer = ctx
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Instanced Index Buffer"),
+                contents: bytemuck::cast_slice(INDICES),
+                usage: BufferUsages::INDEX,
+            });
+        let wire_vertex_buffer = (!ctx.device.features().contains(Features::POLYGON_MODE_LINE))
+            .then(|| {
+                ctx.device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("Instanced Wire Vertex Buffer"),
+                        contents: bytemuck::cast_slice(&Cube::wire_vertices(&vertices, INDICES)),
+                        usage: BufferUsages::VERTEX,
+                    })
+            });
+
+        let identity: [f32; 16] = *Matrix4::identity().as_ref();
+        let uniform_buffer = ctx
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::bytes_of(&CubeUniforms {
+                    model_matrix: identity,
+                    model_matrix_inverted_transposed: identity,
+                    wire_color: WIRE_COLOR,
+                }),
+                usage: BufferUsages::UNIFORM,
+            });
+        let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &cube.bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+            label: None,
+        });
+
+        let (pipelines_fill, pipelines_blend, pipeline_wire) =
+            Cube::create_pipelines(ctx, &cube.pipeline_layout, &cube.shader, true);
+        let pipelines_shadow =
+            Cube::create_shadow_pipelines(ctx, &cube.bind_group_layout, &cube.shader, true);
+
+        let mut instances = CubeInstances {
+            material_binding: material.create_binding(ctx),
+            material,
+            instances: Vec::new(),
+            ids: Vec::new(),
+            slots: HashMap::new(),
+            bounds: Vec::new(),
+            mesh_bounds,
+            visible: Vec::new(),
+            visible_order: Vec::new(),
+            next_id: 0,
+            dirty: None,
+            instance_buffer: Self::create_instance_buffer(ctx, INITIAL_CAPACITY),
+            visible_buffer: Self::create_instance_buffer(ctx, INITIAL_CAPACITY),
+            indirect_buffer: Self::create_indirect_buffer(ctx, INITIAL_CAPACITY),
+            capacity: INITIAL_CAPACITY,
+            vertex_buffer,
+            index_buffer,
+            wire_vertex_buffer,
+            _uniform_buffer: uniform_buffer,
+            bind_group,
+            pipelines_fill,
+            pipelines_blend,
+            pipeline_wire,
+            pipelines_shadow,
+            render_bundles: Vec::new(),
+            instance_bundles: Vec::new(),
+        };
+        instances.recreate_render_bundles(ctx);
+        instances
+    }
+
+    fn create_instance_buffer(ctx: &Context, capacity: usize) -> Buffer {
+        ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instance Buffer"),
+            size: (capacity * mem::size_of::<InstanceRaw>()) as u64,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_indirect_buffer(ctx: &Context, capacity: usize) -> Buffer {
+        ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instance Indirect Buffer"),
+            size: INSTANCE_ARGS_OFFSET + capacity as u64 * INSTANCE_ARGS_SIZE,
+            usage: BufferUsages::INDIRECT | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    pub fn add(&mut self, model_matrix: Matrix4<f32>) -> InstanceId {
+        let id = InstanceId(self.next_id);
+        self.next_id += 1;
+        self.slots.insert(id, self.instances.len());
+        self.instances.push(InstanceRaw::new(model_matrix));
+        self.bounds.push(self.mesh_bounds.transform(&model_matrix));
+        self.ids.push(id);
+        self.mark_dirty(self.instances.len() - 1);
+        id
+    }
+
+    /// Returns whether `id` was an instance.
+    pub fn remove(&mut self, id: InstanceId) -> bool {
+        let slot = match self.slots.remove(&id) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        self.instances.swap_remove(slot);
+        self.bounds.swap_remove(slot);
+        self.ids.swap_remove(slot);
+        if let Some(&moved) = self.ids.get(slot) {
+            self.slots.insert(moved, slot);
+            self.mark_dirty(slot);
+        }
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.instances.clear();
+        self.bounds.clear();
+        self.ids.clear();
+        self.slots.clear();
+        self.dirty = None;
+    }
+
+    /// Places the instance `id`, if it exists.
+    pub fn set_transform(&mut self, id: InstanceId, model_matrix: Matrix4<f32>) {
+        if let Some(&slot) = self.slots.get(&id) {
+            self.instances[slot] = InstanceRaw::new(model_matrix);
+            self.bounds[slot] = self.mesh_bounds.transform(&model_matrix);
+            self.mark_dirty(slot);
+        }
+    }
+
+    pub fn transform(&self, id: InstanceId) -> Option<Matrix4<f32>> {
+        self.slots
+            .get(&id)
+            .map(|&slot| self.instances[slot].model_matrix.into())
+    }
+
+    fn mark_dirty(&mut self, slot: usize) {
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => dirty.start.min(slot)..dirty.end.max(slot + 1),
+            None => slot..slot + 1,
+        });
+    }
+
+    /// Writes the instances edited since the last upload, growing the buffers and re-recording
+    /// the render bundles if they no longer fit.
+    pub fn upload(&mut self, ctx: &Context) {
+        if self.instances.len() > self.capacity {
+            self.capacity = self.instances.len().next_power_of_two();
+            self.instance_buffer = Self::create_instance_buffer(ctx, self.capacity);
+            self.visible_buffer = Self::create_instance_buffer(ctx, self.capacity);
+            self.indirect_buffer = Self::create_indirect_buffer(ctx, self.capacity);
+            self.dirty = Some(0..self.instances.len());
+            self.recreate_render_bundles(ctx);
+        }
+        if let Some(dirty) = self.dirty.take() {
+            let dirty = dirty.start..dirty.end.min(self.instances.len());
+            if !dirty.is_empty() {
+                ctx.queue.write_buffer(
+                    &self.instance_buffer,
+                    (dirty.start * mem::size_of::<InstanceRaw>()) as u64,
+                    bytemuck::cast_slice(&self.instances[dirty]),
+                );
+            }
+        }
+    }
+
+    /// Makes the render bundles draw only the instances within `frustum`, from the farthest to
+    /// the nearest to `eye`, until the next call. Call it after `upload`, for each viewport
+    /// before recording its draws.
+    pub fn cull(&mut self, ctx: &Context, frustum: &Frustum, eye: Point3<f32>) -> CullStats {
+        self.visible_order.clear();
+        let mut stats = CullStats::default();
+        for (slot, bounds) in self.bounds.iter().enumerate() {
+            let visible = frustum.intersects(bounds);
+            stats.record(visible);
+            if visible {
+                let distance2 = (bounds.sphere.center - eye).magnitude2();
+                self.visible_order.push((distance2, slot));
+            }
+        }
+        self.visible_order
+            .sort_unstable_by(|a, b| b.0.total_cmp(&a.0));
+        self.visible.clear();
+        self.visible.extend(
+            self.visible_order
+                .iter()
+                .map(|&(_, slot)| self.instances[slot]),
+        );
+        if !self.visible.is_empty() {
+            ctx.queue
+                .write_buffer(&self.visible_buffer, 0, bytemuck::cast_slice(&self.visible));
+        }
+        let count = INDICES.len() as u32;
+        let instance_count = self.visible.len() as u32;
+        let args: [u32; 9] = [count, instance_count, 0, 0, 0, count, instance_count, 0, 0];
+        ctx.queue
+            .write_buffer(&self.indirect_buffer, 0, bytemuck::cast_slice(&args));
+        if self.material.alpha_mode == AlphaMode::Blend {
+            // one instance each, nothing for the bundles past the visible ones
+            let args: Vec<[u32; 5]> = (0..self.capacity as u32)
+                .map(|slot| [count, u32::from(slot < instance_count), 0, 0, 0])
+                .collect();
+            ctx.queue.write_buffer(
+                &self.indirect_buffer,
+                INSTANCE_ARGS_OFFSET,
+                bytemuck::cast_slice(&args),
+            );
+        }
+        stats
+    }
+
+    /// Triangles drawn for the instances passing the last `cull`.
+    pub fn triangle_count(&self) -> usize {
+        self.visible.len() * INDICES.len() / 3
+    }
+
+    /// The distance to the eye of each instance passing the last `cull` and the render bundle
+    /// drawing it, if the material blends in `mode`. `render_bundle` draws none of them then.
+    pub fn blended_bundles(&self, mode: ShadingMode) -> impl Iterator<Item = (f32, &RenderBundle)> {
+        let blends = self.material.blends(mode);
+        self.visible_order
+            .iter()
+            .zip(&self.instance_bundles)
+            .filter(move |_| blends)
+            .map(|(&(distance2, _), bundle)| (distance2.sqrt(), bundle))
+    }
+
+    /// Rebuilds the pipelines and render bundles after the MSAA sample count changed.
+    pub fn recreate_pipelines(&mut self, ctx: &Context, cube: &Cube) {
+        let (pipelines_fill, pipelines_blend, pipeline_wire) =
+            Cube::create_pipelines(ctx, &cube.pipeline_layout, &cube.shader, true);
+        self.pipelines_fill = pipelines_fill;
+        self.pipelines_blend = pipelines_blend;
+        self.pipeline_wire = pipeline_wire;
+        self.recreate_render_bundles(ctx);
+    }
+
+    /// Replaces the material every instance is drawn with, as `Cube::set_material` does.
+    pub fn set_material(&mut self, ctx: &Context, material: Material) {
+        self.material_binding = material.create_binding(ctx);
+        self.material = material;
+        self.recreate_render_bundles(ctx);
+    }
+
+    fn recreate_render_bundles(&mut self, ctx: &Context) {
+        self.render_bundles = ShadingMode::ALL
+            .iter()
+            .map(|&mode| self.create_render_bundle(ctx, mode))
+            .collect();
+        self.instance_bundles = match self.material.alpha_mode {
+            AlphaMode::Blend => (0..self.capacity)
+                .map(|slot| self.create_instance_bundle(ctx, slot))
+                .collect(),
+            AlphaMode::Opaque | AlphaMode::Mask => Vec::new(),
+        };
+    }
+
+    /// Draws the instances passing the last `cull` in the HDR scene pass of a viewport shaded
+    /// with `mode`, but for the blended faces of `blended_bundles`.
+    pub fn render_bundle(&self, mode: ShadingMode) -> &RenderBundle {
+        &self.render_bundles[mode as usize]
+    }
+
+    /// An encoder with the bind groups and buffers of the instances passing the last `cull`.
+    fn begin_render_bundle<'a>(&'a self, ctx: &'a Context) -> wgpu::RenderBundleEncoder<'a> {
+        let mut render_bundle_encoder =
+            Cube::begin_render_bundle(ctx, &self.bind_group, &self.material_binding);
+        render_bundle_encoder
+            .set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_bundle_encoder.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_bundle_encoder.set_vertex_buffer(1, self.visible_buffer.slice(..));
+        render_bundle_encoder
+    }
+
+    fn create_render_bundle(&self, ctx: &Context, mode: ShadingMode) -> RenderBundle {
+        let mut render_bundle_encoder = self.begin_render_bundle(ctx);
+        let pipeline_fill = self.pipelines_fill[mode as usize]
+            .as_ref()
+            .filter(|_| !self.material.blends(mode));
+        if let Some(pipeline_fill) = pipeline_fill {
+            render_bundle_encoder.set_pipeline(pipeline_fill);
+            render_bundle_encoder.draw_indexed_indirect(&self.indirect_buffer, 0);
+        }
+
+        if mode.draws_wireframe() {
+            render_bundle_encoder.set_pipeline(&self.pipeline_wire);
+            match &self.wire_vertex_buffer {
+                Some(wire_vertex_buffer) => {
+                    render_bundle_encoder.set_vertex_buffer(0, wire_vertex_buffer.slice(..));
+                    render_bundle_encoder
+                        .draw_indirect(&self.indirect_buffer, NON_INDEXED_ARGS_OFFSET);
+                }
+                None => render_bundle_encoder.draw_indexed_indirect(&self.indirect_buffer, 0),
+            }
+        }
+
+        render_bundle_encoder.finish(&RenderBundleDescriptor { label: None })
+    }
+
+    /// Draws the `slot`th farthest instance passing the last `cull`, if there is one, with
+    /// the blending pipelines.
+    fn create_instance_bundle(&self, ctx: &Context, slot: usize) -> RenderBundle {
+        let mut render_bundle_encoder = self.begin_render_bundle(ctx);
+        // the instance comes first in the buffer, since a first instance in indirect
+        // arguments needs a feature
+        let instance_offset = (slot * mem::size_of::<InstanceRaw>()) as u64;
+        render_bundle_encoder.set_vertex_buffer(1, self.visible_buffer.slice(instance_offset..));
+        let offset = INSTANCE_ARGS_OFFSET + slot as u64 * INSTANCE_ARGS_SIZE;
+        for pipeline_blend in &self.pipelines_blend {
+            render_bundle_encoder.set_pipeline(pipeline_blend);
+            render_bundle_encoder.draw_indexed_indirect(&self.indirect_buffer, offset);
+        }
+        render_bundle_encoder.finish(&RenderBundleDescriptor { label: None })
+    }
+}
+
+impl ShadowCaster for CubeInstances {
+    fn draw_shadow<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>) {
+        if self.instances.is_empty() {
+            return;
+        }
+        rpass.set_pipeline(Cube::shadow_pipeline(
+            &self.pipelines_shadow,
+            &self.material,
+        ));
+        rpass.set_bind_group(1, &self.bind_group, &[]);
+        rpass.set_bind_group(2, &self.material_binding.bind_group, &[]);
+        rpass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        rpass.draw_indexed(0..INDICES.len() as u32, 0, 0..self.instances.len() as u32);
+    }
+}
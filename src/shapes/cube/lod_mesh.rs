@@ -0,0 +1,285 @@
+use std::mem;
+use std::ops::Range;
+
+use cgmath::{Matrix4, Point3, SquareMatrix};
+use wgpu::util::DeviceExt;
+use wgpu::{
+    BindGroup, Buffer, BufferUsages, Features, RenderBundle, RenderBundleDescriptor, RenderPipeline,
+};
+
+use super::polygon::Vertex;
+use super::{Cube, CubeUniforms, NON_INDEXED_ARGS_OFFSET, WIRE_COLOR};
+use crate::bounds::Bounds;
+use crate::material::{Material, MaterialBinding};
+use crate::mesh::{generate_tangents, smooth_normals, LodChain, LodLevel};
+use crate::shading::ShadingMode;
+use crate::{Camera, Context, ShadowCaster};
+
+/// A dense mesh, such as a scan, drawn at the level of detail its size on screen calls for,
+/// with the shader and pipelines of a `Cube` and a material of its own.
+///
+/// The levels share one vertex buffer and follow each other in the index buffer. `select_lod`
+/// points an indirect draw at one of them, so the render bundles stay valid whichever is
+/// drawn.
+pub struct LodMesh {
+    pub model_matrix: Matrix4<f32>,
+    /// Drawn instead of the level chosen by size, if set.
+    pub forced_level: Option<usize>,
+    pub material: Material,
+    pub material_binding: MaterialBinding,
+    chain: LodChain,
+    // of each level in the index buffer
+    ranges: Vec<Range<u32>>,
+    level: usize,
+    // object space
+    bounds: Bounds,
+
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    wire_vertex_buffer: Option<Buffer>,
+    indirect_buffer: Buffer,
+    uniform_buffer: Buffer,
+    bind_group: BindGroup,
+    pipelines_shadow: Vec<RenderPipeline>,
+    // indexed by `ShadingMode`
+    render_bundles: Vec<RenderBundle>,
+}
+
+impl LodMesh {
+    /// Generates the levels of detail of the triangles `indices` makes of `positions`, with
+    /// smooth normals.
+    pub fn new(
+        ctx: &Context,
+        cube: &Cube,
+        material: Material,
+        positions: &[[f32; 3]],
+        uvs: &[[f32; 2]],
+        indices: &[u32],
+    ) -> Self {
+        let chain = LodChain::generate(positions, indices, 5, 0.25);
+        let mut ranges = Vec::with_capacity(chain.levels.len());
+        let mut all_indices = Vec::new();
+        for level in &chain.levels {
+            let start = all_indices.len() as u32;
+            all_indices.extend_from_slice(&level.indices);
+            ranges.push(start..all_indices.len() as u32);
+        }
+        log::info!(
+            "levels of detail: {:?} triangles",
+            chain
+                .levels
+                .iter()
+                .map(LodLevel::triangle_count)
+                .collect::<Vec<_>>()
+        );
+
+        let normals = smooth_normals(positions, indices);
+        let tangents = generate_tangents(positions, &normals, uvs, indices);
+        let vertices: Vec<Vertex> = positions
+            .iter()
+            .zip(uvs)
+            .zip(normals.iter().zip(tangents))
+            .map(|((&a_pos, &a_uv), (&a_normal, a_tangent))| Vertex {
+                a_pos,
+                a_uv,
+                a_normal,
+                a_tangent,
+            })
+            .collect();
+        let points: Vec<_> = positions.iter().map(|&p| Point3::from(p)).collect();
+        let bounds = Bounds::from_points(&points).expect("the mesh has no vertices");
+
+        let vertex_buffer = ctx
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("LOD Vertex Buffer"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: BufferUsages::VERTEX,
+            });
+        let index_buffer = ctx
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("LOD Index Buffer"),
+                contents: bytemuck::cast_slice(&all_indices),
+                usage: BufferUsages::INDEX,
+            });
+        let wire_vertex_buffer = (!ctx.device.features().contains(Features::POLYGON_MODE_LINE))
+            .then(|| {
+                ctx.device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("LOD Wire Vertex Buffer"),
+                        contents: bytemuck::cast_slice(&Cube::wire_vertices(
+                            &vertices,
+                            &all_indices,
+                        )),
+                        usage: BufferUsages::VERTEX,
+                    })
+            });
+        let indirect_buffer = ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("LOD Indirect Buffer"),
+            size: NON_INDEXED_ARGS_OFFSET + 4 * mem::size_of::<u32>() as u64,
+            usage: BufferUsages::INDIRECT | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let model_matrix = Matrix4::identity();
+        let uniform_buffer = ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            size: mem::size_of::<CubeUniforms>() as u64,
+            mapped_at_creation: false,
+        });
+        Cube::update_inner(&ctx.queue, &uniform_buffer, model_matrix);
+        Cube::write_wire_color(&ctx.queue, &uniform_buffer, WIRE_COLOR);
+        let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &cube.bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+            label: None,
+        });
+        let pipelines_shadow =
+            Cube::create_shadow_pipelines(ctx, &cube.bind_group_layout, &cube.shader, false);
+
+        let mut lod_mesh = LodMesh {
+            model_matrix,
+            forced_level: None,
+            material_binding: material.create_binding(ctx),
+            material,
+            chain,
+            ranges,
+            level: 0,
+            bounds,
+            vertex_buffer,
+            index_buffer,
+            wire_vertex_buffer,
+            indirect_buffer,
+            uniform_buffer,
+            bind_group,
+            pipelines_shadow,
+            render_bundles: Vec::new(),
+        };
+        lod_mesh.write_draw_args(&ctx.queue);
+        lod_mesh.recreate_render_bundles(ctx, cube);
+        lod_mesh
+    }
+
+    /// From the full mesh down to the coarsest level.
+    pub fn levels(&self) -> &[LodLevel] {
+        &self.chain.levels
+    }
+
+    /// The level picked by the last `select_lod`.
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    /// Triangles of the level picked by the last `select_lod`.
+    pub fn triangle_count(&self) -> usize {
+        self.chain.levels[self.level].triangle_count()
+    }
+
+    /// Bounding volumes in world space, to cull the mesh with.
+    pub fn world_bounds(&self) -> Bounds {
+        self.bounds.transform(&self.model_matrix)
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue) {
+        Cube::update_inner(queue, &self.uniform_buffer, self.model_matrix);
+    }
+
+    /// Picks the level to draw the mesh and its shadows with, until the next call, for how
+    /// large it appears through `camera` on a viewport `viewport_height` pixels high. Call it
+    /// for each viewport before recording its draws.
+    pub fn select_lod(&mut self, ctx: &Context, camera: &Camera, viewport_height: f32) -> usize {
+        let level = match self.forced_level {
+            Some(level) => level.min(self.ranges.len() - 1),
+            None => {
+                let sphere = self.world_bounds().sphere;
+                self.chain
+                    .select(camera.projected_size(&sphere, viewport_height))
+            }
+        };
+        if level != self.level {
+            log::debug!("level of detail: {}", level);
+        }
+        self.level = level;
+        self.write_draw_args(&ctx.queue);
+        level
+    }
+
+    fn write_draw_args(&self, queue: &wgpu::Queue) {
+        let range = &self.ranges[self.level];
+        let count = range.end - range.start;
+        let args: [u32; 9] = [count, 1, range.start, 0, 0, count, 1, range.start, 0];
+        queue.write_buffer(&self.indirect_buffer, 0, bytemuck::cast_slice(&args));
+    }
+
+    /// Replaces the material of the mesh, as `Cube::set_material` does. The render bundles are
+    /// recorded again with the pipelines of `cube`.
+    pub fn set_material(&mut self, ctx: &Context, cube: &Cube, material: Material) {
+        self.material_binding = material.create_binding(ctx);
+        self.material = material;
+        self.recreate_render_bundles(ctx, cube);
+    }
+
+    /// Re-records the render bundles, to follow the pipelines of `cube`.
+    pub fn recreate_render_bundles(&mut self, ctx: &Context, cube: &Cube) {
+        self.render_bundles = ShadingMode::ALL
+            .iter()
+            .map(|&mode| self.create_render_bundle(ctx, cube, mode))
+            .collect();
+    }
+
+    /// Draws the level picked by the last `select_lod` in the HDR scene pass of a viewport
+    /// shaded with `mode`.
+    pub fn render_bundle(&self, mode: ShadingMode) -> &RenderBundle {
+        &self.render_bundles[mode as usize]
+    }
+
+    fn create_render_bundle(&self, ctx: &Context, cube: &Cube, mode: ShadingMode) -> RenderBundle {
+        let mut render_bundle_encoder =
+            Cube::begin_render_bundle(ctx, &self.bind_group, &self.material_binding);
+        render_bundle_encoder
+            .set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_bundle_encoder.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        for pipeline_fill in Cube::fill_pipelines(
+            &cube.pipelines_fill,
+            &cube.pipelines_blend,
+            &self.material,
+            mode,
+        ) {
+            render_bundle_encoder.set_pipeline(pipeline_fill);
+            render_bundle_encoder.draw_indexed_indirect(&self.indirect_buffer, 0);
+        }
+
+        if mode.draws_wireframe() {
+            render_bundle_encoder.set_pipeline(&cube.pipeline_wire);
+            match &self.wire_vertex_buffer {
+                Some(wire_vertex_buffer) => {
+                    render_bundle_encoder.set_vertex_buffer(0, wire_vertex_buffer.slice(..));
+                    render_bundle_encoder
+                        .draw_indirect(&self.indirect_buffer, NON_INDEXED_ARGS_OFFSET);
+                }
+                None => render_bundle_encoder.draw_indexed_indirect(&self.indirect_buffer, 0),
+            }
+        }
+
+        render_bundle_encoder.finish(&RenderBundleDescriptor { label: None })
+    }
+}
+
+impl ShadowCaster for LodMesh {
+    fn draw_shadow<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>) {
+        rpass.set_pipeline(Cube::shadow_pipeline(
+            &self.pipelines_shadow,
+            &self.material,
+        ));
+        rpass.set_bind_group(1, &self.bind_group, &[]);
+        rpass.set_bind_group(2, &self.material_binding.bind_group, &[]);
+        rpass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.draw_indexed_indirect(&self.indirect_buffer, 0);
+    }
+}
@@ -6,6 +6,8 @@ pub struct Vertex {
     pub a_pos: [f32; 3],
     pub a_uv: [f32; 2],
     pub a_normal: [f32; 3],
+    /// Filled in by `generate_tangents`.
+    pub a_tangent: [f32; 4],
 }
 
 const TOP: [f32; 3] = [0.0, 0.0, 1.0];
@@ -21,126 +23,150 @@ pub const VERTICES: &[Vertex] = &[
         a_pos: [-1.0, -1.0, 1.0],
         a_uv: [0.0, 0.0],
         a_normal: TOP,
+        a_tangent: [0.0; 4],
     },
     Vertex {
         a_pos: [1.0, -1.0, 1.0],
         a_uv: [1.0, 0.0],
         a_normal: TOP,
+        a_tangent: [0.0; 4],
     },
     Vertex {
         a_pos: [1.0, 1.0, 1.0],
         a_uv: [1.0, 1.0],
         a_normal: TOP,
+        a_tangent: [0.0; 4],
     },
     Vertex {
         a_pos: [-1.0, 1.0, 1.0],
         a_uv: [0.0, 1.0],
         a_normal: TOP,
+        a_tangent: [0.0; 4],
     },
     // bottom (0.0, 0.0, -1.0)
     Vertex {
         a_pos: [-1.0, 1.0, -1.0],
         a_uv: [1.0, 0.0],
         a_normal: BOTTOM,
+        a_tangent: [0.0; 4],
     },
     Vertex {
         a_pos: [1.0, 1.0, -1.0],
         a_uv: [0.0, 0.0],
         a_normal: BOTTOM,
+        a_tangent: [0.0; 4],
     },
     Vertex {
         a_pos: [1.0, -1.0, -1.0],
         a_uv: [0.0, 1.0],
         a_normal: BOTTOM,
+        a_tangent: [0.0; 4],
     },
     Vertex {
         a_pos: [-1.0, -1.0, -1.0],
         a_uv: [1.0, 1.0],
         a_normal: BOTTOM,
+        a_tangent: [0.0; 4],
     },
     // right (1.0, 0.0, 0.0)
     Vertex {
         a_pos: [1.0, -1.0, -1.0],
         a_uv: [0.0, 0.0],
         a_normal: RIGHT,
+        a_tangent: [0.0; 4],
     },
     Vertex {
         a_pos: [1.0, 1.0, -1.0],
         a_uv: [1.0, 0.0],
         a_normal: RIGHT,
+        a_tangent: [0.0; 4],
     },
     Vertex {
         a_pos: [1.0, 1.0, 1.0],
         a_uv: [1.0, 1.0],
         a_normal: RIGHT,
+        a_tangent: [0.0; 4],
     },
     Vertex {
         a_pos: [1.0, -1.0, 1.0],
         a_uv: [0.0, 1.0],
         a_normal: RIGHT,
+        a_tangent: [0.0; 4],
     },
     // left (-1.0, 0.0, 0.0)
     Vertex {
         a_pos: [-1.0, -1.0, 1.0],
         a_uv: [1.0, 0.0],
         a_normal: LEFT,
+        a_tangent: [0.0; 4],
     },
     Vertex {
         a_pos: [-1.0, 1.0, 1.0],
         a_uv: [0.0, 0.0],
         a_normal: LEFT,
+        a_tangent: [0.0; 4],
     },
     Vertex {
         a_pos: [-1.0, 1.0, -1.0],
         a_uv: [0.0, 1.0],
         a_normal: LEFT,
+        a_tangent: [0.0; 4],
     },
     Vertex {
         a_pos: [-1.0, -1.0, -1.0],
         a_uv: [1.0, 1.0],
         a_normal: LEFT,
+        a_tangent: [0.0; 4],
     },
     // front (0.0, 1.0, 0.0)
     Vertex {
         a_pos: [1.0, 1.0, -1.0],
         a_uv: [1.0, 0.0],
         a_normal: FRONT,
+        a_tangent: [0.0; 4],
     },
     Vertex {
         a_pos: [-1.0, 1.0, -1.0],
         a_uv: [0.0, 0.0],
         a_normal: FRONT,
+        a_tangent: [0.0; 4],
     },
     Vertex {
         a_pos: [-1.0, 1.0, 1.0],
         a_uv: [0.0, 1.0],
         a_normal: FRONT,
+        a_tangent: [0.0; 4],
     },
     Vertex {
         a_pos: [1.0, 1.0, 1.0],
         a_uv: [1.0, 1.0],
         a_normal: FRONT,
+        a_tangent: [0.0; 4],
     },
     // back (0.0, -1.0, 0.0)
     Vertex {
         a_pos: [1.0, -1.0, 1.0],
         a_uv: [0.0, 0.0],
         a_normal: BACK,
+        a_tangent: [0.0; 4],
     },
     Vertex {
         a_pos: [-1.0, -1.0, 1.0],
         a_uv: [1.0, 0.0],
         a_normal: BACK,
+        a_tangent: [0.0; 4],
     },
     Vertex {
         a_pos: [-1.0, -1.0, -1.0],
         a_uv: [1.0, 1.0],
         a_normal: BACK,
+        a_tangent: [0.0; 4],
     },
     Vertex {
         a_pos: [1.0, -1.0, -1.0],
         a_uv: [0.0, 1.0],
         a_normal: BACK,
+        a_tangent: [0.0; 4],
     },
 ];
 pub const INDICES: &[u16] = &[
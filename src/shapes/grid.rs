@@ -0,0 +1,205 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::Vector3;
+use wgpu::{util::DeviceExt, BufferUsages, PipelineLayoutDescriptor};
+
+use crate::{Context, ScreenSpace};
+
+use std::borrow::Cow;
+use std::mem;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridPlane {
+    XY,
+    XZ,
+    YZ,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GridUniforms {
+    inverse_vp_matrix: [f32; 16],
+    axis_u: [f32; 4],
+    axis_v: [f32; 4],
+    normal: [f32; 4],
+    params: [f32; 4],
+}
+
+/// Infinite ground grid drawn by intersecting view rays with a plane in the fragment shader.
+pub struct Grid {
+    pub plane: GridPlane,
+    /// Offset of the plane along its normal.
+    pub offset: f32,
+    /// Minor line spacing when the camera is one unit away from the plane.
+    pub base_spacing: f32,
+    /// Distance at which lines have faded out, relative to the camera distance to the plane.
+    pub fade_distance: f32,
+    pub intensity: f32,
+
+    pub uniform_buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+    pub render_pipeline: wgpu::RenderPipeline,
+    pub render_bundle: wgpu::RenderBundle,
+}
+
+impl GridPlane {
+    pub fn next(self) -> Self {
+        match self {
+            GridPlane::XY => GridPlane::XZ,
+            GridPlane::XZ => GridPlane::YZ,
+            GridPlane::YZ => GridPlane::XY,
+        }
+    }
+
+    /// The two in-plane axes and the normal.
+    fn axes(self) -> [Vector3<f32>; 3] {
+        match self {
+            GridPlane::XY => [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()],
+            GridPlane::XZ => [Vector3::unit_x(), Vector3::unit_z(), Vector3::unit_y()],
+            GridPlane::YZ => [Vector3::unit_y(), Vector3::unit_z(), Vector3::unit_x()],
+        }
+    }
+}
+
+impl Grid {
+    pub fn new(ctx: &Context) -> Self {
+        let uniform_buffer = ctx
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::bytes_of(&GridUniforms::zeroed()),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            });
+
+        let bind_group_layout =
+            ctx.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: None,
+                    entries: &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(
+                                mem::size_of::<GridUniforms>() as u64
+                            ),
+                        },
+                        count: None,
+                    }],
+                });
+
+        let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let shader_module = ctx
+            .device
+            .create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: Some("grid/grid.wgsl"),
+                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("grid/grid.wgsl"))),
+            });
+
+        let pipeline_layout = ctx
+            .device
+            .create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&ctx.global.bind_group_layout, &bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = ctx
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                // the grid is translucent, so it is tested against but never occludes the scene
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: wgpu::TextureFormat::Depth32Float,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::LessEqual,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: ctx.multisample_state(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point: "fs_main",
+                    targets: &[wgpu::ColorTargetState {
+                        format: ctx.surface_config.format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                }),
+                multiview: None,
+            });
+
+        let mut render_bundle_encoder =
+            ctx.device
+                .create_render_bundle_encoder(&wgpu::RenderBundleEncoderDescriptor {
+                    label: None,
+                    color_formats: &[ctx.surface_config.format],
+                    depth_stencil: Some(wgpu::RenderBundleDepthStencil {
+                        format: wgpu::TextureFormat::Depth32Float,
+                        depth_read_only: false,
+                        stencil_read_only: true,
+                    }),
+                    sample_count: ctx.sample_count,
+                    ..wgpu::RenderBundleEncoderDescriptor::default()
+                });
+        render_bundle_encoder.set_pipeline(&render_pipeline);
+        render_bundle_encoder.set_bind_group(0, &ctx.global.bind_group, &[]);
+        render_bundle_encoder.set_bind_group(1, &bind_group, &[]);
+        render_bundle_encoder.draw(0..3, 0..1);
+
+        let render_bundle =
+            render_bundle_encoder.finish(&wgpu::RenderBundleDescriptor { label: None });
+
+        Grid {
+            plane: GridPlane::XZ,
+            offset: 0.0,
+            base_spacing: 0.1,
+            fade_distance: 40.0,
+            intensity: 0.7,
+            uniform_buffer,
+            bind_group,
+            render_pipeline,
+            render_bundle,
+        }
+    }
+
+    /// Rebuilds the pipeline and render bundle after the MSAA sample count changed, keeping
+    /// the grid settings.
+    pub fn recreate_pipelines(&mut self, ctx: &Context) {
+        *self = Grid {
+            plane: self.plane,
+            offset: self.offset,
+            base_spacing: self.base_spacing,
+            fade_distance: self.fade_distance,
+            intensity: self.intensity,
+            ..Grid::new(ctx)
+        };
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, screen: &ScreenSpace) {
+        let [u, v, normal] = self.plane.axes();
+        let uniforms = GridUniforms {
+            inverse_vp_matrix: *screen.inverse_vp_matrix().as_ref(),
+            axis_u: u.extend(0.0).into(),
+            axis_v: v.extend(0.0).into(),
+            normal: normal.extend(self.offset).into(),
+            params: [self.base_spacing, self.fade_distance, self.intensity, 0.0],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+}
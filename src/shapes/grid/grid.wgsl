@@ -0,0 +1,94 @@
+struct GlobalUniforms {
+    vp_matrix: mat4x4<f32>;
+    camera_position: vec3<f32>;
+};
+
+struct GridUniforms {
+    inverse_vp_matrix: mat4x4<f32>;
+    axis_u: vec4<f32>;
+    axis_v: vec4<f32>;
+    // xyz: plane normal, w: plane offset along the normal
+    normal: vec4<f32>;
+    // x: minor spacing at a camera distance of 1, y: fade distance, z: line color intensity
+    params: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> u_global: GlobalUniforms;
+[[group(1), binding(0)]]
+var<uniform> u_grid: GridUniforms;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] near_point: vec3<f32>;
+    [[location(1)]] far_point: vec3<f32>;
+};
+
+struct FragmentOutput {
+    [[location(0)]] color: vec4<f32>;
+    [[builtin(frag_depth)]] depth: f32;
+};
+
+fn unproject(ndc: vec3<f32>) -> vec3<f32> {
+    let world = u_grid.inverse_vp_matrix * vec4<f32>(ndc, 1.0);
+    return world.xyz / world.w;
+}
+
+// a single triangle covering the whole screen
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let ndc = uv * 2.0 - 1.0;
+    var out: VertexOutput;
+    out.position = vec4<f32>(ndc, 0.0, 1.0);
+    out.near_point = unproject(vec3<f32>(ndc, 0.0));
+    out.far_point = unproject(vec3<f32>(ndc, 1.0));
+    return out;
+}
+
+// 1.0 on a line of the given spacing, antialiased over one pixel
+fn grid_lines(coord: vec2<f32>, spacing: f32) -> f32 {
+    let c = coord / spacing;
+    let derivative = max(fwidth(c), vec2<f32>(1e-6));
+    let distance = abs(fract(c - 0.5) - 0.5) / derivative;
+    return 1.0 - min(min(distance.x, distance.y), 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    let normal = u_grid.normal.xyz;
+    let offset = u_grid.normal.w;
+    let ray = in.far_point - in.near_point;
+    let t = (offset - dot(in.near_point, normal)) / dot(ray, normal);
+    if (t < 0.0 || t > 1.0) {
+        discard;
+    }
+    let position = in.near_point + t * ray;
+
+    let clip = u_global.vp_matrix * vec4<f32>(position, 1.0);
+    let depth = clip.z / clip.w;
+
+    // spacing steps by powers of ten with the camera distance to the plane,
+    // cross-fading the finest level out as the camera moves away
+    let height = max(abs(dot(u_global.camera_position, normal) - offset), 1e-3);
+    let level = log(height) / log(10.0);
+    let blend = fract(level);
+    let spacing = u_grid.params.x * pow(10.0, floor(level));
+
+    let coord = vec2<f32>(dot(position, u_grid.axis_u.xyz), dot(position, u_grid.axis_v.xyz));
+    let minor = grid_lines(coord, spacing) * 0.4 * (1.0 - blend);
+    let middle = grid_lines(coord, spacing * 10.0) * mix(0.8, 0.4, blend);
+    let major = grid_lines(coord, spacing * 100.0) * 0.8;
+    let intensity = max(max(minor, middle), major);
+
+    // fade out with distance and at grazing angles toward the horizon
+    let to_camera = u_global.camera_position - position;
+    let distance_fade = 1.0 - smoothStep(0.0, u_grid.params.y * height, length(to_camera));
+    let angle_fade = abs(dot(normalize(to_camera), normal));
+    let alpha = intensity * distance_fade * smoothStep(0.0, 0.3, angle_fade);
+
+    var out: FragmentOutput;
+    out.color = vec4<f32>(vec3<f32>(u_grid.params.z), alpha);
+    out.depth = depth;
+    return out;
+}
@@ -0,0 +1,246 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{InnerSpace, Point3, Vector3};
+use wgpu::{BufferUsages, PipelineLayoutDescriptor, VertexAttribute};
+
+use crate::Context;
+
+use std::borrow::Cow;
+use std::mem;
+
+const INITIAL_CAPACITY: usize = 1024;
+const CIRCLE_SEGMENTS: usize = 48;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct LineVertex {
+    pub a_position: [f32; 3],
+    pub a_color: [f32; 4],
+}
+
+/// Colored line list rebuilt on the CPU every time it changes and drawn on top of the scene.
+pub struct LineBatch {
+    lines: Vec<LineVertex>,
+    vertex_capacity: usize,
+
+    pub vertex_buffer: wgpu::Buffer,
+    pub render_pipeline: wgpu::RenderPipeline,
+    pub render_bundle: wgpu::RenderBundle,
+}
+
+impl LineBatch {
+    pub fn new(ctx: &Context) -> Self {
+        let vertex_capacity = INITIAL_CAPACITY;
+        let vertex_buffer = Self::create_vertex_buffer(ctx, vertex_capacity);
+
+        let shader_module = ctx
+            .device
+            .create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: Some("line_batch/line_batch.wgsl"),
+                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                    "line_batch/line_batch.wgsl"
+                ))),
+            });
+
+        let pipeline_layout = ctx
+            .device
+            .create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&ctx.global.bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = ctx
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: "vs_main",
+                    buffers: &[wgpu::VertexBufferLayout {
+                        array_stride: mem::size_of::<LineVertex>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &[
+                            VertexAttribute {
+                                format: wgpu::VertexFormat::Float32x3,
+                                offset: 0,
+                                shader_location: 0,
+                            },
+                            VertexAttribute {
+                                format: wgpu::VertexFormat::Float32x4,
+                                offset: 4 * 3,
+                                shader_location: 1,
+                            },
+                        ],
+                    }],
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::LineList,
+                    ..wgpu::PrimitiveState::default()
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: wgpu::TextureFormat::Depth32Float,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: ctx.multisample_state(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point: "fs_main",
+                    targets: &[wgpu::ColorTargetState {
+                        format: ctx.surface_config.format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                }),
+                multiview: None,
+            });
+
+        let render_bundle = Self::create_render_bundle(ctx, &render_pipeline, &vertex_buffer, 0);
+
+        LineBatch {
+            lines: Vec::new(),
+            vertex_capacity,
+            vertex_buffer,
+            render_pipeline,
+            render_bundle,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    pub fn line(&mut self, a: Point3<f32>, b: Point3<f32>, color: [f32; 4]) {
+        self.lines.push(LineVertex {
+            a_position: a.into(),
+            a_color: color,
+        });
+        self.lines.push(LineVertex {
+            a_position: b.into(),
+            a_color: color,
+        });
+    }
+
+    /// Axis-aligned cross centered at `p`, used as a point marker.
+    pub fn cross(&mut self, p: Point3<f32>, half_size: f32, color: [f32; 4]) {
+        for axis in [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()] {
+            self.line(p - axis * half_size, p + axis * half_size, color);
+        }
+    }
+
+    pub fn polyline_closed(&mut self, points: &[Point3<f32>], color: [f32; 4]) {
+        for (i, &a) in points.iter().enumerate() {
+            self.line(a, points[(i + 1) % points.len()], color);
+        }
+    }
+
+    pub fn circle(
+        &mut self,
+        center: Point3<f32>,
+        normal: Vector3<f32>,
+        radius: f32,
+        color: [f32; 4],
+    ) {
+        let points = circle_points(center, normal, radius, CIRCLE_SEGMENTS);
+        self.polyline_closed(&points, color);
+    }
+
+    pub fn aabb(&mut self, min: Point3<f32>, max: Point3<f32>, color: [f32; 4]) {
+        let corner = |i: usize| {
+            Point3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            )
+        };
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corner(i), corner(i | bit), color);
+                }
+            }
+        }
+    }
+
+    /// Uploads the queued lines and re-records the render bundle.
+    pub fn upload(&mut self, ctx: &Context) {
+        if self.lines.len() > self.vertex_capacity {
+            self.vertex_capacity = self.lines.len().next_power_of_two();
+            self.vertex_buffer = Self::create_vertex_buffer(ctx, self.vertex_capacity);
+        }
+        ctx.queue
+            .write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.lines));
+        self.render_bundle = Self::create_render_bundle(
+            ctx,
+            &self.render_pipeline,
+            &self.vertex_buffer,
+            self.lines.len() as u32,
+        );
+    }
+
+    fn create_vertex_buffer(ctx: &Context, capacity: usize) -> wgpu::Buffer {
+        ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (capacity * mem::size_of::<LineVertex>()) as u64,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_render_bundle(
+        ctx: &Context,
+        render_pipeline: &wgpu::RenderPipeline,
+        vertex_buffer: &wgpu::Buffer,
+        vertex_count: u32,
+    ) -> wgpu::RenderBundle {
+        let mut render_bundle_encoder =
+            ctx.device
+                .create_render_bundle_encoder(&wgpu::RenderBundleEncoderDescriptor {
+                    label: None,
+                    color_formats: &[ctx.surface_config.format],
+                    depth_stencil: Some(wgpu::RenderBundleDepthStencil {
+                        format: wgpu::TextureFormat::Depth32Float,
+                        depth_read_only: false,
+                        stencil_read_only: true,
+                    }),
+                    sample_count: ctx.sample_count,
+                    ..wgpu::RenderBundleEncoderDescriptor::default()
+                });
+        render_bundle_encoder.set_pipeline(render_pipeline);
+        render_bundle_encoder.set_bind_group(0, &ctx.global.bind_group, &[]);
+        render_bundle_encoder.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_bundle_encoder.draw(0..vertex_count, 0..1);
+
+        render_bundle_encoder.finish(&wgpu::RenderBundleDescriptor { label: None })
+    }
+}
+
+pub fn circle_points(
+    center: Point3<f32>,
+    normal: Vector3<f32>,
+    radius: f32,
+    segments: usize,
+) -> Vec<Point3<f32>> {
+    let [u, v] = perpendicular_basis(normal);
+    (0..segments)
+        .map(|i| {
+            let angle = i as f32 / segments as f32 * std::f32::consts::TAU;
+            center + (u * angle.cos() + v * angle.sin()) * radius
+        })
+        .collect()
+}
+
+/// Two unit vectors perpendicular to `normal` and to each other.
+pub fn perpendicular_basis(normal: Vector3<f32>) -> [Vector3<f32>; 2] {
+    let normal = normal.normalize();
+    let helper = if normal.x.abs() < 0.9 {
+        Vector3::unit_x()
+    } else {
+        Vector3::unit_y()
+    };
+    let u = normal.cross(helper).normalize();
+    [u, normal.cross(u)]
+}
@@ -0,0 +1,38 @@
+struct SkyboxUniforms {
+    inverse_vp_matrix: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> u_skybox: SkyboxUniforms;
+[[group(0), binding(1)]]
+var t_environment: texture_cube<f32>;
+[[group(0), binding(2)]]
+var s_environment: sampler;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] ndc: vec2<f32>;
+};
+
+fn unproject(ndc: vec3<f32>) -> vec3<f32> {
+    let world = u_skybox.inverse_vp_matrix * vec4<f32>(ndc, 1.0);
+    return world.xyz / world.w;
+}
+
+// a single triangle covering the whole screen
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let ndc = uv * 2.0 - 1.0;
+    var out: VertexOutput;
+    out.position = vec4<f32>(ndc, 1.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let direction = unproject(vec3<f32>(in.ndc, 1.0)) - unproject(vec3<f32>(in.ndc, 0.0));
+    let radiance = textureSampleLevel(t_environment, s_environment, direction, 0.0).rgb;
+    return vec4<f32>(radiance, 1.0);
+}
@@ -0,0 +1,343 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{Point2, Vector2};
+use wgpu::util::DeviceExt;
+use wgpu::{BufferUsages, PipelineLayoutDescriptor};
+use winit::dpi::PhysicalSize;
+
+use crate::Context;
+
+use std::borrow::Cow;
+use std::mem;
+
+mod font;
+
+use font::{FIRST_CHAR, GLYPHS, GLYPH_SIZE};
+
+const INITIAL_CAPACITY: usize = 256;
+/// Glyphs per row of the atlas.
+const ATLAS_COLUMNS: u32 = 16;
+/// Index of the filled glyph, drawn by `rect`.
+const FILLED_GLYPH: u32 = GLYPHS.len() as u32 - 1;
+/// Drawn for characters without a glyph.
+const REPLACEMENT_CHAR: u8 = b'?';
+
+/// A quad in pixels, and the part of the atlas it shows.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GlyphInstance {
+    rect: [f32; 4],
+    texel_rect: [f32; 4],
+    color: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct TextUniforms {
+    screen_size: [f32; 2],
+    _0: [f32; 2],
+}
+
+/// Screen-space text in a monospaced 8x8 bitmap font, rebuilt on the CPU every time it
+/// changes and drawn on top of the scene like `LineBatch`.
+///
+/// Positions and sizes are in pixels from the top-left corner of the target. Glyphs are
+/// scaled by whole pixels, so they stay sharp.
+pub struct TextBatch {
+    glyphs: Vec<GlyphInstance>,
+    capacity: usize,
+
+    instance_buffer: wgpu::Buffer,
+    uniform_buffer: wgpu::Buffer,
+    _atlas: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+    render_pipeline: wgpu::RenderPipeline,
+    pub render_bundle: wgpu::RenderBundle,
+}
+
+impl TextBatch {
+    pub fn new(ctx: &Context) -> Self {
+        let atlas = Self::create_atlas(ctx);
+        let uniform_buffer = ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("text"),
+            size: mem::size_of::<TextUniforms>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout =
+            ctx.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("text"),
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::VERTEX,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: wgpu::BufferSize::new(
+                                    mem::size_of::<TextUniforms>() as u64,
+                                ),
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                    ],
+                });
+        let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("text"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(
+                        &atlas.create_view(&wgpu::TextureViewDescriptor::default()),
+                    ),
+                },
+            ],
+        });
+
+        let shader_module = ctx
+            .device
+            .create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: Some("text_batch/text_batch.wgsl"),
+                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                    "text_batch/text_batch.wgsl"
+                ))),
+            });
+        let pipeline_layout = ctx
+            .device
+            .create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let render_pipeline = ctx
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: "vs_main",
+                    buffers: &[wgpu::VertexBufferLayout {
+                        array_stride: mem::size_of::<GlyphInstance>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Instance,
+                        attributes: &wgpu::vertex_attr_array![
+                            0 => Float32x4,
+                            1 => Float32x4,
+                            2 => Float32x4,
+                        ],
+                    }],
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    ..wgpu::PrimitiveState::default()
+                },
+                // over everything, in the overlay pass
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: wgpu::TextureFormat::Depth32Float,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: ctx.multisample_state(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point: "fs_main",
+                    targets: &[wgpu::ColorTargetState {
+                        format: ctx.surface_config.format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                }),
+                multiview: None,
+            });
+
+        let capacity = INITIAL_CAPACITY;
+        let instance_buffer = Self::create_instance_buffer(ctx, capacity);
+        let render_bundle =
+            Self::create_render_bundle(ctx, &render_pipeline, &bind_group, &instance_buffer, 0);
+
+        TextBatch {
+            glyphs: Vec::new(),
+            capacity,
+            instance_buffer,
+            uniform_buffer,
+            _atlas: atlas,
+            bind_group,
+            render_pipeline,
+            render_bundle,
+        }
+    }
+
+    /// The glyphs in rows of `ATLAS_COLUMNS`, one byte of coverage per pixel.
+    fn create_atlas(ctx: &Context) -> wgpu::Texture {
+        let rows = (GLYPHS.len() as u32).div_ceil(ATLAS_COLUMNS);
+        let width = ATLAS_COLUMNS * GLYPH_SIZE;
+        let height = rows * GLYPH_SIZE;
+        let mut texels = vec![0; (width * height) as usize];
+        for (index, glyph) in GLYPHS.iter().enumerate() {
+            let (column, row) = (index as u32 % ATLAS_COLUMNS, index as u32 / ATLAS_COLUMNS);
+            for (y, bits) in glyph.iter().enumerate() {
+                for x in 0..GLYPH_SIZE {
+                    if bits >> x & 1 == 1 {
+                        let texel_x = column * GLYPH_SIZE + x;
+                        let texel_y = row * GLYPH_SIZE + y as u32;
+                        texels[(texel_y * width + texel_x) as usize] = 255;
+                    }
+                }
+            }
+        }
+        ctx.device.create_texture_with_data(
+            &ctx.queue,
+            &wgpu::TextureDescriptor {
+                label: Some("font atlas"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::R8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            },
+            &texels,
+        )
+    }
+
+    pub fn clear(&mut self) {
+        self.glyphs.clear();
+    }
+
+    /// Size in pixels of `text` drawn `scale` times the font size, with a line per `\n`.
+    pub fn measure(text: &str, scale: u32) -> Vector2<f32> {
+        let glyph_size = (GLYPH_SIZE * scale) as f32;
+        let columns = text.lines().map(|line| line.chars().count()).max();
+        let rows = text.lines().count();
+        Vector2::new(columns.unwrap_or(0) as f32, rows as f32) * glyph_size
+    }
+
+    /// Queues `text` with its top-left corner at `position`, drawn `scale` times the font
+    /// size, and returns its size. Characters outside of printable ASCII are drawn as `?`.
+    pub fn text(
+        &mut self,
+        position: Point2<f32>,
+        text: &str,
+        scale: u32,
+        color: [f32; 4],
+    ) -> Vector2<f32> {
+        let glyph_size = (GLYPH_SIZE * scale) as f32;
+        for (row, line) in text.lines().enumerate() {
+            for (column, character) in line.chars().enumerate() {
+                let glyph = match u8::try_from(character) {
+                    Ok(b' ') => continue,
+                    Ok(byte @ FIRST_CHAR..=b'~') => byte,
+                    _ => REPLACEMENT_CHAR,
+                };
+                let corner = position + Vector2::new(column as f32, row as f32) * glyph_size;
+                self.glyph(
+                    corner,
+                    Vector2::new(glyph_size, glyph_size),
+                    (glyph - FIRST_CHAR) as u32,
+                    color,
+                );
+            }
+        }
+        Self::measure(text, scale)
+    }
+
+    /// Queues a filled rectangle, e.g. as the background of some text.
+    pub fn rect(&mut self, position: Point2<f32>, size: Vector2<f32>, color: [f32; 4]) {
+        self.glyph(position, size, FILLED_GLYPH, color);
+    }
+
+    fn glyph(&mut self, position: Point2<f32>, size: Vector2<f32>, index: u32, color: [f32; 4]) {
+        let column = (index % ATLAS_COLUMNS * GLYPH_SIZE) as f32;
+        let row = (index / ATLAS_COLUMNS * GLYPH_SIZE) as f32;
+        let glyph_size = GLYPH_SIZE as f32;
+        self.glyphs.push(GlyphInstance {
+            rect: [position.x, position.y, size.x, size.y],
+            texel_rect: [column, row, glyph_size, glyph_size],
+            color,
+        });
+    }
+
+    /// Uploads the queued text, laid out on a target of `size`, and re-records the render
+    /// bundle.
+    pub fn upload(&mut self, ctx: &Context, size: PhysicalSize<u32>) {
+        if self.glyphs.len() > self.capacity {
+            self.capacity = self.glyphs.len().next_power_of_two();
+            self.instance_buffer = Self::create_instance_buffer(ctx, self.capacity);
+        }
+        ctx.queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::bytes_of(&TextUniforms {
+                screen_size: [size.width as f32, size.height as f32],
+                _0: [0.0; 2],
+            }),
+        );
+        ctx.queue
+            .write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&self.glyphs));
+        self.render_bundle = Self::create_render_bundle(
+            ctx,
+            &self.render_pipeline,
+            &self.bind_group,
+            &self.instance_buffer,
+            self.glyphs.len() as u32,
+        );
+    }
+
+    fn create_instance_buffer(ctx: &Context, capacity: usize) -> wgpu::Buffer {
+        ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (capacity * mem::size_of::<GlyphInstance>()) as u64,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_render_bundle(
+        ctx: &Context,
+        render_pipeline: &wgpu::RenderPipeline,
+        bind_group: &wgpu::BindGroup,
+        instance_buffer: &wgpu::Buffer,
+        glyph_count: u32,
+    ) -> wgpu::RenderBundle {
+        let mut render_bundle_encoder =
+            ctx.device
+                .create_render_bundle_encoder(&wgpu::RenderBundleEncoderDescriptor {
+                    label: None,
+                    color_formats: &[ctx.surface_config.format],
+                    depth_stencil: Some(wgpu::RenderBundleDepthStencil {
+                        format: wgpu::TextureFormat::Depth32Float,
+                        depth_read_only: false,
+                        stencil_read_only: true,
+                    }),
+                    sample_count: ctx.sample_count,
+                    ..wgpu::RenderBundleEncoderDescriptor::default()
+                });
+        render_bundle_encoder.set_pipeline(render_pipeline);
+        render_bundle_encoder.set_bind_group(0, bind_group, &[]);
+        render_bundle_encoder.set_vertex_buffer(0, instance_buffer.slice(..));
+        render_bundle_encoder.draw(0..4, 0..glyph_count);
+
+        render_bundle_encoder.finish(&wgpu::RenderBundleDescriptor { label: None })
+    }
+}
@@ -0,0 +1,42 @@
+struct TextUniforms {
+    // of the target, in pixels
+    screen_size: vec2<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> u_text: TextUniforms;
+[[group(0), binding(1)]]
+var t_font: texture_2d<f32>;
+
+struct VertexOutput {
+    [[location(0)]] texel: vec2<f32>;
+    [[location(1)]] color: vec4<f32>;
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+// one quad per instance, as a triangle strip
+[[stage(vertex)]]
+fn vs_main(
+    [[builtin(vertex_index)]] vertex_index: u32,
+    [[location(0)]] rect: vec4<f32>,
+    [[location(1)]] texel_rect: vec4<f32>,
+    [[location(2)]] color: vec4<f32>,
+) -> VertexOutput {
+    let corner = vec2<f32>(f32(vertex_index & 1u), f32(vertex_index >> 1u));
+    let pixel = rect.xy + corner * rect.zw;
+    var out: VertexOutput;
+    out.texel = texel_rect.xy + corner * texel_rect.zw;
+    out.color = color;
+    let ndc = pixel / u_text.screen_size * 2.0 - vec2<f32>(1.0);
+    out.position = vec4<f32>(ndc.x, -ndc.y, 0.0, 1.0);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let coverage = textureLoad(t_font, vec2<i32>(floor(in.texel)), 0).r;
+    if (coverage <= 0.0) {
+        discard;
+    }
+    return in.color;
+}
@@ -0,0 +1,274 @@
+use cgmath::{Deg, EuclideanSpace, MetricSpace, Point2, Point3, Rad, Transform, Vector3};
+use winit::event::VirtualKeyCode;
+
+use crate::gizmo::{Constraint, GizmoDelta, GizmoFrame, GizmoMode};
+use crate::selection::{distance_to_segment, SelectionTarget, TransformSession};
+use crate::shapes::LineBatch;
+use crate::ScreenSpace;
+
+const SNAP_RADIUS: f32 = 12.0;
+const SNAP_MARKER_SIZE_IN_PIXELS: f32 = 6.0;
+const SNAP_MARKER_COLOR: [f32; 4] = [1.0, 0.3, 1.0, 1.0];
+const MIN_GRID_INCREMENT: f32 = 1.0 / 64.0;
+const MAX_GRID_INCREMENT: f32 = 64.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapTarget {
+    Grid,
+    Vertex,
+    Edge,
+    Face,
+}
+
+/// Keyboard nudge directions, interpreted in the gizmo frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Nudge {
+    Left,
+    Right,
+    Up,
+    Down,
+    Raise,
+    Lower,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Snapping {
+    pub enabled: bool,
+    pub target: SnapTarget,
+    pub grid_increment: f32,
+    pub angle_increment: Rad<f32>,
+    pub scale_increment: f32,
+}
+
+impl Default for Snapping {
+    fn default() -> Self {
+        Snapping {
+            enabled: false,
+            target: SnapTarget::Grid,
+            grid_increment: 0.25,
+            angle_increment: Deg(15.0).into(),
+            scale_increment: 0.1,
+        }
+    }
+}
+
+impl SnapTarget {
+    pub fn next(self) -> Self {
+        match self {
+            SnapTarget::Grid => SnapTarget::Vertex,
+            SnapTarget::Vertex => SnapTarget::Edge,
+            SnapTarget::Edge => SnapTarget::Face,
+            SnapTarget::Face => SnapTarget::Grid,
+        }
+    }
+}
+
+impl Nudge {
+    /// Arrow keys nudge horizontally, Page Up/Down vertically.
+    pub fn from_keycode(keycode: VirtualKeyCode) -> Option<Self> {
+        match keycode {
+            VirtualKeyCode::Left => Some(Nudge::Left),
+            VirtualKeyCode::Right => Some(Nudge::Right),
+            VirtualKeyCode::Up => Some(Nudge::Up),
+            VirtualKeyCode::Down => Some(Nudge::Down),
+            VirtualKeyCode::PageUp => Some(Nudge::Raise),
+            VirtualKeyCode::PageDown => Some(Nudge::Lower),
+            _ => None,
+        }
+    }
+}
+
+impl Snapping {
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    /// Multiplies the grid increment by `factor`, e.g. 2.0 or 0.5.
+    pub fn scale_grid(&mut self, factor: f32) {
+        self.grid_increment =
+            (self.grid_increment * factor).clamp(MIN_GRID_INCREMENT, MAX_GRID_INCREMENT);
+    }
+
+    /// Point on scene geometry under the cursor to snap a translation to. Geometry moved by
+    /// `session` is ignored so the selection does not snap to itself.
+    pub fn snap_point(
+        &self,
+        cursor: Point2<f32>,
+        screen: &ScreenSpace,
+        targets: &[SelectionTarget],
+        session: &TransformSession,
+    ) -> Option<Point3<f32>> {
+        if !self.enabled {
+            return None;
+        }
+        let nearest = |candidates: Vec<(f32, Point3<f32>)>| {
+            candidates
+                .into_iter()
+                .filter(|(distance, _)| *distance <= SNAP_RADIUS)
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+                .map(|(_, point)| point)
+        };
+        match self.target {
+            SnapTarget::Grid => None,
+            SnapTarget::Vertex => nearest(
+                targets
+                    .iter()
+                    .flat_map(|target| {
+                        target
+                            .topology
+                            .positions
+                            .iter()
+                            .enumerate()
+                            .filter(move |(i, _)| !session.moves(target.object, &[*i]))
+                            .map(move |(_, &p)| target.model_matrix.transform_point(p))
+                    })
+                    .filter_map(|p| Some((screen.project(p)?.distance(cursor), p)))
+                    .collect(),
+            ),
+            SnapTarget::Edge => nearest(
+                targets
+                    .iter()
+                    .flat_map(|target| {
+                        target
+                            .topology
+                            .edges
+                            .iter()
+                            .filter(move |edge| !session.moves(target.object, &edge[..]))
+                            .map(move |edge| {
+                                edge.map(|i| {
+                                    target
+                                        .model_matrix
+                                        .transform_point(target.topology.positions[i])
+                                })
+                            })
+                    })
+                    .filter_map(|[a, b]| {
+                        let (pa, pb) = (screen.project(a)?, screen.project(b)?);
+                        let ab = pb - pa;
+                        let t = if ab.x == 0.0 && ab.y == 0.0 {
+                            0.0
+                        } else {
+                            (cgmath::dot(cursor - pa, ab) / cgmath::dot(ab, ab)).clamp(0.0, 1.0)
+                        };
+                        // interpolating in screen space is close enough for short edges
+                        Some((distance_to_segment(cursor, pa, pb), a + (b - a) * t))
+                    })
+                    .collect(),
+            ),
+            SnapTarget::Face => {
+                let ray = screen.ray(cursor);
+                targets
+                    .iter()
+                    .flat_map(|target| {
+                        target
+                            .topology
+                            .faces
+                            .iter()
+                            .filter(move |face| !session.moves(target.object, &face.vertices))
+                            .flat_map(move |face| {
+                                face.triangles.iter().map(move |t| {
+                                    t.map(|i| {
+                                        target
+                                            .model_matrix
+                                            .transform_point(target.topology.positions[i])
+                                    })
+                                })
+                            })
+                    })
+                    .filter_map(|triangle| ray.intersect_triangle(triangle))
+                    .min_by(|a, b| a.total_cmp(b))
+                    .map(|t| ray.at(t))
+            }
+        }
+    }
+
+    /// Applies grid, element, angle and scale snapping to a drag or nudge.
+    pub fn snap(
+        &self,
+        delta: GizmoDelta,
+        frame: &GizmoFrame,
+        constraint: Constraint,
+        snap_point: Option<Point3<f32>>,
+    ) -> GizmoDelta {
+        if !self.enabled {
+            return delta;
+        }
+        match delta {
+            GizmoDelta::Translate(offset) => {
+                let target = match (self.target, snap_point) {
+                    (SnapTarget::Grid, _) => {
+                        let moved = frame.pivot + offset;
+                        Point3::from_vec(moved.to_vec().map(|v| round_to(v, self.grid_increment)))
+                    }
+                    (_, Some(point)) => point,
+                    (_, None) => return delta,
+                };
+                GizmoDelta::Translate(constraint.apply(target - frame.pivot))
+            }
+            GizmoDelta::Rotate { axis, angle } => GizmoDelta::Rotate {
+                axis,
+                angle: Rad(round_to(angle.0, self.angle_increment.0)),
+            },
+            GizmoDelta::Scale(factors) => GizmoDelta::Scale(factors.map(|factor| {
+                (1.0 + round_to(factor - 1.0, self.scale_increment)).max(self.scale_increment)
+            })),
+        }
+    }
+
+    /// One increment of the current gizmo mode in the direction of `nudge`.
+    pub fn nudge(&self, mode: GizmoMode, frame: &GizmoFrame, nudge: Nudge) -> GizmoDelta {
+        let [x, y, z] = frame.axes;
+        let direction: Vector3<f32> = match nudge {
+            Nudge::Left => -x,
+            Nudge::Right => x,
+            Nudge::Up => -z,
+            Nudge::Down => z,
+            Nudge::Raise => y,
+            Nudge::Lower => -y,
+        };
+        match mode {
+            GizmoMode::Translate => GizmoDelta::Translate(direction * self.grid_increment),
+            GizmoMode::Rotate => {
+                // turn around the axis perpendicular to the nudge on screen
+                let axis = match nudge {
+                    Nudge::Left | Nudge::Right => y,
+                    Nudge::Up | Nudge::Down => x,
+                    Nudge::Raise | Nudge::Lower => z,
+                };
+                let sign = match nudge {
+                    Nudge::Right | Nudge::Down | Nudge::Raise => 1.0,
+                    Nudge::Left | Nudge::Up | Nudge::Lower => -1.0,
+                };
+                GizmoDelta::Rotate {
+                    axis,
+                    angle: self.angle_increment * sign,
+                }
+            }
+            GizmoMode::Scale => {
+                let factor = match nudge {
+                    Nudge::Right | Nudge::Up | Nudge::Raise => 1.0 + self.scale_increment,
+                    Nudge::Left | Nudge::Down | Nudge::Lower => {
+                        (1.0 - self.scale_increment).max(self.scale_increment)
+                    }
+                };
+                GizmoDelta::Scale(Vector3::new(factor, factor, factor))
+            }
+        }
+    }
+}
+
+pub fn draw_snap_point(lines: &mut LineBatch, screen: &ScreenSpace, point: Point3<f32>) {
+    lines.cross(
+        point,
+        SNAP_MARKER_SIZE_IN_PIXELS * screen.pixel_size_at(point),
+        SNAP_MARKER_COLOR,
+    );
+}
+
+fn round_to(value: f32, increment: f32) -> f32 {
+    if increment > 0.0 {
+        (value / increment).round() * increment
+    } else {
+        value
+    }
+}
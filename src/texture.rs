@@ -0,0 +1,188 @@
+use wgpu::{Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages};
+
+use crate::Context;
+
+mod cache;
+mod hdr;
+mod mipmap;
+pub use cache::{TextureCache, TextureError, ASSET_DIR};
+pub use hdr::{HdrError, HdrImage};
+pub use mipmap::MipmapGenerator;
+
+use std::io::Cursor;
+use std::num::{NonZeroU32, NonZeroU8};
+
+/// Formats `Texture` creates, and therefore the ones mipmaps are generated for.
+pub const TEXTURE_FORMATS: [TextureFormat; 2] =
+    [TextureFormat::Rgba8UnormSrgb, TextureFormat::Rgba8Unorm];
+
+/// A 2D texture together with the view shaders sample it through.
+pub struct Texture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub width: u32,
+    pub height: u32,
+    pub mip_level_count: u32,
+}
+
+/// Decoded image with tightly packed 8-bit RGBA texels.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    /// Linear within a mip level, nearest between levels.
+    Bilinear,
+    /// Linear within and between mip levels.
+    Trilinear,
+}
+
+/// How a material samples its textures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SamplerOptions {
+    pub filter: Filter,
+    /// Maximum anisotropy, 1 to disable. Only applies to trilinear filtering.
+    pub anisotropy: u8,
+    pub address_mode: wgpu::AddressMode,
+}
+
+impl Default for SamplerOptions {
+    fn default() -> Self {
+        SamplerOptions {
+            filter: Filter::Trilinear,
+            anisotropy: 1,
+            address_mode: wgpu::AddressMode::Repeat,
+        }
+    }
+}
+
+impl SamplerOptions {
+    pub fn create_sampler(&self, device: &wgpu::Device) -> wgpu::Sampler {
+        let (filter, mipmap_filter) = match self.filter {
+            Filter::Nearest => (wgpu::FilterMode::Nearest, wgpu::FilterMode::Nearest),
+            Filter::Bilinear => (wgpu::FilterMode::Linear, wgpu::FilterMode::Nearest),
+            Filter::Trilinear => (wgpu::FilterMode::Linear, wgpu::FilterMode::Linear),
+        };
+        // anisotropic filtering is only valid with every filter linear
+        let anisotropy_clamp = match self.filter {
+            Filter::Trilinear if self.anisotropy > 1 => NonZeroU8::new(self.anisotropy.min(16)),
+            _ => None,
+        };
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: None,
+            address_mode_u: self.address_mode,
+            address_mode_v: self.address_mode,
+            address_mode_w: self.address_mode,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter,
+            anisotropy_clamp,
+            ..wgpu::SamplerDescriptor::default()
+        })
+    }
+}
+
+impl Image {
+    /// Decodes a PNG of any size, converting RGB, grayscale and palette images, with or
+    /// without alpha and at any bit depth, to 8-bit RGBA.
+    pub fn decode_png(bytes: &[u8]) -> Result<Self, png::DecodingError> {
+        let mut decoder = png::Decoder::new(Cursor::new(bytes));
+        // expands palettes, low bit depths and tRNS chunks, and strips 16-bit channels to 8 bits
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf)?;
+        let texels = &buf[..info.buffer_size()];
+
+        let rgba = match info.color_type {
+            png::ColorType::Rgba => texels.to_vec(),
+            png::ColorType::Rgb => texels
+                .chunks_exact(3)
+                .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], u8::MAX])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => texels
+                .chunks_exact(2)
+                .flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]])
+                .collect(),
+            png::ColorType::Grayscale => texels.iter().flat_map(|&g| [g, g, g, u8::MAX]).collect(),
+            // palettes are always expanded by the transformations above
+            png::ColorType::Indexed => unreachable!("indexed PNG was not expanded"),
+        };
+
+        Ok(Image {
+            width: info.width,
+            height: info.height,
+            rgba,
+        })
+    }
+}
+
+impl Texture {
+    /// Uploads an image and generates its full mip chain on the GPU. Color data is sRGB
+    /// encoded, anything else (metallic-roughness, normals, ...) is linear.
+    pub fn from_image(ctx: &Context, image: &Image, srgb: bool) -> Self {
+        Self::from_rgba8(ctx, image.width, image.height, &image.rgba, srgb)
+    }
+
+    /// Uploads tightly packed 8-bit RGBA texels and generates the mip chain.
+    pub fn from_rgba8(ctx: &Context, width: u32, height: u32, texels: &[u8], srgb: bool) -> Self {
+        let format = if srgb {
+            TextureFormat::Rgba8UnormSrgb
+        } else {
+            TextureFormat::Rgba8Unorm
+        };
+        let size = Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let mip_level_count = u32::BITS - width.max(height).max(1).leading_zeros();
+        let texture = ctx.device.create_texture(&TextureDescriptor {
+            label: None,
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+        });
+        ctx.queue.write_texture(
+            texture.as_image_copy(),
+            texels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(width * 4),
+                rows_per_image: None,
+            },
+            size,
+        );
+        ctx.mipmap_generator.generate(
+            &ctx.device,
+            &ctx.queue,
+            &texture,
+            format,
+            mip_level_count,
+            1,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Texture {
+            texture,
+            view,
+            width,
+            height,
+            mip_level_count,
+        }
+    }
+
+    /// 1x1 texture of a single color, standing in for missing maps.
+    pub fn solid(ctx: &Context, rgba: [u8; 4], srgb: bool) -> Self {
+        Self::from_rgba8(ctx, 1, 1, &rgba, srgb)
+    }
+}
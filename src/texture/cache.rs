@@ -0,0 +1,164 @@
+use super::{HdrError, Image, Texture};
+use crate::Context;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+
+/// Directory the bundled assets live in, so they are found whatever the working directory.
+pub const ASSET_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets");
+
+/// Why a texture could not be loaded from disk.
+#[derive(Debug)]
+pub enum TextureError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Decode {
+        path: PathBuf,
+        source: png::DecodingError,
+    },
+    DecodeHdr {
+        path: PathBuf,
+        source: HdrError,
+    },
+    /// Wider or taller than the textures of the device can be.
+    TooLarge {
+        path: PathBuf,
+        width: u32,
+        height: u32,
+        limit: u32,
+    },
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextureError::Io { path, source } => {
+                write!(f, "failed to read texture {}: {}", path.display(), source)
+            }
+            TextureError::Decode { path, source } => {
+                write!(f, "failed to decode texture {}: {}", path.display(), source)
+            }
+            TextureError::DecodeHdr { path, source } => {
+                write!(f, "failed to decode texture {}: {}", path.display(), source)
+            }
+            TextureError::TooLarge {
+                path,
+                width,
+                height,
+                limit,
+            } => write!(
+                f,
+                "texture {} is {}x{}, the device supports at most {} texels per side",
+                path.display(),
+                width,
+                height,
+                limit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TextureError::Io { source, .. } => Some(source),
+            TextureError::Decode { source, .. } => Some(source),
+            TextureError::DecodeHdr { source, .. } => Some(source),
+            TextureError::TooLarge { .. } => None,
+        }
+    }
+}
+
+/// Loads textures from image files, handing out shared references so every material using
+/// a file binds the same GPU texture. The cache only holds weak references: a texture is
+/// freed once the last material using it is dropped, and loaded again on the next request.
+///
+/// It also holds the solid textures standing in for missing maps, created on first use and
+/// kept for the lifetime of the cache.
+#[derive(Default)]
+pub struct TextureCache {
+    // keyed by canonical path and whether the texture is sRGB encoded
+    textures: RefCell<HashMap<(PathBuf, bool), Weak<Texture>>>,
+    solids: RefCell<HashMap<[u8; 4], Rc<Texture>>>,
+}
+
+impl TextureCache {
+    /// Returns the texture of the PNG at `path`, reading and uploading it unless it is
+    /// already loaded.
+    pub fn load(
+        &self,
+        ctx: &Context,
+        path: impl AsRef<Path>,
+        srgb: bool,
+    ) -> Result<Rc<Texture>, TextureError> {
+        let path = path.as_ref();
+        let path = path.canonicalize().map_err(|source| TextureError::Io {
+            path: path.to_owned(),
+            source,
+        })?;
+        let key = (path, srgb);
+        if let Some(texture) = self.textures.borrow().get(&key).and_then(Weak::upgrade) {
+            return Ok(texture);
+        }
+
+        let path = &key.0;
+        let bytes = std::fs::read(path).map_err(|source| TextureError::Io {
+            path: path.clone(),
+            source,
+        })?;
+        let image = Image::decode_png(&bytes).map_err(|source| TextureError::Decode {
+            path: path.clone(),
+            source,
+        })?;
+        let texture = Rc::new(Texture::from_image(ctx, &image, srgb));
+        log::info!(
+            "loaded texture {} ({}x{})",
+            path.display(),
+            image.width,
+            image.height
+        );
+
+        let mut textures = self.textures.borrow_mut();
+        // forget textures nobody uses anymore while we are at it
+        textures.retain(|_, texture| texture.strong_count() > 0);
+        textures.insert(key, Rc::downgrade(&texture));
+        Ok(texture)
+    }
+
+    /// The 1x1 linear texture of `rgba`, shared by every caller asking for the same color.
+    pub fn solid(&self, ctx: &Context, rgba: [u8; 4]) -> Rc<Texture> {
+        self.solids
+            .borrow_mut()
+            .entry(rgba)
+            .or_insert_with(|| Rc::new(Texture::solid(ctx, rgba, false)))
+            .clone()
+    }
+
+    /// Like `load`, with `path` relative to `ASSET_DIR`.
+    pub fn load_asset(
+        &self,
+        ctx: &Context,
+        path: impl AsRef<Path>,
+        srgb: bool,
+    ) -> Result<Rc<Texture>, TextureError> {
+        self.load(ctx, Path::new(ASSET_DIR).join(path), srgb)
+    }
+
+    /// Number of textures loaded from disk and currently alive.
+    pub fn len(&self) -> usize {
+        self.textures
+            .borrow()
+            .values()
+            .filter(|texture| texture.strong_count() > 0)
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,311 @@
+use std::fmt;
+
+/// Texels a run of the run-length encoding covers at most.
+const MAX_RUN: usize = 127;
+
+/// Decoded Radiance (.hdr) image with linear RGB texels.
+pub struct HdrImage {
+    pub width: u32,
+    pub height: u32,
+    pub rgb: Vec<[f32; 3]>,
+}
+
+/// Why a Radiance image could not be decoded.
+#[derive(Debug)]
+pub enum HdrError {
+    /// The file does not start with a Radiance signature.
+    NotRadiance,
+    /// Only `32-bit_rle_rgbe` is supported, not XYZE.
+    UnsupportedFormat(String),
+    /// Only `-Y <height> +X <width>` and `+Y <height> +X <width>` resolutions are supported,
+    /// of at least one texel and at most `u32::MAX` texels in all.
+    UnsupportedResolution(String),
+    /// The data ended before every scanline was read.
+    Truncated,
+    /// A run-length encoded scanline overflowed its width.
+    InvalidRunLength,
+}
+
+impl fmt::Display for HdrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HdrError::NotRadiance => write!(f, "not a Radiance HDR image"),
+            HdrError::UnsupportedFormat(format) => write!(f, "unsupported format {}", format),
+            HdrError::UnsupportedResolution(resolution) => {
+                write!(f, "unsupported resolution line {}", resolution)
+            }
+            HdrError::Truncated => write!(f, "unexpected end of image data"),
+            HdrError::InvalidRunLength => write!(f, "run length exceeds the scanline"),
+        }
+    }
+}
+
+impl std::error::Error for HdrError {}
+
+impl HdrImage {
+    /// Decodes a Radiance RGBE image, flat or with the run-length encoding of scanlines most
+    /// tools write.
+    pub fn decode(bytes: &[u8]) -> Result<Self, HdrError> {
+        let mut reader = Reader { bytes, pos: 0 };
+
+        let signature = reader.line()?;
+        if signature != "#?RADIANCE" && signature != "#?RGBE" {
+            return Err(HdrError::NotRadiance);
+        }
+        // header variables end with an empty line
+        loop {
+            let line = reader.line()?;
+            if line.is_empty() {
+                break;
+            }
+            if let Some(format) = line.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    return Err(HdrError::UnsupportedFormat(format.to_owned()));
+                }
+            }
+        }
+
+        let resolution = reader.line()?;
+        let (flip_y, height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+            [y @ ("-Y" | "+Y"), height, "+X", width] => (y == "+Y", height.parse(), width.parse()),
+            _ => return Err(HdrError::UnsupportedResolution(resolution)),
+        };
+        let (height, width): (u32, u32) = match (height, width) {
+            (Ok(height), Ok(width)) if height > 0 && width > 0 => (height, width),
+            _ => return Err(HdrError::UnsupportedResolution(resolution)),
+        };
+        let texels = match width.checked_mul(height) {
+            Some(texels) => texels as usize,
+            None => return Err(HdrError::UnsupportedResolution(resolution)),
+        };
+        // a corrupt header must not reserve more than the file could hold
+        let remaining = bytes.len() - reader.pos;
+        if (height as usize).saturating_mul(min_scanline_len(width as usize)) > remaining {
+            return Err(HdrError::Truncated);
+        }
+
+        let mut rgb = Vec::with_capacity(texels.min(bytes.len()));
+        let mut scanline = vec![[0u8; 4]; width as usize];
+        for _ in 0..height {
+            reader.scanline(&mut scanline)?;
+            rgb.extend(scanline.iter().map(|&rgbe| rgbe_to_rgb(rgbe)));
+        }
+        if flip_y {
+            // +Y stores the bottom row first
+            let rows: Vec<_> = rgb.chunks_exact(width as usize).rev().collect();
+            rgb = rows.concat();
+        }
+
+        Ok(HdrImage { width, height, rgb })
+    }
+
+    /// Texels as half floats for an `Rgba16Float` texture, with alpha set to one.
+    pub fn to_rgba16f(&self) -> Vec<u16> {
+        self.rgb
+            .iter()
+            .flat_map(|&[r, g, b]| [f16_bits(r), f16_bits(g), f16_bits(b), f16_bits(1.0)])
+            .collect()
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, HdrError> {
+        let byte = *self.bytes.get(self.pos).ok_or(HdrError::Truncated)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn line(&mut self) -> Result<String, HdrError> {
+        let rest = &self.bytes[self.pos..];
+        let len = rest
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or(HdrError::Truncated)?;
+        self.pos += len + 1;
+        Ok(String::from_utf8_lossy(&rest[..len]).trim().to_owned())
+    }
+
+    fn scanline(&mut self, scanline: &mut [[u8; 4]]) -> Result<(), HdrError> {
+        let width = scanline.len();
+        let header = [self.byte()?, self.byte()?, self.byte()?, self.byte()?];
+        let encoded =
+            is_encodable(width) && header[0] == 2 && header[1] == 2 && header[2] & 0x80 == 0;
+        if !encoded {
+            // flat scanline, the header was its first texel
+            scanline[0] = header;
+            for texel in &mut scanline[1..] {
+                *texel = [self.byte()?, self.byte()?, self.byte()?, self.byte()?];
+            }
+            return Ok(());
+        }
+        if ((header[2] as usize) << 8 | header[3] as usize) != width {
+            return Err(HdrError::InvalidRunLength);
+        }
+
+        // each channel is run-length encoded separately
+        for channel in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let count = self.byte()? as usize;
+                let (len, run) = if count > 128 {
+                    (count - 128, true)
+                } else {
+                    (count, false)
+                };
+                if len == 0 || x + len > width {
+                    return Err(HdrError::InvalidRunLength);
+                }
+                if run {
+                    let value = self.byte()?;
+                    for texel in &mut scanline[x..x + len] {
+                        texel[channel] = value;
+                    }
+                } else {
+                    for texel in &mut scanline[x..x + len] {
+                        texel[channel] = self.byte()?;
+                    }
+                }
+                x += len;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether scanlines `width` texels wide may be run-length encoded.
+fn is_encodable(width: usize) -> bool {
+    (8..0x8000).contains(&width)
+}
+
+/// Bytes of the shortest scanline `width` texels wide: flat, or run-length encoded with a
+/// header and the longest runs in each channel.
+fn min_scanline_len(width: usize) -> usize {
+    if is_encodable(width) {
+        4 + 4 * 2 * width.div_ceil(MAX_RUN)
+    } else {
+        4 * width
+    }
+}
+
+fn rgbe_to_rgb([r, g, b, e]: [u8; 4]) -> [f32; 3] {
+    if e == 0 {
+        return [0.0; 3];
+    }
+    let scale = 2f32.powi(e as i32 - (128 + 8));
+    [r, g, b].map(|c| (c as f32 + 0.5) * scale)
+}
+
+/// Bits of the IEEE half float nearest to `value`. Values beyond the half range saturate to
+/// the largest finite half, so bright HDR texels do not turn into infinities when filtered.
+fn f16_bits(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+    if value.is_nan() {
+        return sign | 0x7e00;
+    }
+    if exponent >= 0x1f {
+        return sign | 0x7bff;
+    }
+    if exponent <= 0 {
+        // subnormal half, or zero
+        let shift = 14 - exponent;
+        if shift > 24 {
+            return sign;
+        }
+        return sign | ((mantissa | 0x80_0000) >> shift) as u16;
+    }
+    // rounding may carry into the exponent, which is still the nearest half
+    let half = ((exponent as u32) << 10) + ((mantissa + 0x1000) >> 13);
+    sign | half.min(0x7bff) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(resolution: &str, data: &[u8]) -> Vec<u8> {
+        let header = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n{}\n", resolution);
+        [header.as_bytes(), data].concat()
+    }
+
+    #[test]
+    fn decodes_flat_scanlines() {
+        let image = HdrImage::decode(&file("-Y 1 +X 2", &[128, 64, 0, 129, 9, 9, 9, 0])).unwrap();
+
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(
+            image.rgb,
+            [[128.5 / 128.0, 64.5 / 128.0, 0.5 / 128.0], [0.0; 3]]
+        );
+    }
+
+    #[test]
+    fn decodes_run_length_encoded_scanlines() {
+        let mut data = vec![2, 2, 0, 8];
+        // red in a run, green literally, blue and the exponent in runs
+        data.extend([128 + 8, 128]);
+        data.push(8);
+        data.extend(0..8);
+        data.extend([128 + 8, 0, 128 + 8, 129]);
+        let image = HdrImage::decode(&file("-Y 1 +X 8", &data)).unwrap();
+
+        let expected: Vec<_> = (0..8).map(|g| rgbe_to_rgb([128, g, 0, 129])).collect();
+        assert_eq!(image.rgb, expected);
+    }
+
+    #[test]
+    fn rejects_runs_past_the_scanline() {
+        let data = [2, 2, 0, 8, 128 + 9, 0, 0, 0, 0, 0, 0, 0];
+        assert!(matches!(
+            HdrImage::decode(&file("-Y 1 +X 8", &data)),
+            Err(HdrError::InvalidRunLength)
+        ));
+    }
+
+    #[test]
+    fn flips_bottom_up_images() {
+        let data = [1, 1, 1, 128, 2, 2, 2, 128];
+        let image = HdrImage::decode(&file("+Y 2 +X 1", &data)).unwrap();
+
+        assert_eq!(
+            image.rgb,
+            [rgbe_to_rgb([2, 2, 2, 128]), rgbe_to_rgb([1, 1, 1, 128])]
+        );
+    }
+
+    #[test]
+    fn rejects_resolutions_the_data_cannot_hold() {
+        assert!(matches!(
+            HdrImage::decode(&file("-Y 1 +X 4000000000", &[0; 16])),
+            Err(HdrError::Truncated)
+        ));
+        assert!(matches!(
+            HdrImage::decode(&file("-Y 4000000 +X 1000", &[0; 16])),
+            Err(HdrError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn converts_to_nearest_half_floats() {
+        assert_eq!(f16_bits(0.0), 0x0000);
+        assert_eq!(f16_bits(1.0), 0x3c00);
+        assert_eq!(f16_bits(0.5), 0x3800);
+        assert_eq!(f16_bits(-2.0), 0xc000);
+        assert_eq!(f16_bits(1.0 + 1.0 / 1024.0), 0x3c01);
+        // the smallest subnormal, and what rounds below it
+        assert_eq!(f16_bits(2f32.powi(-24)), 0x0001);
+        assert_eq!(f16_bits(2f32.powi(-30)), 0x0000);
+        // saturating instead of overflowing to infinity
+        assert_eq!(f16_bits(65504.0), 0x7bff);
+        assert_eq!(f16_bits(1e6), 0x7bff);
+        assert_eq!(f16_bits(f32::INFINITY), 0x7bff);
+        assert_eq!(f16_bits(f32::NAN), 0x7e00);
+    }
+}
@@ -0,0 +1,202 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::Context;
+
+use std::borrow::Cow;
+use std::mem;
+
+/// Curve compressing scene radiance into the displayable range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    Aces,
+    Reinhard,
+    /// Hable's curve from Uncharted 2.
+    Filmic,
+}
+
+impl ToneMapOperator {
+    pub fn next(self) -> Self {
+        match self {
+            ToneMapOperator::Aces => ToneMapOperator::Reinhard,
+            ToneMapOperator::Reinhard => ToneMapOperator::Filmic,
+            ToneMapOperator::Filmic => ToneMapOperator::Aces,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct ToneMappingUniforms {
+    operator: u32,
+    exposure: f32,
+    gamma_correction: f32,
+    _0: f32,
+}
+
+/// Maps the HDR scene target (`FrameTargets::hdr`) to the displayable range, as the last
+/// step of `PostProcess` before FXAA.
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    /// In stops: the scene is scaled by `2^exposure` before tone mapping.
+    pub exposure: f32,
+    /// Display gamma; 2.2 leaves the sRGB encoding of the surface as is.
+    pub gamma: f32,
+
+    /// Whether the surface encodes to sRGB itself, or the shader has to apply the gamma.
+    srgb_surface: bool,
+    uniform_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl ToneMapping {
+    pub fn new(ctx: &Context) -> Self {
+        let uniform_buffer = ctx
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::bytes_of(&ToneMappingUniforms::zeroed()),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
+        let bind_group_layout =
+            ctx.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("tone mapping"),
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: wgpu::BufferSize::new(mem::size_of::<
+                                    ToneMappingUniforms,
+                                >(
+                                )
+                                    as u64),
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                    ],
+                });
+
+        let shader_module = ctx
+            .device
+            .create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: Some("tone_mapping/tone_mapping.wgsl"),
+                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                    "tone_mapping/tone_mapping.wgsl"
+                ))),
+            });
+
+        let pipeline_layout = ctx
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let pipeline = ctx
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("tone mapping"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point: "fs_main",
+                    targets: &[ctx.surface_config.format.into()],
+                }),
+                multiview: None,
+            });
+
+        let tone_mapping = ToneMapping {
+            operator: ToneMapOperator::Aces,
+            exposure: 0.0,
+            gamma: 2.2,
+            srgb_surface: ctx.surface_config.format.describe().srgb,
+            uniform_buffer,
+            bind_group_layout,
+            pipeline,
+        };
+        tone_mapping.update(&ctx.queue);
+        tone_mapping
+    }
+
+    /// Uploads the operator, exposure and gamma.
+    pub fn update(&self, queue: &wgpu::Queue) {
+        let gamma_correction = if self.srgb_surface {
+            2.2 / self.gamma
+        } else {
+            1.0 / self.gamma
+        };
+        let uniforms = ToneMappingUniforms {
+            operator: self.operator as u32,
+            exposure: self.exposure.exp2(),
+            gamma_correction,
+            _0: 0.0,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    /// Tone maps `hdr` into `target`, a single-sampled texture of the surface format,
+    /// overwriting it.
+    pub fn render(
+        &self,
+        ctx: &Context,
+        encoder: &mut wgpu::CommandEncoder,
+        hdr: &wgpu::TextureView,
+        target: &wgpu::TextureView,
+    ) {
+        // the HDR target is reallocated on resize, so bind whichever is current
+        let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("tone mapping"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(hdr),
+                },
+            ],
+        });
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("tone mapping"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
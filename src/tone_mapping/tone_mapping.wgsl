@@ -0,0 +1,65 @@
+struct ToneMappingUniforms {
+    // 0: ACES, 1: Reinhard, 2: filmic
+    operator: u32;
+    // linear scale, 2^EV
+    exposure: f32;
+    // exponent applied after tone mapping, on top of the sRGB encoding of the surface
+    gamma_correction: f32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> u_tone_mapping: ToneMappingUniforms;
+[[group(0), binding(1)]]
+var t_hdr: texture_2d<f32>;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+};
+
+// one triangle covering the whole target
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    return out;
+}
+
+// Narkowicz's fit of the ACES filmic curve
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn reinhard(x: vec3<f32>) -> vec3<f32> {
+    return x / (vec3<f32>(1.0) + x);
+}
+
+// Hable's Uncharted 2 curve
+fn hable(x: vec3<f32>) -> vec3<f32> {
+    let a = 0.15;
+    let b = 0.50;
+    let c = 0.10;
+    let d = 0.20;
+    let e = 0.02;
+    let f = 0.30;
+    return (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f;
+}
+
+fn filmic(x: vec3<f32>) -> vec3<f32> {
+    let white_point = 11.2;
+    let exposure_bias = 2.0;
+    return hable(x * exposure_bias) / hable(vec3<f32>(white_point));
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let hdr = textureLoad(t_hdr, vec2<i32>(in.position.xy), 0).rgb;
+    let exposed = max(hdr * u_tone_mapping.exposure, vec3<f32>(0.0));
+    var mapped: vec3<f32>;
+    switch (i32(u_tone_mapping.operator)) {
+        case 0: { mapped = aces(exposed); }
+        case 1: { mapped = reinhard(exposed); }
+        default: { mapped = filmic(exposed); }
+    }
+    return vec4<f32>(pow(mapped, vec3<f32>(u_tone_mapping.gamma_correction)), 1.0);
+}
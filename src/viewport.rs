@@ -0,0 +1,463 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{Point2, Vector3};
+use wgpu::util::DeviceExt;
+use winit::dpi::PhysicalSize;
+use winit::window::CursorIcon;
+
+use crate::render_graph::{RenderGraph, ResourceId};
+use crate::shading::ShadingMode;
+use crate::shapes::Viewport;
+use crate::{Camera, Context, Projection, ScreenSpace};
+
+use std::borrow::Cow;
+use std::mem;
+
+/// Gap between the views, in pixels.
+const SPLITTER_WIDTH: u32 = 4;
+/// How far from the middle of a splitter it can still be grabbed, in pixels.
+const SPLITTER_GRAB_DISTANCE: f32 = 4.0;
+/// Bounds of the splitter positions, as fractions of the window size.
+const MIN_SPLIT: f32 = 0.1;
+const MAX_SPLIT: f32 = 0.9;
+const SPLITTER_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.02,
+    g: 0.02,
+    b: 0.02,
+    a: 1.0,
+};
+const FOCUS_COLOR: [f32; 4] = [1.0, 0.6, 0.1, 1.0];
+const FOCUS_BORDER_WIDTH: f32 = 1.0;
+/// Height shown by the orthographic views, in world units.
+const ORTHOGRAPHIC_HEIGHT: f32 = 6.0;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct CompositeUniforms {
+    border_color: [f32; 4],
+    rect: [f32; 4],
+    border_width: f32,
+    _0: [f32; 3],
+}
+
+/// One of the views of a `ViewportLayout`, looking at the scene through its own camera.
+pub struct View {
+    pub name: &'static str,
+    pub camera: Camera,
+    pub shading: ShadingMode,
+    rect: Viewport,
+}
+
+impl View {
+    fn new(name: &'static str, camera: Camera, shading: ShadingMode) -> Self {
+        View {
+            name,
+            camera,
+            shading,
+            rect: (0, 0, 0, 0),
+        }
+    }
+
+    /// Where the view is in the window.
+    pub fn rect(&self) -> Viewport {
+        self.rect
+    }
+
+    pub fn size(&self) -> PhysicalSize<u32> {
+        PhysicalSize::new(self.rect.2, self.rect.3)
+    }
+
+    pub fn contains(&self, cursor: Point2<f32>) -> bool {
+        let (x, y, width, height) = self.rect;
+        (x as f32..(x + width) as f32).contains(&cursor.x)
+            && (y as f32..(y + height) as f32).contains(&cursor.y)
+    }
+
+    /// `cursor`, given in window pixels, relative to the top-left corner of the view.
+    pub fn to_local(&self, cursor: Point2<f32>) -> Point2<f32> {
+        Point2::new(cursor.x - self.rect.0 as f32, cursor.y - self.rect.1 as f32)
+    }
+
+    pub fn screen_space(&self) -> ScreenSpace {
+        self.camera.screen_space(self.size())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.rect.2 == 0 || self.rect.3 == 0
+    }
+}
+
+/// Splitters moved by a drag; grabbing them where they cross moves both.
+#[derive(Debug, Clone, Copy)]
+struct SplitterDrag {
+    vertical: bool,
+    horizontal: bool,
+}
+
+/// The window split into four views by a vertical and a horizontal splitter: top, perspective,
+/// front and side, in reading order. Keyboard and mouse input goes to the focused view, which
+/// is focused by clicking into it, and can be maximized to fill the window.
+///
+/// Each view is rendered by a `RenderGraph` of its own size and composited into the window.
+pub struct ViewportLayout {
+    views: Vec<View>,
+    focused: usize,
+    maximized: bool,
+    // positions of the vertical and the horizontal splitter, as fractions of the window size
+    split: [f32; 2],
+    drag: Option<SplitterDrag>,
+    size: PhysicalSize<u32>,
+
+    uniform_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl ViewportLayout {
+    pub fn new(ctx: &Context) -> Self {
+        let orthographic = Projection::Orthographic {
+            height: ORTHOGRAPHIC_HEIGHT,
+        };
+        let views = vec![
+            View::new(
+                "top",
+                Camera::looking_along(Vector3::unit_y(), orthographic),
+                ShadingMode::Solid,
+            ),
+            View::new("perspective", Camera::default(), ShadingMode::Textured),
+            View::new(
+                "front",
+                Camera::looking_along(Vector3::unit_z(), orthographic),
+                ShadingMode::Solid,
+            ),
+            View::new(
+                "side",
+                Camera::looking_along(Vector3::unit_x(), orthographic),
+                ShadingMode::Solid,
+            ),
+        ];
+
+        let device = &ctx.device;
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("viewport composite"),
+            contents: bytemuck::bytes_of(&CompositeUniforms::zeroed()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("viewport composite"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            mem::size_of::<CompositeUniforms>() as u64,
+                        ),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("viewport/composite.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                "viewport/composite.wgsl"
+            ))),
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("viewport composite"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[ctx.surface_config.format.into()],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let mut layout = ViewportLayout {
+            views,
+            focused: 1,
+            maximized: false,
+            split: [0.5, 0.5],
+            drag: None,
+            size: ctx.size,
+            uniform_buffer,
+            bind_group_layout,
+            pipeline,
+        };
+        layout.layout();
+        layout
+    }
+
+    pub fn views(&self) -> &[View] {
+        &self.views
+    }
+
+    pub fn view(&self, index: usize) -> &View {
+        &self.views[index]
+    }
+
+    pub fn focused_index(&self) -> usize {
+        self.focused
+    }
+
+    pub fn focused(&self) -> &View {
+        &self.views[self.focused]
+    }
+
+    pub fn focused_mut(&mut self) -> &mut View {
+        &mut self.views[self.focused]
+    }
+
+    /// Focuses the view under `cursor`, returning whether the focus moved.
+    pub fn focus_at(&mut self, cursor: Point2<f32>) -> bool {
+        match self
+            .views
+            .iter()
+            .position(|view| !view.is_empty() && view.contains(cursor))
+        {
+            Some(index) if index != self.focused => {
+                self.focus(index);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn focus_next(&mut self) {
+        self.focus((self.focused + 1) % self.views.len());
+    }
+
+    fn focus(&mut self, index: usize) {
+        // the keys held down were pressed for the previous view, which would never see them
+        // released
+        self.views[self.focused].camera.stop();
+        self.focused = index;
+        if self.maximized {
+            self.layout();
+        }
+    }
+
+    /// Switches between the focused view filling the window and all four views.
+    pub fn toggle_maximized(&mut self) {
+        self.maximized = !self.maximized;
+        self.layout();
+    }
+
+    pub fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.size = size;
+        self.layout();
+    }
+
+    /// Grabs the splitters under `cursor`, returning whether there were any.
+    pub fn begin_splitter_drag(&mut self, cursor: Point2<f32>) -> bool {
+        self.drag = self.splitters_at(cursor);
+        self.drag.is_some()
+    }
+
+    /// Moves the grabbed splitters to `cursor`, returning whether any are grabbed.
+    pub fn drag_splitter(&mut self, cursor: Point2<f32>) -> bool {
+        let drag = match self.drag {
+            Some(drag) => drag,
+            None => return false,
+        };
+        if drag.vertical {
+            self.split[0] = (cursor.x / self.size.width as f32).clamp(MIN_SPLIT, MAX_SPLIT);
+        }
+        if drag.horizontal {
+            self.split[1] = (cursor.y / self.size.height as f32).clamp(MIN_SPLIT, MAX_SPLIT);
+        }
+        self.layout();
+        true
+    }
+
+    /// Releases the grabbed splitters, returning whether there were any.
+    pub fn end_splitter_drag(&mut self) -> bool {
+        self.drag.take().is_some()
+    }
+
+    /// The cursor to show at `cursor`, telling which way the splitters there can be dragged.
+    pub fn cursor_icon(&self, cursor: Point2<f32>) -> CursorIcon {
+        match self.drag.or_else(|| self.splitters_at(cursor)) {
+            Some(SplitterDrag {
+                vertical: true,
+                horizontal: true,
+            }) => CursorIcon::Move,
+            Some(SplitterDrag { vertical: true, .. }) => CursorIcon::ColResize,
+            Some(_) => CursorIcon::RowResize,
+            None => CursorIcon::Default,
+        }
+    }
+
+    /// Advances the cameras of all views, so the ones that lost focus finish their moves.
+    pub fn update_cameras(&mut self) {
+        for view in &mut self.views {
+            view.camera.update();
+        }
+    }
+
+    /// Indices of the views to render, the focused one last: what is derived from the camera
+    /// while rendering a view, like the orientation of the `AxisWidget`, is then the focused
+    /// view's when handling input.
+    pub fn render_order(&self) -> Vec<usize> {
+        (0..self.views.len())
+            .filter(|&index| index != self.focused)
+            .chain([self.focused])
+            .filter(|&index| !self.views[index].is_empty())
+            .collect()
+    }
+
+    /// Writes the uniforms of the composite pass of the view at `index`.
+    pub fn update(&self, queue: &wgpu::Queue, index: usize) {
+        let (x, y, width, height) = self.views[index].rect;
+        // a maximized view is the only one, there is nothing to tell it from
+        let focused = index == self.focused && !self.maximized;
+        let uniforms = CompositeUniforms {
+            border_color: FOCUS_COLOR,
+            rect: [x as f32, y as f32, width as f32, height as f32],
+            border_width: if focused { FOCUS_BORDER_WIDTH } else { 0.0 },
+            _0: [0.0; 3],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    /// Clears `window` to the color of the splitters, before the views are composited into it.
+    pub fn clear(&self, encoder: &mut wgpu::CommandEncoder, window: &wgpu::TextureView) {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("viewport splitters"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: window,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(SPLITTER_COLOR),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+    }
+
+    /// Adds the pass copying `frame`, the rendered view at `index`, into its rect of `window`.
+    pub fn add_composite_pass<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        ctx: &'a Context,
+        index: usize,
+        frame: ResourceId,
+        window: ResourceId,
+    ) {
+        let (x, y, width, height) = self.views[index].rect;
+        graph.add_pass(
+            "composite",
+            &[frame],
+            &[window],
+            move |encoder, resources| {
+                let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("viewport composite"),
+                    layout: &self.bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: self.uniform_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(resources.view(frame)),
+                        },
+                    ],
+                });
+                let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("viewport composite"),
+                    color_attachments: &[wgpu::RenderPassColorAttachment {
+                        view: resources.view(window),
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: true,
+                        },
+                    }],
+                    depth_stencil_attachment: None,
+                });
+                rpass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+                rpass.set_pipeline(&self.pipeline);
+                rpass.set_bind_group(0, &bind_group, &[]);
+                rpass.draw(0..3, 0..1);
+            },
+        );
+    }
+
+    /// Splitter positions in window pixels.
+    fn split_pixels(&self) -> [u32; 2] {
+        [
+            (self.split[0] * self.size.width as f32) as u32,
+            (self.split[1] * self.size.height as f32) as u32,
+        ]
+    }
+
+    fn splitters_at(&self, cursor: Point2<f32>) -> Option<SplitterDrag> {
+        if self.maximized {
+            return None;
+        }
+        let [x, y] = self.split_pixels();
+        let vertical = (cursor.x - x as f32).abs() <= SPLITTER_GRAB_DISTANCE;
+        let horizontal = (cursor.y - y as f32).abs() <= SPLITTER_GRAB_DISTANCE;
+        (vertical || horizontal).then_some(SplitterDrag {
+            vertical,
+            horizontal,
+        })
+    }
+
+    fn layout(&mut self) {
+        let PhysicalSize { width, height } = self.size;
+        if self.maximized {
+            for (index, view) in self.views.iter_mut().enumerate() {
+                view.rect = if index == self.focused {
+                    (0, 0, width, height)
+                } else {
+                    (0, 0, 0, 0)
+                };
+            }
+            return;
+        }
+
+        // start and length of the two columns and rows, around the splitters
+        let [split_x, split_y] = self.split_pixels();
+        let half = SPLITTER_WIDTH / 2;
+        let spans = |split: u32, length: u32| {
+            let second = (split + half).min(length);
+            [(0, split.saturating_sub(half)), (second, length - second)]
+        };
+        let columns = spans(split_x, width);
+        let rows = spans(split_y, height);
+        for (index, view) in self.views.iter_mut().enumerate() {
+            let (x, width) = columns[index % 2];
+            let (y, height) = rows[index / 2];
+            view.rect = (x, y, width, height);
+        }
+    }
+}
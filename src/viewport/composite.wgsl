@@ -0,0 +1,29 @@
+struct CompositeUniforms {
+    border_color: vec4<f32>;
+    // x, y, width and height of the view in window pixels
+    rect: vec4<f32>;
+    border_width: f32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> u_composite: CompositeUniforms;
+[[group(0), binding(1)]]
+var t_frame: texture_2d<f32>;
+
+// one triangle covering the viewport
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32) -> [[builtin(position)]] vec4<f32> {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_main([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    // the frame has the size of the view, texel for pixel
+    let pixel = position.xy - u_composite.rect.xy;
+    let edge = min(pixel, u_composite.rect.zw - pixel);
+    if (min(edge.x, edge.y) < u_composite.border_width) {
+        return u_composite.border_color;
+    }
+    return textureLoad(t_frame, vec2<i32>(pixel), 0);
+}